@@ -8,23 +8,155 @@ CREATE TABLE users (
     username VARCHAR(255) NOT NULL UNIQUE,
     email VARCHAR(255) NOT NULL UNIQUE,
     password_hash VARCHAR(255) NOT NULL,
-    role VARCHAR(225) DEFAULT 'customer', -- 'customer' or 'vendor'
+    role VARCHAR(225) DEFAULT 'customer', -- 'customer', 'vendor' or 'admin' (admins are promoted in the database)
+    email_verified_at TIMESTAMP WITH TIME ZONE,  -- NULL until the emailed token is confirmed
+    session_version INTEGER NOT NULL DEFAULT 0,  -- Bumped on password change/reset to invalidate issued tokens
+    display_name VARCHAR(100),
+    pending_email VARCHAR(255),  -- New address waiting for verification; email changes once it's confirmed
+    deleted_at TIMESTAMP WITH TIME ZONE,  -- Closed accounts are anonymized, not removed, to keep order history
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
+-- Only the SHA-256 hash of each emailed token is stored
+CREATE TABLE email_verification_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,  -- Address the token confirms
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Single-use password reset tokens, also stored hashed
+CREATE TABLE password_reset_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+
+-- Every password login attempt, for throttling and lockout. The email is
+-- tracked as entered (lowercased) so unknown accounts are throttled the same way.
+CREATE TABLE login_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    ip_address VARCHAR(64),
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- TWO-FACTOR AUTHENTICATION
+
+CREATE TABLE user_mfa (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    secret VARCHAR(64) NOT NULL,           -- Base32 TOTP secret
+    enabled_at TIMESTAMP WITH TIME ZONE,   -- NULL until the first code is confirmed
+    last_used_step BIGINT,                 -- Last accepted 30s time step, so a code can't be replayed
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE mfa_recovery_codes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Issued by login once the password checks out; exchanged for a session token with a code
+CREATE TABLE mfa_challenges (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR(20) NOT NULL CHECK (purpose IN ('verify', 'enroll')),
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Roles that must use two-factor authentication, managed by admins
+CREATE TABLE mfa_policies (
+    role VARCHAR(50) PRIMARY KEY,
+    required BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- VENDOR PROFILES (Store Details and Approval)
+
+-- A vendor's store application. Only approved vendors can list products;
+-- suspended vendors' products are hidden from the catalog.
+CREATE TABLE vendor_profiles (
+    vendor_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    store_name VARCHAR(100) NOT NULL,
+    slug VARCHAR(50) NOT NULL UNIQUE,  -- Used in storefront URLs
+    description TEXT,
+    logo_url VARCHAR(500),
+    contact_email VARCHAR(255),
+    contact_phone VARCHAR(50),
+    payout_method VARCHAR(20) CHECK (payout_method IN ('bank_transfer', 'paypal')),
+    payout_account VARCHAR(255),  -- IBAN or PayPal email; only the last 4 characters are ever returned
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'suspended')),
+    status_reason TEXT,  -- Admin's note for the vendor, e.g. why they were suspended
+    reviewed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- API KEYS (Vendor Machine-to-Machine Access)
+
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    prefix VARCHAR(20) NOT NULL,           -- Start of the key, shown so it can be recognised
+    key_hash VARCHAR(64) NOT NULL UNIQUE,  -- SHA-256 of the key; the key itself is only shown once
+    scopes TEXT[] NOT NULL,                -- 'products:read', 'products:write', 'orders:read', 'orders:write'
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
 
 -- PRODUCTS TABLE (Vendor Inventory)
 
 CREATE TABLE products (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    sku VARCHAR(64),                                      -- Vendor's stock keeping unit, used by bulk imports
     name VARCHAR(255) NOT NULL,
     description TEXT,
     price DECIMAL(10,2) NOT NULL CHECK (price >= 0),
     stock INTEGER NOT NULL DEFAULT 0 CHECK (stock >= 0),
     category VARCHAR(100),
+    weight_kg DECIMAL(10,3) CHECK (weight_kg >= 0),      -- Shipping weight
+    length_cm DECIMAL(10,2) CHECK (length_cm >= 0),
+    width_cm DECIMAL(10,2) CHECK (width_cm >= 0),
+    height_cm DECIMAL(10,2) CHECK (height_cm >= 0),
+    low_stock_threshold INTEGER CHECK (low_stock_threshold >= 0),  -- Vendor is emailed when stock drops to it
+    archived_at TIMESTAMP WITH TIME ZONE,                 -- Hidden from the catalog and can't be bought; kept for order history
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
-    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(vendor_id, sku)
+);
+
+-- SHIPPING PROFILES (Vendor Shipping Methods)
+
+CREATE TABLE shipping_profiles (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    method VARCHAR(30) NOT NULL,  -- 'flat_rate', 'weight_based', 'free_over_threshold'
+    base_rate DECIMAL(10,2) NOT NULL DEFAULT 0 CHECK (base_rate >= 0),
+    per_kg_rate DECIMAL(10,2) CHECK (per_kg_rate >= 0),          -- weight_based only
+    free_threshold DECIMAL(10,2) CHECK (free_threshold >= 0),    -- free_over_threshold only
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
 -- CART SYSTEM (Customer Shopping Cart)
@@ -43,8 +175,12 @@ CREATE TABLE cart_items (
 CREATE TABLE orders (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-    total DECIMAL(10, 2) NOT NULL CHECK (total >= 0),
-    status VARCHAR(20) NOT NULL DEFAULT 'pending',  -- 'pending', 'shipped', 'delivered'
+    total DECIMAL(10, 2) NOT NULL CHECK (total >= 0),  -- Items plus shipping
+    shipping_total DECIMAL(10, 2) NOT NULL DEFAULT 0 CHECK (shipping_total >= 0),
+    shipping_address TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',  -- 'pending', 'shipped', 'delivered', 'cancelled'
+    cancellation_reason TEXT,
+    cancelled_at TIMESTAMP WITH TIME ZONE,
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
@@ -57,9 +193,344 @@ CREATE TABLE order_items (
     vendor_id UUID NOT NULL REFERENCES users(id),  -- For vendor-specific queries
     quantity INTEGER NOT NULL CHECK (quantity > 0),
     price DECIMAL(10, 2) NOT NULL CHECK (price >= 0), -- Price snapshot at purchase time
+    cancelled_quantity INTEGER NOT NULL DEFAULT 0 CHECK (cancelled_quantity >= 0 AND cancelled_quantity <= quantity),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- ORDER CANCELLATIONS (Who Cancelled What and Why)
+
+CREATE TABLE order_cancellations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    order_item_id UUID REFERENCES order_items(id) ON DELETE CASCADE,  -- NULL when the whole order was cancelled
+    quantity INTEGER CHECK (quantity > 0),
+    amount DECIMAL(10, 2) NOT NULL CHECK (amount >= 0),  -- Amount refunded
+    reason TEXT,
+    cancelled_by UUID NOT NULL REFERENCES users(id),
     created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
 );
 
+-- PAYMENTS (Charges and Refunds from the Payment Layer)
+
+CREATE TABLE payments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL,  -- 'charge' or 'refund'
+    amount DECIMAL(10, 2) NOT NULL CHECK (amount >= 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'succeeded',
+    reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- ORDER SHIPPING (Shipping Method Chosen Per Vendor Group)
+
+CREATE TABLE order_shipping (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    vendor_id UUID NOT NULL REFERENCES users(id),
+    shipping_profile_id UUID REFERENCES shipping_profiles(id) ON DELETE SET NULL,
+    method_name VARCHAR(100) NOT NULL,  -- Snapshot of the profile name at checkout
+    cost DECIMAL(10, 2) NOT NULL CHECK (cost >= 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(order_id, vendor_id)
+);
+
+-- INVOICES AND CREDIT NOTES (Accounting Documents Per Vendor)
+
+CREATE TABLE invoices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    vendor_id UUID NOT NULL REFERENCES users(id),
+    kind VARCHAR(20) NOT NULL,  -- 'invoice' or 'credit_note'
+    number INTEGER NOT NULL,    -- Sequential per vendor and kind
+    document_number VARCHAR(50) NOT NULL UNIQUE,
+    invoice_id UUID REFERENCES invoices(id),  -- Credit notes: the invoice being credited
+    seller_name VARCHAR(255) NOT NULL,
+    seller_email VARCHAR(255) NOT NULL,
+    buyer_name VARCHAR(255) NOT NULL,
+    buyer_email VARCHAR(255) NOT NULL,
+    buyer_address TEXT,
+    subtotal DECIMAL(10, 2) NOT NULL,  -- Net of tax
+    tax_rate DECIMAL(6, 4) NOT NULL DEFAULT 0,
+    tax_amount DECIMAL(10, 2) NOT NULL DEFAULT 0,
+    total DECIMAL(10, 2) NOT NULL,     -- Tax inclusive, matches what was charged or refunded
+    reason TEXT,
+    issued_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(vendor_id, kind, number)
+);
+
+CREATE TABLE invoice_lines (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    line_number INTEGER NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    quantity INTEGER NOT NULL,
+    unit_price DECIMAL(10, 2) NOT NULL,
+    total DECIMAL(10, 2) NOT NULL
+);
+
+CREATE TABLE invoice_counters (
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL,
+    last_number INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (vendor_id, kind)
+);
+
+-- RETURNS (Customer Return Requests / RMA)
+
+CREATE TABLE returns (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id),    -- Customer who opened the return
+    vendor_id UUID NOT NULL REFERENCES users(id),  -- Vendor who reviews it
+    status VARCHAR(20) NOT NULL DEFAULT 'requested',  -- 'requested', 'approved', 'rejected', 'shipped', 'received'
+    reason TEXT NOT NULL,
+    vendor_note TEXT,
+    tracking_number VARCHAR(100),
+    approved_amount DECIMAL(10, 2) CHECK (approved_amount >= 0),
+    refunded_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE return_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    return_id UUID NOT NULL REFERENCES returns(id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    UNIQUE(return_id, order_item_id)
+);
+
+-- VENDOR RATINGS (Customer Feedback Shown on Storefronts)
+
+-- One rating per vendor per delivered order
+CREATE TABLE vendor_ratings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    comment TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(order_id, vendor_id)
+);
+
+-- OUTBOX (Domain Events Written With the Change That Caused Them)
+
+CREATE TABLE outbox (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_type VARCHAR(50) NOT NULL,  -- 'UserRegistered', 'OrderPlaced', 'OrderStatusChanged', 'ProductStockChanged'
+    aggregate_id UUID NOT NULL,       -- User, order or product the event is about
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMP WITH TIME ZONE,  -- Delivered to every subscriber
+    failed_at TIMESTAMP WITH TIME ZONE,     -- Gave up after too many attempts
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Which subscribers already handled an event, so retries never deliver twice
+CREATE TABLE outbox_deliveries (
+    event_id UUID NOT NULL REFERENCES outbox(id) ON DELETE CASCADE,
+    subscriber VARCHAR(100) NOT NULL,
+    delivered_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (event_id, subscriber)
+);
+
+-- WEBHOOKS (Vendor Endpoints Notified of Domain Events)
+
+CREATE TABLE webhook_endpoints (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret VARCHAR(100) NOT NULL,  -- Signs every payload with HMAC-SHA256
+    event_types TEXT[] NOT NULL,   -- 'OrderPlaced', 'OrderStatusChanged', 'ProductStockChanged'
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    disabled_at TIMESTAMP WITH TIME ZONE,  -- Set when turned off after repeated failures
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    endpoint_id UUID NOT NULL REFERENCES webhook_endpoints(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL REFERENCES outbox(id) ON DELETE CASCADE,
+    event_type VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,  -- Exactly what is POSTed
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'succeeded', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    response_status INTEGER,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(endpoint_id, event_id)
+);
+
+-- BACKGROUND JOBS (Durable Queue Polled With SKIP LOCKED)
+
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind VARCHAR(100) NOT NULL,  -- Selects the handler, e.g. 'email.send', 'cart.expire'
+    payload JSONB NOT NULL DEFAULT '{}',
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'running', 'succeeded', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    unique_key TEXT UNIQUE,  -- Optional; enqueueing the same key twice is a no-op
+    run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    locked_at TIMESTAMP WITH TIME ZONE,
+    last_error TEXT,
+    finished_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Recurring jobs; each due schedule enqueues one job and moves to its next run
+CREATE TABLE job_schedules (
+    name VARCHAR(100) PRIMARY KEY,
+    kind VARCHAR(100) NOT NULL,
+    cron VARCHAR(100) NOT NULL,  -- sec min hour day-of-month month day-of-week
+    payload JSONB NOT NULL DEFAULT '{}',
+    next_run_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    last_run_at TIMESTAMP WITH TIME ZONE
+);
+
+-- INVENTORY MOVEMENTS (Stock History)
+
+-- Every change to a product's stock, so a product's stock always equals the sum
+-- of its movements. Rows are never updated or deleted.
+CREATE TABLE inventory_movements (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    change INTEGER NOT NULL CHECK (change <> 0),  -- Positive when stock was added
+    stock_after INTEGER NOT NULL,
+    reason VARCHAR(20) NOT NULL CHECK (reason IN ('initial', 'sale', 'cancellation', 'return', 'adjustment', 'import', 'correction')),
+    order_id UUID REFERENCES orders(id) ON DELETE SET NULL,
+    return_id UUID REFERENCES returns(id) ON DELETE SET NULL,
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,  -- Who made a manual change
+    note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- "Notify me when available" requests for out-of-stock products. A customer is
+-- emailed once when stock comes back; subscribing again re-arms the request.
+CREATE TABLE stock_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    notified_at TIMESTAMP WITH TIME ZONE,
+    UNIQUE(product_id, user_id)
+);
+
+-- CHECKOUT SESSIONS (Stock Held While the Customer Pays)
+
+-- A customer's checkout. While a session is active and not past expires_at, its
+-- holds are subtracted from the stock other customers can buy.
+CREATE TABLE checkout_sessions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'completed', 'released', 'expired', 'failed')),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    order_id UUID REFERENCES orders(id) ON DELETE SET NULL,  -- Set when completed
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    ended_at TIMESTAMP WITH TIME ZONE
+);
+
+-- At most one checkout in progress per customer
+CREATE UNIQUE INDEX idx_checkout_sessions_active_user ON checkout_sessions(user_id) WHERE status = 'active';
+
+-- Quantities reserved by a checkout, copied from the cart when it started
+CREATE TABLE stock_holds (
+    session_id UUID NOT NULL REFERENCES checkout_sessions(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (session_id, product_id)
+);
+
+-- PRODUCT IMPORTS (Bulk Catalog Uploads)
+
+-- A vendor's CSV or JSON upload, matched to products by SKU. Small files are
+-- processed during the request; larger ones are kept in payload until the
+-- import job processes them. report holds the outcome of every row.
+CREATE TABLE product_imports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    format VARCHAR(10) NOT NULL CHECK (format IN ('csv', 'json')),
+    dry_run BOOLEAN NOT NULL DEFAULT FALSE,  -- Validate and report without saving
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'completed')),
+    payload TEXT,                            -- Cleared once processed
+    total_rows INTEGER NOT NULL,
+    created_count INTEGER NOT NULL DEFAULT 0,
+    updated_count INTEGER NOT NULL DEFAULT 0,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    report JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+-- COMMISSION AND VENDOR LEDGER (Platform Cut, Money Owed to Vendors, Payouts)
+
+-- The platform's cut of each sale: one global rate plus overrides per vendor or
+-- per product category. The most specific rate applies (vendor, then category,
+-- then global); with none set the platform takes nothing.
+CREATE TABLE commission_rates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    category VARCHAR(100),  -- Lowercased
+    rate DECIMAL(5, 4) NOT NULL CHECK (rate >= 0 AND rate <= 1),
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    CHECK (vendor_id IS NULL OR category IS NULL),
+    UNIQUE NULLS NOT DISTINCT (vendor_id, category)
+);
+
+-- A vendor's balance paid out by a settlement run; simulated like payments
+CREATE TABLE payouts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    vendor_id UUID NOT NULL REFERENCES users(id),
+    reference VARCHAR(50) NOT NULL UNIQUE,
+    amount DECIMAL(12, 2) NOT NULL CHECK (amount > 0),
+    payout_method VARCHAR(20) NOT NULL,
+    payout_account_last4 VARCHAR(4),
+    settlement_date DATE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    UNIQUE(vendor_id, settlement_date)  -- A retried run never pays twice
+);
+
+-- Double-entry ledger. Every money movement is one transaction whose entries sum
+-- to zero; debits are positive and credits negative. Accounts: platform_cash
+-- (money the platform holds), vendor_payable (owed to the vendor) and
+-- commission_revenue (the platform's cut). Entries are never updated or deleted.
+CREATE TABLE ledger_entries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    transaction_id UUID NOT NULL,
+    account VARCHAR(30) NOT NULL CHECK (account IN ('platform_cash', 'vendor_payable', 'commission_revenue')),
+    vendor_id UUID NOT NULL REFERENCES users(id),  -- Vendor the transaction concerns
+    amount DECIMAL(12, 2) NOT NULL CHECK (amount <> 0),
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('sale', 'refund', 'payout')),
+    order_id UUID REFERENCES orders(id),
+    invoice_id UUID REFERENCES invoices(id),  -- The invoice of a sale or credit note of a refund
+    payout_id UUID REFERENCES payouts(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Checked at commit, once every entry of the transaction is in
+CREATE FUNCTION check_ledger_transaction_balanced() RETURNS trigger AS $$
+BEGIN
+    IF (SELECT SUM(amount) FROM ledger_entries WHERE transaction_id = NEW.transaction_id) <> 0 THEN
+        RAISE EXCEPTION 'Ledger transaction % does not balance', NEW.transaction_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER ledger_transaction_balanced
+    AFTER INSERT ON ledger_entries
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION check_ledger_transaction_balanced();
+
 -- PERFORMANCE INDEXES
 
 -- Product indexes (for searching and vendor queries)
@@ -67,6 +538,7 @@ CREATE INDEX idx_products_vendor_id ON products(vendor_id);
 CREATE INDEX idx_products_category ON products(category);
 CREATE INDEX idx_products_price ON products(price);
 CREATE INDEX idx_products_name ON products(name);
+CREATE INDEX idx_products_archived ON products(vendor_id, archived_at) WHERE archived_at IS NOT NULL;
 
 -- Cart indexes (for fast cart operations)
 CREATE INDEX idx_cart_items_user_id ON cart_items(user_id);
@@ -79,5 +551,75 @@ CREATE INDEX idx_orders_created_at ON orders(created_at);
 
 -- Order items indexes (for vendor-specific order filtering)
 CREATE INDEX idx_order_items_order_id ON order_items(order_id);
-CREATE INDEX idx_order_items_vendor_id ON order_items(vendor_id);
-CREATE INDEX idx_order_items_product_id ON order_items(product_id);
\ No newline at end of file
+-- Vendor analytics read ranges of a vendor's items by creation time; INCLUDE
+-- makes them index-only scans
+CREATE INDEX idx_order_items_vendor_created_at ON order_items(vendor_id, created_at)
+    INCLUDE (order_id, product_id, quantity, cancelled_quantity, price);
+CREATE INDEX idx_order_items_product_id ON order_items(product_id);
+
+-- Shipping indexes
+CREATE INDEX idx_shipping_profiles_vendor_id ON shipping_profiles(vendor_id);
+CREATE INDEX idx_order_shipping_order_id ON order_shipping(order_id);
+
+-- Cancellation and payment indexes
+CREATE INDEX idx_order_cancellations_order_id ON order_cancellations(order_id);
+CREATE INDEX idx_payments_order_id ON payments(order_id);
+
+-- Return indexes
+CREATE INDEX idx_returns_order_id ON returns(order_id);
+CREATE INDEX idx_returns_user_id ON returns(user_id);
+CREATE INDEX idx_returns_vendor_id ON returns(vendor_id);
+CREATE INDEX idx_return_items_order_item_id ON return_items(order_item_id);
+
+-- Invoice indexes
+CREATE INDEX idx_invoices_order_id ON invoices(order_id);
+CREATE INDEX idx_invoice_lines_invoice_id ON invoice_lines(invoice_id);
+
+-- Outbox indexes
+CREATE INDEX idx_outbox_pending ON outbox(next_attempt_at) WHERE processed_at IS NULL AND failed_at IS NULL;
+CREATE INDEX idx_outbox_aggregate_id ON outbox(aggregate_id);
+
+-- Webhook indexes
+CREATE INDEX idx_webhook_endpoints_vendor_id ON webhook_endpoints(vendor_id);
+CREATE INDEX idx_webhook_deliveries_endpoint_id ON webhook_deliveries(endpoint_id, created_at);
+CREATE INDEX idx_webhook_deliveries_pending ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+
+-- Job indexes
+CREATE INDEX idx_jobs_pending ON jobs(run_at) WHERE status = 'pending';
+CREATE INDEX idx_jobs_running ON jobs(locked_at) WHERE status = 'running';
+CREATE INDEX idx_jobs_dead ON jobs(kind, created_at) WHERE status = 'dead';
+
+-- Email verification and password reset indexes
+CREATE INDEX idx_email_verification_tokens_user_id ON email_verification_tokens(user_id, created_at);
+CREATE INDEX idx_password_reset_tokens_user_id ON password_reset_tokens(user_id, created_at);
+
+-- Two-factor authentication indexes
+CREATE INDEX idx_mfa_recovery_codes_user_id ON mfa_recovery_codes(user_id);
+CREATE INDEX idx_mfa_challenges_user_id ON mfa_challenges(user_id);
+
+-- Login attempt indexes
+CREATE INDEX idx_login_attempts_email_created_at ON login_attempts(email, created_at);
+CREATE INDEX idx_login_attempts_ip_created_at ON login_attempts(ip_address, created_at);
+
+-- API key indexes
+CREATE INDEX idx_api_keys_vendor_id ON api_keys(vendor_id);
+
+-- Vendor profile indexes
+CREATE INDEX idx_vendor_profiles_status ON vendor_profiles(status, created_at);
+CREATE INDEX idx_vendor_ratings_vendor_id ON vendor_ratings(vendor_id);
+
+-- Ledger indexes
+CREATE INDEX idx_ledger_entries_vendor_id ON ledger_entries(vendor_id, account, created_at);
+CREATE INDEX idx_ledger_entries_transaction_id ON ledger_entries(transaction_id);
+CREATE INDEX idx_ledger_entries_order_id ON ledger_entries(order_id, vendor_id);
+CREATE INDEX idx_payouts_vendor_id ON payouts(vendor_id, created_at);
+
+-- Inventory indexes
+CREATE INDEX idx_inventory_movements_product_id ON inventory_movements(product_id, created_at DESC);
+CREATE INDEX idx_stock_subscriptions_pending ON stock_subscriptions(product_id) WHERE notified_at IS NULL;
+CREATE INDEX idx_stock_subscriptions_user_id ON stock_subscriptions(user_id, created_at DESC);
+CREATE INDEX idx_stock_holds_product_id ON stock_holds(product_id);
+CREATE INDEX idx_checkout_sessions_expiry ON checkout_sessions(expires_at) WHERE status = 'active';
+
+-- Product import indexes
+CREATE INDEX idx_product_imports_vendor_id ON product_imports(vendor_id, created_at DESC);
@@ -1,7 +1,12 @@
 use sqlx::PgPool;
 use std::sync::Arc;
 
+use crate::jwt::KeyRing;
+use crate::notifier::Notifier;
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<PgPool>,
+    pub notifier: Arc<dyn Notifier>,
+    pub keys: Arc<KeyRing>,
 }
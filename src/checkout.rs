@@ -0,0 +1,66 @@
+//! Checkout sessions hold the cart's quantities for a customer while they pay.
+//! A hold counts against the stock other customers can buy until the session
+//! completes (the order's stock decrement takes over), is released, fails or
+//! passes `expires_at`. Expiry needs no cleanup to take effect: every query
+//! ignores sessions past their expiry, and a job only tidies up their status.
+
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::env;
+use uuid::Uuid;
+
+/// How long a checkout holds stock, from `CHECKOUT_HOLD_MINUTES` (default 15)
+pub fn hold_minutes() -> i32 {
+    env::var("CHECKOUT_HOLD_MINUTES").ok().and_then(|v| v.parse().ok()).unwrap_or(15)
+}
+
+/// Units of product `p` held by active checkouts, as a scalar subquery, leaving
+/// out the checkout of the user bound at `user_param` ("NULL" counts all)
+pub fn held_stock(user_param: &str) -> String {
+    format!(
+        "(SELECT COALESCE(SUM(h.quantity), 0)::INTEGER FROM stock_holds h \
+         JOIN checkout_sessions cs ON cs.id = h.session_id \
+         WHERE h.product_id = p.id AND cs.status = 'active' AND cs.expires_at > NOW() \
+         AND cs.user_id IS DISTINCT FROM {})",
+        user_param
+    )
+}
+
+/// End the user's checkout in progress, if any, with `status` ("released" or
+/// "failed"), giving its stock back. Returns whether there was one.
+pub async fn release<'e>(executor: impl PgExecutor<'e>, user_id: Uuid, status: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE checkout_sessions SET status = $2, ended_at = NOW() WHERE user_id = $1 AND status = 'active'",
+    )
+    .bind(user_id)
+    .bind(status)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark the user's checkout as turned into `order_id`; its holds stop counting
+/// in the same transaction that decrements the stock
+pub async fn complete(conn: &mut PgConnection, user_id: Uuid, order_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE checkout_sessions
+        SET status = 'completed', order_id = $2, ended_at = NOW()
+        WHERE user_id = $1 AND status = 'active'
+        "#,
+    )
+    .bind(user_id)
+    .bind(order_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Mark checkouts past their expiry as expired. Returns how many there were.
+pub async fn expire(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE checkout_sessions SET status = 'expired', ended_at = expires_at WHERE status = 'active' AND expires_at <= NOW()",
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
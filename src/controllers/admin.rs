@@ -0,0 +1,366 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth_guard::AuthUser, vendors::VENDOR_PROFILE_COLUMNS},
+    email_templates, jobs,
+    models::Mfa::{MfaPolicy, UpdateMfaPolicy, MFA_POLICY_ROLES},
+    models::Ledger::{CommissionRate, RunPayouts, UpsertCommissionRate},
+    models::Vendor::{UpdateVendorStatus, VendorApplication, VendorQuery, VENDOR_STATUSES},
+    notifier::Recipient,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_admin(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "admin" {
+        return Err(error(StatusCode::FORBIDDEN, "Only admins can access this resource"));
+    }
+    Ok(())
+}
+
+/// The MFA policy of every role, including ones never set
+pub async fn get_mfa_policies(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<Json<Vec<MfaPolicy>>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&auth_user.role)?;
+
+    let policies = sqlx::query_as::<_, MfaPolicy>(
+        r#"
+        SELECT r.role, COALESCE(p.required, FALSE) AS required, p.updated_by, p.updated_at
+        FROM UNNEST($1::VARCHAR[]) AS r(role)
+        LEFT JOIN mfa_policies p ON p.role = r.role
+        "#,
+    )
+    .bind(&MFA_POLICY_ROLES[..])
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch MFA policies", e))?;
+
+    Ok(Json(policies))
+}
+
+/// Require (or stop requiring) two-factor authentication for a role.
+/// Requiring it signs out members who haven't enrolled, so their next login
+/// takes them through enrollment.
+pub async fn update_mfa_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(target_role): Path<String>,
+    Json(payload): Json<UpdateMfaPolicy>,
+) -> Result<Json<MfaPolicy>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&role)?;
+    if !MFA_POLICY_ROLES.contains(&target_role.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid role. Valid roles are: {}", MFA_POLICY_ROLES.join(", ")),
+        ));
+    }
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let policy = sqlx::query_as::<_, MfaPolicy>(
+        r#"
+        INSERT INTO mfa_policies (role, required, updated_by, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (role) DO UPDATE
+        SET required = EXCLUDED.required, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+        RETURNING role, required, updated_by, updated_at
+        "#,
+    )
+    .bind(&target_role)
+    .bind(payload.required)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to update MFA policy", e))?;
+
+    if payload.required {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET session_version = session_version + 1
+            WHERE role = $1
+              AND NOT EXISTS (SELECT 1 FROM user_mfa m WHERE m.user_id = users.id AND m.enabled_at IS NOT NULL)
+            "#,
+        )
+        .bind(&target_role)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to update MFA policy", e))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(policy))
+}
+
+/// Vendor store profiles, oldest first, optionally filtered by status
+pub async fn get_vendors(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Query(params): Query<VendorQuery>,
+) -> Result<Json<Vec<VendorApplication>>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&auth_user.role)?;
+    if let Some(status) = &params.status {
+        if !VENDOR_STATUSES.contains(&status.as_str()) {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid status. Valid statuses are: {}", VENDOR_STATUSES.join(", ")),
+            ));
+        }
+    }
+
+    let vendors = sqlx::query_as::<_, VendorApplication>(&format!(
+        r#"
+        SELECT {}, u.id, u.username, u.display_name, u.email, p.reviewed_by
+        FROM vendor_profiles p
+        JOIN users u ON u.id = p.vendor_id
+        WHERE ($1::VARCHAR IS NULL OR p.status = $1)
+        ORDER BY p.created_at
+        "#,
+        VENDOR_PROFILE_COLUMNS
+    ))
+    .bind(&params.status)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch vendors", e))?;
+
+    Ok(Json(vendors))
+}
+
+/// Approve or suspend a vendor's store, emailing the vendor about it.
+/// Suspending hides the vendor's products and stops new listings.
+pub async fn update_vendor_status(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(vendor_id): Path<Uuid>,
+    Json(payload): Json<UpdateVendorStatus>,
+) -> Result<Json<VendorApplication>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&role)?;
+    if !VENDOR_STATUSES.contains(&payload.status.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid status. Valid statuses are: {}", VENDOR_STATUSES.join(", ")),
+        ));
+    }
+    let reason = payload.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let vendor = sqlx::query_as::<_, VendorApplication>(&format!(
+        r#"
+        WITH updated AS (
+            UPDATE vendor_profiles
+            SET status = $2, status_reason = $3, reviewed_by = $4, reviewed_at = NOW(), updated_at = NOW()
+            WHERE vendor_id = $1
+            RETURNING *
+        )
+        SELECT {}, u.id, u.username, u.display_name, u.email, p.reviewed_by
+        FROM updated p
+        JOIN users u ON u.id = p.vendor_id
+        "#,
+        VENDOR_PROFILE_COLUMNS
+    ))
+    .bind(vendor_id)
+    .bind(&payload.status)
+    .bind(reason)
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to update vendor", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Vendor profile not found"))?;
+
+    let message = email_templates::vendor_status_changed(
+        &Recipient {
+            username: vendor.vendor.username.clone(),
+            email: vendor.email.clone(),
+        },
+        &vendor.profile.store_name,
+        &vendor.profile.status,
+        reason,
+    );
+    let message = serde_json::to_value(message).expect("EmailMessage serializes");
+    jobs::enqueue(&mut *tx, "email.send", message, None).await
+        .map_err(|e| internal_error("Failed to update vendor", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    println!("Vendor {} is now {} (by {})", vendor_id, vendor.profile.status, user_id);
+    Ok(Json(vendor))
+}
+
+/// Every commission rate: the global one first, then categories, then vendors
+pub async fn get_commission_rates(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<Json<Vec<CommissionRate>>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&auth_user.role)?;
+
+    let rates = sqlx::query_as::<_, CommissionRate>(
+        r#"
+        SELECT id, vendor_id, category, rate, updated_by, updated_at
+        FROM commission_rates
+        ORDER BY vendor_id NULLS FIRST, category NULLS FIRST
+        "#,
+    )
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch commission rates", e))?;
+
+    Ok(Json(rates))
+}
+
+/// Set the global commission rate, or a vendor's or category's override.
+/// New rates apply to orders placed from now on; refunds of earlier orders
+/// hand back commission at the rate those orders were charged.
+pub async fn upsert_commission_rate(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Json(payload): Json<UpsertCommissionRate>,
+) -> Result<Json<CommissionRate>, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&role)?;
+    if payload.rate < BigDecimal::from(0) || payload.rate > BigDecimal::from(1) {
+        return Err(error(StatusCode::BAD_REQUEST, "rate must be between 0 and 1"));
+    }
+    let category = payload
+        .category
+        .as_deref()
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty());
+    if payload.vendor_id.is_some() && category.is_some() {
+        return Err(error(StatusCode::BAD_REQUEST, "Set a rate for a vendor or for a category, not both"));
+    }
+
+    if let Some(vendor_id) = payload.vendor_id {
+        let is_vendor: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND role = 'vendor' AND deleted_at IS NULL)",
+        )
+        .bind(vendor_id)
+        .fetch_one(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to update commission rate", e))?;
+        if !is_vendor {
+            return Err(error(StatusCode::NOT_FOUND, "Vendor not found"));
+        }
+    }
+
+    let rate = sqlx::query_as::<_, CommissionRate>(
+        r#"
+        INSERT INTO commission_rates (vendor_id, category, rate, updated_by, updated_at)
+        VALUES ($1, $2, ROUND($3, 4), $4, NOW())
+        ON CONFLICT (vendor_id, category) DO UPDATE
+        SET rate = EXCLUDED.rate, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+        RETURNING id, vendor_id, category, rate, updated_by, updated_at
+        "#,
+    )
+    .bind(payload.vendor_id)
+    .bind(&category)
+    .bind(&payload.rate)
+    .bind(user_id)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to update commission rate", e))?;
+
+    println!("Commission rate {} set to {} (by {})", rate.id, rate.rate, user_id);
+    Ok(Json(rate))
+}
+
+/// Remove a commission rate; a removed override falls back to the next one
+pub async fn delete_commission_rate(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(rate_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&auth_user.role)?;
+
+    let result = sqlx::query("DELETE FROM commission_rates WHERE id = $1")
+        .bind(rate_id)
+        .execute(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to delete commission rate", e))?;
+    if result.rows_affected() == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "Commission rate not found"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Queue a payout run now rather than waiting for the weekly one. Vendors
+/// already paid for the settlement date are skipped.
+pub async fn run_payouts(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    payload: Option<Json<RunPayouts>>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    require_admin(&auth_user.role)?;
+    let settlement_date = payload
+        .and_then(|Json(payload)| payload.settlement_date)
+        .unwrap_or_else(|| Utc::now().date_naive());
+
+    jobs::enqueue(
+        &*state.db,
+        "ledger.payouts",
+        serde_json::json!({ "date": settlement_date.to_string() }),
+        None,
+    )
+    .await
+    .map_err(|e| internal_error("Failed to queue payouts", e))?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{
+        app_state, assert_no_hash_material, insert_user, insert_vendor_profile, password_hash, register_alice, response_body,
+    };
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn vendor_listing_has_no_hash_material(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = register_alice(state.clone(), "vendor").await;
+        insert_vendor_profile(&pool, vendor_id, "alices").await;
+        let admin_id = insert_user(&pool, "admin", "admin").await;
+
+        let admin = AuthUser { user_id: admin_id, role: "admin".into() };
+        let listing = get_vendors(State(state), admin, Query(VendorQuery { status: None })).await;
+        let listing = response_body(listing).await;
+
+        assert!(listing.contains(r#""username":"alice""#), "unexpected vendor listing: {}", listing);
+        assert_no_hash_material(&listing, &password_hash(&pool, "alice@example.com").await);
+    }
+}
@@ -0,0 +1,313 @@
+//! Vendor sales analytics, computed from order_items. Every query filters on
+//! `(vendor_id, created_at)`, which a covering index serves without touching
+//! the table, so the cost grows with the vendor's sales in the range rather
+//! than with all orders. Date ranges are resolved in the requested timezone.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    models::Analytics::{AnalyticsQuery, SalesBucket, SalesSummary, TopProduct, ANALYTICS_INTERVALS},
+};
+
+/// Default range, in days, ending today
+const DEFAULT_RANGE_DAYS: i64 = 30;
+/// Longest range one request can cover
+const MAX_RANGE_DAYS: i64 = 731;
+const DEFAULT_TOP_PRODUCTS: i64 = 10;
+const MAX_TOP_PRODUCTS: i64 = 100;
+
+/// The vendor's non-cancelled order items in the range: binds $1 vendor,
+/// $2 first day, $3 last day and $4 timezone
+const SALES_IN_RANGE: &str = r#"
+    SELECT oi.id, oi.order_id, oi.product_id, oi.created_at,
+           oi.quantity - oi.cancelled_quantity AS units,
+           (oi.quantity - oi.cancelled_quantity) * oi.price AS revenue
+    FROM order_items oi
+    WHERE oi.vendor_id = $1
+      AND oi.created_at >= $2::date::timestamp AT TIME ZONE $4::text
+      AND oi.created_at < ($3::date + 1)::timestamp AT TIME ZONE $4::text
+      AND oi.quantity > oi.cancelled_quantity
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors can view sales analytics"));
+    }
+    Ok(())
+}
+
+struct Range {
+    from: NaiveDate,
+    to: NaiveDate,
+    tz: String,
+}
+
+/// Validate the timezone and fill in the default range
+async fn resolve_range(db: &PgPool, params: &AnalyticsQuery) -> Result<Range, (StatusCode, Json<ErrorResponse>)> {
+    let tz = params.tz.clone().unwrap_or_else(|| "UTC".into());
+    // Today in the timezone, or None when Postgres doesn't know it
+    let today: Option<NaiveDate> = sqlx::query_scalar(
+        r#"
+        SELECT (NOW() AT TIME ZONE $1)::date
+        WHERE EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)
+        "#,
+    )
+    .bind(&tz)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch analytics", e))?;
+    let today = today.ok_or_else(|| {
+        error(StatusCode::BAD_REQUEST, "Unknown timezone. Use an IANA name such as Europe/Berlin")
+    })?;
+
+    let to = params.to.unwrap_or(today);
+    let from = params.from.unwrap_or(to - Duration::days(DEFAULT_RANGE_DAYS - 1));
+    if from > to {
+        return Err(error(StatusCode::BAD_REQUEST, "from must not be after to"));
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("The range can cover at most {} days", MAX_RANGE_DAYS),
+        ));
+    }
+    Ok(Range { from, to, tz })
+}
+
+/// Revenue, units, orders, average order value and returns rate for the range
+pub async fn get_sales_summary(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<SalesSummary>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    let range = resolve_range(&state.db, &params).await?;
+
+    let summary = sqlx::query_as::<_, SalesSummary>(&format!(
+        r#"
+        WITH sales AS ({}),
+        totals AS (
+            SELECT COUNT(DISTINCT order_id) AS orders,
+                   COALESCE(SUM(units), 0)::BIGINT AS units_sold,
+                   COALESCE(SUM(revenue), 0) AS revenue
+            FROM sales
+        ),
+        returned AS (
+            SELECT COALESCE(SUM(ri.quantity), 0)::BIGINT AS returned_units
+            FROM return_items ri
+            JOIN returns r ON r.id = ri.return_id
+            WHERE ri.order_item_id IN (SELECT id FROM sales) AND r.status <> 'rejected'
+        )
+        SELECT $2::date AS "from", $3::date AS "to", $4::text AS timezone,
+               t.orders, t.units_sold, t.revenue,
+               COALESCE(ROUND(t.revenue / NULLIF(t.orders, 0), 2), 0) AS average_order_value,
+               r.returned_units,
+               COALESCE(r.returned_units::FLOAT8 / NULLIF(t.units_sold, 0), 0) AS returns_rate
+        FROM totals t, returned r
+        "#,
+        SALES_IN_RANGE
+    ))
+    .bind(user_id)
+    .bind(range.from)
+    .bind(range.to)
+    .bind(&range.tz)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch analytics", e))?;
+
+    Ok(Json(summary))
+}
+
+/// Best-selling products in the range by revenue
+pub async fn get_top_products(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<TopProduct>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    let range = resolve_range(&state.db, &params).await?;
+    let limit = params.limit.unwrap_or(DEFAULT_TOP_PRODUCTS).clamp(1, MAX_TOP_PRODUCTS);
+
+    let products = sqlx::query_as::<_, TopProduct>(&format!(
+        r#"
+        WITH sales AS ({})
+        SELECT s.product_id, p.name,
+               COUNT(DISTINCT s.order_id) AS orders,
+               SUM(s.units)::BIGINT AS units_sold,
+               SUM(s.revenue) AS revenue
+        FROM sales s
+        JOIN products p ON p.id = s.product_id
+        GROUP BY s.product_id, p.name
+        ORDER BY revenue DESC, units_sold DESC
+        LIMIT $5
+        "#,
+        SALES_IN_RANGE
+    ))
+    .bind(user_id)
+    .bind(range.from)
+    .bind(range.to)
+    .bind(&range.tz)
+    .bind(limit)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch analytics", e))?;
+
+    Ok(Json(products))
+}
+
+/// Sales per day, week or month across the range, including empty periods
+pub async fn get_sales_over_time(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<SalesBucket>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    let range = resolve_range(&state.db, &params).await?;
+    let interval = params.interval.clone().unwrap_or_else(|| "day".into());
+    if !ANALYTICS_INTERVALS.contains(&interval.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid interval. Valid intervals are: {}", ANALYTICS_INTERVALS.join(", ")),
+        ));
+    }
+
+    let buckets = sqlx::query_as::<_, SalesBucket>(&format!(
+        r#"
+        WITH sales AS ({}),
+        periods AS (
+            SELECT generate_series(
+                date_trunc($5, $2::date::timestamp),
+                $3::date::timestamp,
+                ('1 ' || $5)::interval
+            )::date AS period_start
+        )
+        SELECT pr.period_start,
+               COUNT(DISTINCT s.order_id) AS orders,
+               COALESCE(SUM(s.units), 0)::BIGINT AS units_sold,
+               COALESCE(SUM(s.revenue), 0) AS revenue
+        FROM periods pr
+        LEFT JOIN sales s ON date_trunc($5, s.created_at AT TIME ZONE $4::text)::date = pr.period_start
+        GROUP BY pr.period_start
+        ORDER BY pr.period_start
+        "#,
+        SALES_IN_RANGE
+    ))
+    .bind(user_id)
+    .bind(range.from)
+    .bind(range.to)
+    .bind(&range.tz)
+    .bind(&interval)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch analytics", e))?;
+
+    Ok(Json(buckets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, insert_order_item, insert_user, insert_vendor_product};
+    use uuid::Uuid;
+
+    fn query(day: &str, tz: &str, interval: Option<&str>) -> Query<AnalyticsQuery> {
+        Query(AnalyticsQuery {
+            from: Some(day.parse().unwrap()),
+            to: Some(day.parse().unwrap()),
+            tz: Some(tz.into()),
+            interval: interval.map(String::from),
+            limit: None,
+        })
+    }
+
+    /// A vendor with one sale at 23:30 UTC on 10 March 2026, already 11 March in Tokyo
+    async fn late_evening_sale(pool: &PgPool) -> Uuid {
+        let vendor_id = insert_user(pool, "vendor", "vendor").await;
+        let customer_id = insert_user(pool, "customer", "customer").await;
+        let product_id = insert_vendor_product(pool, vendor_id, "25.00", 5).await;
+        let order_id: Uuid = sqlx::query_scalar("INSERT INTO orders (user_id, total) VALUES ($1, 50.00) RETURNING id")
+            .bind(customer_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        let item_id = insert_order_item(pool, order_id, product_id, 2).await;
+        sqlx::query("UPDATE order_items SET created_at = '2026-03-10 23:30:00+00' WHERE id = $1")
+            .bind(item_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        vendor_id
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn days_are_counted_in_the_requested_timezone(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = late_evening_sale(&pool).await;
+        let vendor = || AuthUser { user_id: vendor_id, role: "vendor".into() };
+
+        for (day, tz, orders) in [
+            ("2026-03-10", "UTC", 1),
+            ("2026-03-11", "UTC", 0),
+            ("2026-03-10", "Asia/Tokyo", 0),
+            ("2026-03-11", "Asia/Tokyo", 1),
+        ] {
+            let Json(summary) = get_sales_summary(State(state.clone()), vendor(), query(day, tz, None)).await.unwrap();
+            assert_eq!(summary.orders, orders, "{} in {}", day, tz);
+            assert_eq!(summary.timezone, tz);
+
+            let Json(buckets) = get_sales_over_time(State(state.clone()), vendor(), query(day, tz, Some("day")))
+                .await
+                .unwrap();
+            assert_eq!(buckets.len(), 1);
+            assert_eq!(buckets[0].period_start, day.parse::<NaiveDate>().unwrap());
+            assert_eq!(buckets[0].orders, orders, "{} in {}", day, tz);
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn unknown_timezones_are_rejected(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        let vendor = || AuthUser { user_id: vendor_id, role: "vendor".into() };
+
+        for tz in ["Mars/Olympus_Mons", "UTC'; DROP TABLE orders; --", ""] {
+            let (status, _) = get_sales_summary(State(state.clone()), vendor(), query("2026-03-10", tz, None))
+                .await
+                .unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", tz);
+        }
+        let (status, _) = get_top_products(State(state), vendor(), query("2026-03-10", "Nowhere/City", None))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
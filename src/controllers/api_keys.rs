@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    models::ApiKey::{ApiKey, CreateApiKey, CreatedApiKey, API_KEY_SCOPES},
+    tokens,
+};
+
+/// Most active keys one vendor can have
+const MAX_ACTIVE_KEYS: i64 = 20;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+const API_KEY_COLUMNS: &str = "id, vendor_id, name, prefix, scopes, last_used_at, revoked_at, created_at";
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors can manage API keys"));
+    }
+    Ok(())
+}
+
+/// Create an API key (vendor only)
+/// The key is returned in this response only
+pub async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Json(payload): Json<CreateApiKey>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let name = payload.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(error(StatusCode::BAD_REQUEST, "name must be between 1 and 100 characters"));
+    }
+    if payload.scopes.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Grant at least one scope"));
+    }
+    if let Some(unknown) = payload.scopes.iter().find(|s| !API_KEY_SCOPES.contains(&s.as_str())) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid scope '{}'. Valid scopes are: {}", unknown, API_KEY_SCOPES.join(", ")),
+        ));
+    }
+    let mut scopes = payload.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+
+    let active: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE vendor_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .fetch_one(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to create API key", e))?;
+    if active >= MAX_ACTIVE_KEYS {
+        return Err(error(
+            StatusCode::CONFLICT,
+            &format!("A vendor can have at most {} active API keys. Revoke one first", MAX_ACTIVE_KEYS),
+        ));
+    }
+
+    let key = format!("sk_{}", tokens::generate());
+    let api_key = sqlx::query_as::<_, ApiKey>(&format!(
+        r#"
+        INSERT INTO api_keys (vendor_id, name, prefix, key_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        API_KEY_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(name)
+    .bind(&key[..11])
+    .bind(tokens::hash(&key))
+    .bind(&scopes)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to create API key", e))?;
+
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { api_key, key })))
+}
+
+/// List the authenticated vendor's API keys, including revoked ones
+pub async fn get_api_keys(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Vec<ApiKey>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let keys = sqlx::query_as::<_, ApiKey>(&format!(
+        "SELECT {} FROM api_keys WHERE vendor_id = $1 ORDER BY created_at DESC",
+        API_KEY_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch API keys", e))?;
+
+    Ok(Json(keys))
+}
+
+/// Revoke an API key; requests using it are rejected from now on
+pub async fn revoke_api_key(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let result = sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND vendor_id = $2 AND revoked_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .execute(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to revoke API key", e))?;
+
+    if result.rows_affected() == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "API key not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
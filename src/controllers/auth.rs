@@ -1,22 +1,32 @@
 use crate::app_state::AppState;
-use axum::{extract::{Json, State}, http::StatusCode, response::IntoResponse};
+use axum::{extract::{ConnectInfo, Json, State}, http::{header, HeaderMap, StatusCode}, response::IntoResponse};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{encode, Header, EncodingKey};
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
 use chrono::{Utc, Duration};
-use crate::models::User::User; 
-use crate::controllers::auth_guard::AuthUser;
+use crate::models::User::{User, UserPublic};
+use crate::controllers::{mfa, verification};
+use crate::events::{self, DomainEvent};
+use crate::{jwt::KeyRing, login_attempts, tokens};
+use crate::notifier::Recipient;
 use uuid::Uuid;
 
+/// Roles that can be chosen at registration; admins are promoted in the database
+const REGISTRATION_ROLES: [&str; 2] = ["customer", "vendor"];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,      
     pub role: String,    
+    pub iss: String,
+    pub aud: String,
+    pub iat: usize,
     pub exp: usize,
+    pub ver: i32,        // users.session_version when the token was issued
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,11 +55,6 @@ struct ErrorResponse {
 }
 
 
-pub async fn dashboard(auth_user: AuthUser) -> impl IntoResponse {
-    let message = format!("Welcome back, user ID: {} and you are a {}", auth_user.user_id, auth_user.role);
-    (StatusCode::OK, message)
-}
-
 pub async fn register(
     State(state): State<Arc<AppState>>, 
     Json(payload): Json<RegisterRequest>, 
@@ -72,25 +77,71 @@ pub async fn register(
     };
 
     let role = payload.role.clone().unwrap_or_else(|| "customer".to_string());
+    if !REGISTRATION_ROLES.contains(&role.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("Invalid role. Valid roles are: {}", REGISTRATION_ROLES.join(", ")),
+            }),
+        ));
+    }
     let user_id = Uuid::new_v4();
 
+    let mut tx = state.db.begin().await.map_err(|e| {
+        println!("Failed to start transaction: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to register user".to_string(),
+            }),
+        )
+    })?;
+
     let result = sqlx::query_as::<_, User>(
         r#"
         INSERT INTO users (id, username, email, password_hash, role)
         VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, username, email, password_hash, role
+        RETURNING id, username, email, password_hash, role, email_verified_at, session_version
         "#,
     )
-    .bind(&user_id)
+    .bind(user_id)
     .bind(&payload.username)
     .bind(&payload.email)
     .bind(&password_hash)
     .bind(&role)
-    .fetch_one(&*state.db)
+    .fetch_one(&mut *tx)
     .await;
 
     match result {
-        Ok(user) => Ok((StatusCode::CREATED, Json(user))),
+        Ok(user) => {
+            let event = DomainEvent::UserRegistered {
+                user_id: user.id,
+                username: user.username.clone(),
+                email: user.email.clone(),
+                role: user.role.clone(),
+            };
+            let recipient = Recipient {
+                username: user.username.clone(),
+                email: user.email.clone(),
+            };
+            let committed = match events::record(&mut tx, event).await {
+                Ok(_) => match verification::send_verification_email(&mut tx, user.id, &recipient).await {
+                    Ok(()) => tx.commit().await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            if let Err(e) = committed {
+                println!("Failed to record registration: {:?}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: "Failed to register user".to_string(),
+                    }),
+                ));
+            }
+            Ok((StatusCode::CREATED, Json(UserPublic::from(user))))
+        }
         Err(e) => {
             println!("Database error during registration: {:?}", e);
             if e.to_string().contains("duplicate key value") {
@@ -114,13 +165,41 @@ pub async fn register(
 
 pub async fn login(
     State(state): State<Arc<AppState>>, 
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>, 
 ) -> impl IntoResponse {
     println!("Login attempt for email: {}", payload.email);
+    let email = login_attempts::normalize_email(&payload.email);
+    let ip = login_attempts::client_ip(&headers, addr);
+
+    match login_attempts::retry_after(&state.db, &email, &ip).await {
+        Ok(Some(secs)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, secs.to_string())],
+                Json(ErrorResponse {
+                    error: format!("Too many failed login attempts. Please try again in {} seconds", secs),
+                }),
+            )
+                .into_response();
+        }
+        Ok(None) => {}
+        Err(e) => {
+            println!("Database error during login: {:?}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Database error during login".to_string(),
+                }),
+            )
+                .into_response();
+        }
+    }
 
     let user = match sqlx::query_as::<_, User>(
         r#"
-        SELECT id, username, email, password_hash, role
+        SELECT id, username, email, password_hash, role, email_verified_at, session_version
         FROM users
         WHERE email = $1
         "#,
@@ -129,16 +208,7 @@ pub async fn login(
     .fetch_optional(&*state.db)
     .await
     {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "User not found".to_string(),
-                }),
-            )
-                .into_response();
-        }
+        Ok(user) => user,
         Err(e) => {
             println!("Database error during login: {:?}", e);
             return (
@@ -151,56 +221,59 @@ pub async fn login(
         }
     };
 
-    let parsed_hash = match PasswordHash::new(&user.password_hash) {
-        Ok(hash) => hash,
-        Err(_) => {
+    // Unknown emails are checked against a dummy hash so the response takes as
+    // long, and looks the same, as a wrong password
+    let password_hash = match &user {
+        Some(user) => user.password_hash.as_str(),
+        None => dummy_password_hash(),
+    };
+    let password_valid = PasswordHash::new(password_hash)
+        .map(|parsed| Argon2::default().verify_password(payload.password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false);
+
+    let user = match user {
+        Some(user) if password_valid => user,
+        user => {
+            if let Err(e) = login_attempts::record_failure(&state.db, &email, user.map(|user| user.id), &ip).await {
+                println!("Failed to record login attempt: {:?}", e);
+            }
             return (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::UNAUTHORIZED,
                 Json(ErrorResponse {
-                    error: "Invalid password hash format".to_string(),
+                    error: "Invalid email or password".to_string(),
                 }),
             )
                 .into_response();
         }
     };
 
-    let argon2 = Argon2::default();
-    if let Err(_) = argon2.verify_password(payload.password.as_bytes(), &parsed_hash) {
-        return (
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Invalid credentials".to_string(),
-            }),
-        )
-            .into_response();
-    }
-
-    let claims = Claims {
-        sub: user.id,
-        role: user.role.clone(),
-        exp: (Utc::now() + Duration::hours(24)).timestamp() as usize,
-    };
-
-    let jwt_secret = match std::env::var("JWT_SECRET") {
-        Ok(secret) => secret,
-        Err(_) => {
+    // With two-factor authentication the session token comes from the second step
+    match mfa::login_challenge(&state.db, user.id, &user.role).await {
+        Ok(Some(challenge)) => return (StatusCode::OK, Json(challenge)).into_response(),
+        Ok(None) => {}
+        Err(e) => {
+            println!("Database error during login: {:?}", e);
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse {
-                    error: "JWT secret not set".to_string(),
+                    error: "Database error during login".to_string(),
                 }),
             )
                 .into_response();
         }
-    };
+    }
 
-    let token = match encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret.as_ref())) {
+    if let Err(e) = login_attempts::record_success(&state.db, &email, user.id, &ip).await {
+        println!("Failed to record login attempt: {:?}", e);
+    }
+
+    let token = match issue_token(&state.keys, user.id, &user.role, user.session_version) {
         Ok(token) => token,
-        Err(_) => {
+        Err(message) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse {
-                    error: "Token generation failed".to_string(),
+                    error: message.to_string(),
                 }),
             )
                 .into_response();
@@ -210,3 +283,59 @@ pub async fn login(
     let response = LoginResponse { token };
     (StatusCode::OK, Json(response)).into_response()
 }
+
+/// An Argon2 hash of a random password, computed once
+fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(tokens::generate().as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Sign a 24 hour session token for the user with the active key
+pub fn issue_token(keys: &KeyRing, user_id: Uuid, role: &str, session_version: i32) -> Result<String, &'static str> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: user_id,
+        role: role.to_string(),
+        iss: keys.issuer().to_string(),
+        aud: keys.audience().to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::hours(24)).timestamp() as usize,
+        ver: session_version,
+    };
+
+    keys.sign(&claims).map_err(|e| {
+        println!("Token generation failed: {:?}", e);
+        "Token generation failed"
+    })
+}
+
+/// Public keys other services use to verify our tokens
+pub async fn jwks(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.keys.jwks().clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, assert_no_hash_material, password_hash, registration, response_body};
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn register_response_has_no_hash_material(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+
+        let body = response_body(register(State(state), registration("customer")).await).await;
+
+        assert!(body.contains("alice@example.com"), "unexpected register response: {}", body);
+        assert_no_hash_material(&body, &password_hash(&pool, "alice@example.com").await);
+    }
+}
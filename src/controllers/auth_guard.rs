@@ -1,23 +1,27 @@
 use async_trait::async_trait;
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::{FromRequestParts, OriginalUri},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Method, StatusCode},
     RequestPartsExt, // Add this import
 };
 use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::{app_state::AppState, tokens};
+
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: Uuid,       
-    pub role: String,   
+    pub sub: Uuid,
+    pub role: String,
     pub exp: usize,
+    #[serde(default)]
+    pub ver: i32,        // Must match users.session_version
 }
 
 
@@ -27,59 +31,184 @@ pub struct AuthUser {
     pub role: String,
 }
 
+fn decode_token(state: &AppState, token: &str) -> Result<Claims, StatusCode> {
+    state.keys.verify::<Claims>(token).map_err(|e| {
+        println!("JWT validation failed: {:?}", e);
+        StatusCode::UNAUTHORIZED
+    })
+}
+
+/// An API key sent as `X-API-Key: <key>` or `Authorization: ApiKey <key>`
+fn api_key_from_headers(headers: &HeaderMap) -> Option<String> {
+    if let Some(key) = headers.get("x-api-key").and_then(|value| value.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("ApiKey "))
+        .map(|key| key.trim().to_string())
+}
+
+/// The scope an API key needs for a request. Keys only reach the product and
+/// order routes; GET needs read access and anything else write access.
+fn required_scope(method: &Method, path: &str) -> Option<String> {
+    let resource = path.trim_start_matches('/').split('/').next()?;
+    if resource != "products" && resource != "orders" {
+        return None;
+    }
+    let access = if method == Method::GET || method == Method::HEAD { "read" } else { "write" };
+    Some(format!("{}:{}", resource, access))
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyOwner {
+    id: Uuid,
+    vendor_id: Uuid,
+    role: String,
+    scopes: Vec<String>,
+    recently_used: bool,
+}
+
+/// Authenticate a request made with a vendor API key, checking its scopes
+async fn authenticate_api_key(parts: &Parts, state: &AppState, key: &str) -> Result<AuthUser, StatusCode> {
+    let owner = sqlx::query_as::<_, ApiKeyOwner>(
+        r#"
+        SELECT k.id, k.vendor_id, u.role, k.scopes,
+               COALESCE(k.last_used_at > NOW() - INTERVAL '1 minute', FALSE) AS recently_used
+        FROM api_keys k
+        JOIN users u ON u.id = k.vendor_id
+        WHERE k.key_hash = $1 AND k.revoked_at IS NULL
+        "#,
+    )
+    .bind(tokens::hash(key))
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| {
+        println!("Failed to check API key: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .filter(|owner| owner.role == "vendor")
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Nested routers see the path without their prefix
+    let path = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(parts.uri.path(), |uri| uri.0.path());
+    match required_scope(&parts.method, path) {
+        Some(scope) if owner.scopes.contains(&scope) => {}
+        _ => return Err(StatusCode::FORBIDDEN),
+    }
+
+    // Recorded at most once a minute so busy integrations don't write on every request
+    if !owner.recently_used {
+        let touched = sqlx::query("UPDATE api_keys SET last_used_at = NOW() WHERE id = $1")
+            .bind(owner.id)
+            .execute(&*state.db)
+            .await;
+        if let Err(e) = touched {
+            println!("Failed to record API key use: {:?}", e);
+        }
+    }
+
+    Ok(AuthUser {
+        user_id: owner.vendor_id,
+        role: owner.role,
+    })
+}
+
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<Arc<AppState>> for AuthUser {
     type Rejection = StatusCode;
-   
+
     async fn from_request_parts(
         parts: &mut Parts,
-        _state: &S,
+        state: &Arc<AppState>,
     ) -> Result<Self, Self::Rejection> {
+        if let Some(key) = api_key_from_headers(&parts.headers) {
+            return authenticate_api_key(parts, state, &key).await;
+        }
+
         // Extract the Authorization header first
-        if let Ok(TypedHeader(Authorization(bearer))) = parts.extract::<TypedHeader<Authorization<Bearer>>>().await {
-            let token = bearer.token();
-            
-            let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into());
-            let decoded = decode::<Claims>(
-                token,
-                &DecodingKey::from_secret(secret.as_ref()),
-                &Validation::default(),
-            )
+        let claims = if let Ok(TypedHeader(Authorization(bearer))) = parts.extract::<TypedHeader<Authorization<Bearer>>>().await {
+            decode_token(state, bearer.token())?
+        } else {
+            // Fallback: try to get token from cookies
+            let jar = axum_extra::extract::cookie::CookieJar::from_headers(&parts.headers);
+            let token = jar
+                .get("token")
+                .map(|cookie| cookie.value().to_string())
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            decode_token(state, &token)?
+        };
+
+        // Tokens issued before a password change or reset are no longer valid
+        let session_version: Option<i32> = sqlx::query_scalar("SELECT session_version FROM users WHERE id = $1")
+            .bind(claims.sub)
+            .fetch_optional(&*state.db)
+            .await
             .map_err(|e| {
-                println!("JWT validation failed: {:?}", e);
-                StatusCode::UNAUTHORIZED
+                println!("Failed to check session: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
             })?;
-           
-            return Ok(AuthUser {
-    user_id: decoded.claims.sub,
-    role: decoded.claims.role, 
-});
+        if session_version != Some(claims.ver) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        Ok(AuthUser {
+            user_id: claims.sub,
+            role: claims.role,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_need_read_scope() {
+        let cases = [
+            (Method::GET, "/products", "products:read"),
+            (Method::GET, "/products/6f1c2a8e-0000-0000-0000-000000000000", "products:read"),
+            (Method::HEAD, "/products/archived", "products:read"),
+            (Method::GET, "/orders", "orders:read"),
+            (Method::GET, "/orders/vendor/items", "orders:read"),
+        ];
+        for (method, path, scope) in cases {
+            assert_eq!(required_scope(&method, path).as_deref(), Some(scope), "{} {}", method, path);
         }
+    }
 
-        // Fallback: try to get token from cookies
-        let jar = axum_extra::extract::cookie::CookieJar::from_headers(&parts.headers);
-        let token = jar
-            .get("token")
-            .map(|cookie| cookie.value().to_string())
-            .ok_or(StatusCode::UNAUTHORIZED)?;
-       
-        let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "secret".into());
-        let decoded = decode::<Claims>(
-            &token,
-            &DecodingKey::from_secret(secret.as_ref()),
-            &Validation::default(),
-        )
-        .map_err(|e| {
-            println!("JWT validation failed: {:?}", e);
-            StatusCode::UNAUTHORIZED
-        })?;
-       
-       return Ok(AuthUser {
-    user_id: decoded.claims.sub,
-    role: decoded.claims.role, 
-});
+    #[test]
+    fn everything_else_needs_write_scope() {
+        let cases = [
+            (Method::POST, "/products", "products:write"),
+            (Method::PUT, "/products/6f1c2a8e-0000-0000-0000-000000000000", "products:write"),
+            (Method::DELETE, "/products/6f1c2a8e-0000-0000-0000-000000000000", "products:write"),
+            (Method::POST, "/products/6f1c2a8e-0000-0000-0000-000000000000/inventory", "products:write"),
+            (Method::POST, "/orders/6f1c2a8e-0000-0000-0000-000000000000/cancel", "orders:write"),
+            (Method::PATCH, "/orders/6f1c2a8e-0000-0000-0000-000000000000/status", "orders:write"),
+        ];
+        for (method, path, scope) in cases {
+            assert_eq!(required_scope(&method, path).as_deref(), Some(scope), "{} {}", method, path);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn other_routes_are_out_of_reach() {
+        let cases = [
+            (Method::GET, "/"),
+            (Method::GET, "/cart"),
+            (Method::GET, "/users/me"),
+            (Method::POST, "/admin/payouts/run"),
+            (Method::POST, "/api-keys"),
+            (Method::GET, "/vendor/products/export"),
+            (Method::GET, "/productsx"),
+        ];
+        for (method, path) in cases {
+            assert_eq!(required_scope(&method, path), None, "{} {}", method, path);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{ extract::{ State, Path }, http::StatusCode, Json };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use uuid::Uuid;
 use std::sync::Arc;
 use crate::{ app_state::AppState, models::CartItem };
@@ -42,10 +42,38 @@ pub async fn add_cart_item(
 ) -> Result<Json<Vec<CartItem>>, (StatusCode, Json<ErrorResponse>)> {
     println!("User ID: {}, Product ID: {}", user_id, product_id);
     
-    // First add/update the item in cart
-    sqlx
+    // Suspended stores can't sell, even products already in the catalog listing
+    let suspended: bool = sqlx
+        ::query_scalar(
+            "SELECT EXISTS (
+             SELECT 1 FROM products p JOIN vendor_profiles vp ON vp.vendor_id = p.vendor_id
+             WHERE p.id = $1 AND vp.status = 'suspended')"
+        )
+        .bind(product_id)
+        .fetch_one(&*state.db).await
+        .map_err(|e| {
+            eprintln!("DB fetch error: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to add item to cart".to_string(),
+                }),
+            )
+        })?;
+    if suspended {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "This product's store is suspended and can't take orders".to_string(),
+            }),
+        ));
+    }
+
+    // First add/update the item in cart, unless the product is archived
+    let added = sqlx
         ::query(
-            "INSERT INTO cart_items (user_id, product_id, quantity) VALUES ($1, $2, 1)
+            "INSERT INTO cart_items (user_id, product_id, quantity)
+         SELECT $1, id, 1 FROM products WHERE id = $2 AND archived_at IS NULL
          ON CONFLICT (user_id, product_id) DO UPDATE SET quantity = cart_items.quantity + 1"
         )
         .bind(user_id)
@@ -61,6 +89,15 @@ pub async fn add_cart_item(
             )
         })?;
 
+    if added.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Product not found or no longer available".to_string(),
+            }),
+        ));
+    }
+
     // Then fetch and return updated cart items
     let items = sqlx
         ::query_as::<_, CartItem>("SELECT * FROM cart_items WHERE user_id = $1")
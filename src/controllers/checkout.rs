@@ -0,0 +1,284 @@
+//! Starting, viewing and abandoning a checkout. The order itself is still
+//! placed with `POST /orders`, which completes the checkout.
+
+use axum::{extract::State, http::StatusCode, Json};
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    checkout,
+    controllers::auth_guard::AuthUser,
+    models::Checkout::{CheckoutDetails, CheckoutItem, CheckoutSession, UnavailableItem},
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unavailable: Vec<UnavailableItem>,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+            unavailable: Vec::new(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// Expired sessions keep status "active" until the expiry job runs, so the
+/// status is derived here
+const SESSION_COLUMNS: &str = "id, CASE WHEN status = 'active' AND expires_at <= NOW() THEN 'expired' ELSE status END AS status, \
+     expires_at, order_id, created_at";
+
+async fn load_details(conn: &mut PgConnection, session: CheckoutSession) -> Result<CheckoutDetails, sqlx::Error> {
+    let items = sqlx::query_as::<_, CheckoutItem>(
+        r#"
+        SELECT h.product_id, p.name AS product_name, h.quantity, p.price
+        FROM stock_holds h
+        JOIN products p ON p.id = h.product_id
+        WHERE h.session_id = $1
+        ORDER BY p.name
+        "#,
+    )
+    .bind(session.id)
+    .fetch_all(conn)
+    .await?;
+
+    let subtotal = items
+        .iter()
+        .fold(BigDecimal::from(0), |sum, item| sum + &item.price * BigDecimal::from(item.quantity));
+    Ok(CheckoutDetails { session, items, subtotal })
+}
+
+/// Hold everything in the cart for `CHECKOUT_HOLD_MINUTES`. Starting again
+/// replaces the previous checkout, e.g. after the cart changed.
+pub async fn start_checkout(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<CheckoutDetails>), (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    checkout::release(&mut *tx, auth_user.user_id, "released").await
+        .map_err(|e| internal_error("Failed to start checkout", e))?;
+
+    // Lock the products so two checkouts can't both take the last units.
+    // Archived products can't be bought at all.
+    let cart = sqlx::query_as::<_, UnavailableItem>(&format!(
+        r#"
+        SELECT ci.product_id, p.name AS product_name, ci.quantity AS requested,
+               CASE WHEN p.archived_at IS NULL THEN p.stock - {} ELSE 0 END AS available
+        FROM cart_items ci
+        JOIN products p ON p.id = ci.product_id
+        WHERE ci.user_id = $1
+        ORDER BY ci.product_id
+        FOR UPDATE OF p
+        "#,
+        checkout::held_stock("$1")
+    ))
+    .bind(auth_user.user_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to start checkout", e))?;
+
+    if cart.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Cart is empty. Add items to cart before checking out"));
+    }
+    let unavailable: Vec<UnavailableItem> = cart
+        .into_iter()
+        .filter(|item| item.requested > item.available)
+        .map(|item| UnavailableItem { available: item.available.max(0), ..item })
+        .collect();
+    if !unavailable.is_empty() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "Some items in your cart are no longer available in the requested quantity".into(),
+                unavailable,
+            }),
+        ));
+    }
+
+    let session = sqlx::query_as::<_, CheckoutSession>(&format!(
+        r#"
+        INSERT INTO checkout_sessions (user_id, expires_at)
+        VALUES ($1, NOW() + make_interval(mins => $2))
+        RETURNING {}
+        "#,
+        SESSION_COLUMNS
+    ))
+    .bind(auth_user.user_id)
+    .bind(checkout::hold_minutes())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to start checkout", e))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO stock_holds (session_id, product_id, quantity)
+        SELECT $1, product_id, quantity FROM cart_items WHERE user_id = $2
+        "#,
+    )
+    .bind(session.id)
+    .bind(auth_user.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to start checkout", e))?;
+
+    let details = load_details(&mut tx, session).await
+        .map_err(|e| internal_error("Failed to start checkout", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok((StatusCode::CREATED, Json(details)))
+}
+
+/// The caller's most recent checkout
+pub async fn get_checkout(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<Json<CheckoutDetails>, (StatusCode, Json<ErrorResponse>)> {
+    let mut conn = state.db.acquire().await
+        .map_err(|e| internal_error("Failed to fetch checkout", e))?;
+
+    let session = sqlx::query_as::<_, CheckoutSession>(&format!(
+        "SELECT {} FROM checkout_sessions WHERE user_id = $1 ORDER BY created_at DESC LIMIT 1",
+        SESSION_COLUMNS
+    ))
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch checkout", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "No checkout found"))?;
+
+    let details = load_details(&mut conn, session).await
+        .map_err(|e| internal_error("Failed to fetch checkout", e))?;
+    Ok(Json(details))
+}
+
+/// Abandon the checkout in progress, giving the held stock back
+pub async fn cancel_checkout(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let released = checkout::release(&*state.db, auth_user.user_id, "released").await
+        .map_err(|e| internal_error("Failed to release checkout", e))?;
+    if !released {
+        return Err(error(StatusCode::NOT_FOUND, "No checkout in progress"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::{cart::add_cart_item, order::create_order};
+    use crate::models::Order::CreateOrderRequest;
+    use crate::test_support::{app_state, insert_user, insert_vendor_product, insert_vendor_profile};
+    use axum::extract::Path;
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    fn customer(user_id: Uuid) -> AuthUser {
+        AuthUser { user_id, role: "customer".into() }
+    }
+
+    /// Two customers after the same product, each with one in their cart
+    async fn two_carts(state: &Arc<AppState>, pool: &PgPool, stock: i32) -> (Uuid, Uuid, Uuid) {
+        let vendor_id = insert_user(pool, "vendor", "vendor").await;
+        insert_vendor_profile(pool, vendor_id, "bikes").await;
+        let product_id = insert_vendor_product(pool, vendor_id, "20.00", stock).await;
+        let first = insert_user(pool, "first", "customer").await;
+        let second = insert_user(pool, "second", "customer").await;
+        for user_id in [first, second] {
+            let Json(cart) = add_cart_item(State(state.clone()), customer(user_id), Path(product_id)).await.unwrap();
+            assert_eq!(cart.len(), 1);
+        }
+        (first, second, product_id)
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn a_hold_keeps_its_stock_from_other_customers(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let (first, second, product_id) = two_carts(&state, &pool, 1).await;
+
+        let (status, Json(held)) = start_checkout(State(state.clone()), customer(first)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(held.items.len(), 1);
+
+        let (status, Json(refused)) = start_checkout(State(state.clone()), customer(second)).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(refused.unavailable.len(), 1);
+        assert_eq!((refused.unavailable[0].product_id, refused.unavailable[0].available), (product_id, 0));
+
+        let payload = CreateOrderRequest { shipping: vec![], shipping_address: None };
+        let Err((status, _)) = create_order(State(state), customer(second), Json(payload)).await else {
+            panic!("stock held by another checkout was ordered");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn an_expired_hold_gives_its_stock_back(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let (first, second, _) = two_carts(&state, &pool, 1).await;
+        let (status, _) = start_checkout(State(state.clone()), customer(first)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        sqlx::query("UPDATE checkout_sessions SET expires_at = NOW() - INTERVAL '1 second'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Before the expiry job has run as well as after
+        let (status, _) = start_checkout(State(state.clone()), customer(second)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        checkout::release(&pool, second, "released").await.unwrap();
+
+        assert_eq!(checkout::expire(&pool).await.unwrap(), 1);
+        let (status, _) = start_checkout(State(state), customer(second)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn placing_the_order_consumes_the_hold(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let (first, second, product_id) = two_carts(&state, &pool, 2).await;
+        let (_, Json(held)) = start_checkout(State(state.clone()), customer(first)).await.unwrap();
+
+        let payload = CreateOrderRequest { shipping: vec![], shipping_address: None };
+        if let Err((status, Json(e))) = create_order(State(state.clone()), customer(first), Json(payload)).await {
+            panic!("order failed with {}: {:?}", status, e);
+        }
+
+        let (status, order_id): (String, Option<Uuid>) =
+            sqlx::query_as("SELECT status, order_id FROM checkout_sessions WHERE id = $1")
+                .bind(held.session.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "completed");
+        assert!(order_id.is_some());
+        let stock: i32 = sqlx::query_scalar("SELECT stock FROM products WHERE id = $1")
+            .bind(product_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stock, 1);
+
+        // The unit sold no longer counts as held, so the last one is still available
+        let (status, _) = start_checkout(State(state), customer(second)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,390 @@
+//! A product's inventory history, manual stock adjustments, low-stock alerts
+//! and back-in-stock subscriptions
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    checkout,
+    controllers::auth_guard::AuthUser,
+    inventory::{self, Movement, MOVEMENT_COLUMNS, MOVEMENT_REASONS},
+    models::Inventory::{
+        CreateInventoryAdjustment, InventoryHistory, InventoryMovement, InventoryQuery, LowStockAlert,
+        StockSubscription, UpdateLowStockAlert,
+    },
+};
+
+const DEFAULT_MOVEMENTS: i64 = 50;
+const MAX_MOVEMENTS: i64 = 500;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// A product's stock, and whether it has been archived
+struct ProductStock {
+    stock: i32,
+    archived: bool,
+}
+
+/// The product's stock, if the caller is its vendor or an admin. With `lock`
+/// the row stays locked until the transaction ends.
+async fn owned_product_stock(
+    conn: &mut PgConnection,
+    auth_user: &AuthUser,
+    product_id: Uuid,
+    lock: bool,
+) -> Result<ProductStock, (StatusCode, Json<ErrorResponse>)> {
+    let query = if lock {
+        "SELECT vendor_id, stock, archived_at IS NOT NULL FROM products WHERE id = $1 FOR UPDATE"
+    } else {
+        "SELECT vendor_id, stock, archived_at IS NOT NULL FROM products WHERE id = $1"
+    };
+    let (vendor_id, stock, archived): (Uuid, i32, bool) = sqlx::query_as(query)
+        .bind(product_id)
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| internal_error("Failed to fetch product", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Product not found"))?;
+
+    if auth_user.role != "admin" && vendor_id != auth_user.user_id {
+        return Err(error(StatusCode::FORBIDDEN, "Only the product's vendor can manage its inventory"));
+    }
+    Ok(ProductStock { stock, archived })
+}
+
+/// The product's stock movements, newest first (vendor or admin)
+pub async fn get_inventory(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(product_id): Path<Uuid>,
+    Query(params): Query<InventoryQuery>,
+) -> Result<Json<InventoryHistory>, (StatusCode, Json<ErrorResponse>)> {
+    if let Some(reason) = &params.reason {
+        if !MOVEMENT_REASONS.contains(&reason.as_str()) {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid reason. Valid reasons are: {}", MOVEMENT_REASONS.join(", ")),
+            ));
+        }
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_MOVEMENTS).clamp(1, MAX_MOVEMENTS);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let mut conn = state.db.acquire().await
+        .map_err(|e| internal_error("Failed to fetch inventory", e))?;
+    let stock = owned_product_stock(&mut conn, &auth_user, product_id, false).await?.stock;
+
+    let (low_stock_threshold, waiting_customers): (Option<i32>, i64) = sqlx::query_as(
+        r#"
+        SELECT p.low_stock_threshold,
+               (SELECT COUNT(*) FROM stock_subscriptions s WHERE s.product_id = p.id AND s.notified_at IS NULL)
+        FROM products p
+        WHERE p.id = $1
+        "#,
+    )
+    .bind(product_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch inventory", e))?;
+
+    let movements = sqlx::query_as::<_, InventoryMovement>(&format!(
+        r#"
+        SELECT {}
+        FROM inventory_movements
+        WHERE product_id = $1 AND ($2::VARCHAR IS NULL OR reason = $2)
+        ORDER BY created_at DESC, id
+        LIMIT $3 OFFSET $4
+        "#,
+        MOVEMENT_COLUMNS
+    ))
+    .bind(product_id)
+    .bind(&params.reason)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch inventory", e))?;
+
+    Ok(Json(InventoryHistory {
+        product_id,
+        stock,
+        low_stock_threshold,
+        waiting_customers,
+        movements,
+    }))
+}
+
+/// Add or remove stock by a relative amount with a note (vendor or admin)
+pub async fn adjust_inventory(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(product_id): Path<Uuid>,
+    Json(payload): Json<CreateInventoryAdjustment>,
+) -> Result<(StatusCode, Json<InventoryMovement>), (StatusCode, Json<ErrorResponse>)> {
+    if payload.change == 0 {
+        return Err(error(StatusCode::BAD_REQUEST, "change must not be zero"));
+    }
+    let note = payload.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+    let ProductStock { stock, archived } = owned_product_stock(&mut tx, &auth_user, product_id, true).await?;
+    if archived {
+        return Err(error(StatusCode::CONFLICT, "This product is archived; restore it before adjusting its stock"));
+    }
+    let Some(new_stock) = stock.checked_add(payload.change) else {
+        return Err(error(StatusCode::BAD_REQUEST, "change is too large"));
+    };
+    if new_stock < 0 {
+        return Err(error(
+            StatusCode::CONFLICT,
+            &format!("Only {} in stock; stock can't go below zero", stock),
+        ));
+    }
+
+    let movement = Movement {
+        product_id,
+        change: payload.change,
+        reason: "adjustment",
+        actor_id: Some(auth_user.user_id),
+        note: note.map(String::from),
+        ..Default::default()
+    };
+    let movement = inventory::apply(&mut tx, movement).await
+        .map_err(|e| internal_error("Failed to adjust stock", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Product not found"))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok((StatusCode::CREATED, Json(movement)))
+}
+
+/// Set or remove the product's low-stock alert (vendor or admin). Only a later
+/// drop to the threshold sends an alert, not setting one below the current stock.
+pub async fn update_low_stock_alert(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(product_id): Path<Uuid>,
+    Json(payload): Json<UpdateLowStockAlert>,
+) -> Result<Json<LowStockAlert>, (StatusCode, Json<ErrorResponse>)> {
+    if payload.low_stock_threshold.is_some_and(|threshold| threshold < 0) {
+        return Err(error(StatusCode::BAD_REQUEST, "low_stock_threshold must not be negative"));
+    }
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+    owned_product_stock(&mut tx, &auth_user, product_id, true).await?;
+
+    let alert = sqlx::query_as::<_, LowStockAlert>(
+        r#"
+        UPDATE products SET low_stock_threshold = $2, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id AS product_id, stock, low_stock_threshold
+        "#,
+    )
+    .bind(product_id)
+    .bind(payload.low_stock_threshold)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to update low-stock alert", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(alert))
+}
+
+const SUBSCRIPTION_COLUMNS: &str = "s.id, s.product_id, p.name AS product_name, p.stock, s.created_at, s.notified_at";
+
+/// Ask to be emailed when an out-of-stock product is available again
+/// (customers only). Subscribing again after being notified re-arms it.
+pub async fn subscribe_to_restock(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(product_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<StockSubscription>), (StatusCode, Json<ErrorResponse>)> {
+    if role != "customer" {
+        return Err(error(StatusCode::FORBIDDEN, "Only customers can subscribe to back-in-stock emails"));
+    }
+
+    // Stock held by other customers' checkouts isn't available either
+    let stock: i32 = sqlx::query_scalar(&format!(
+        "SELECT p.stock - {} FROM products p WHERE p.id = $1 AND p.archived_at IS NULL",
+        checkout::held_stock("$2")
+    ))
+    .bind(product_id)
+    .bind(user_id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch product", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Product not found"))?;
+    if stock > 0 {
+        return Err(error(StatusCode::CONFLICT, "This product is in stock"));
+    }
+
+    let subscription = sqlx::query_as::<_, StockSubscription>(&format!(
+        r#"
+        WITH s AS (
+            INSERT INTO stock_subscriptions (product_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT (product_id, user_id) DO UPDATE SET created_at = NOW(), notified_at = NULL
+            RETURNING *
+        )
+        SELECT {}
+        FROM s
+        JOIN products p ON p.id = s.product_id
+        "#,
+        SUBSCRIPTION_COLUMNS
+    ))
+    .bind(product_id)
+    .bind(user_id)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to subscribe", e))?;
+
+    Ok((StatusCode::CREATED, Json(subscription)))
+}
+
+/// Stop waiting for a product
+pub async fn unsubscribe_from_restock(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(product_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let result = sqlx::query("DELETE FROM stock_subscriptions WHERE product_id = $1 AND user_id = $2")
+        .bind(product_id)
+        .bind(auth_user.user_id)
+        .execute(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to unsubscribe", e))?;
+    if result.rows_affected() == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "You are not subscribed to this product"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The caller's back-in-stock subscriptions, newest first
+pub async fn get_my_stock_subscriptions(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<Json<Vec<StockSubscription>>, (StatusCode, Json<ErrorResponse>)> {
+    let subscriptions = sqlx::query_as::<_, StockSubscription>(&format!(
+        r#"
+        SELECT {}
+        FROM stock_subscriptions s
+        JOIN products p ON p.id = s.product_id
+        WHERE s.user_id = $1
+        ORDER BY s.created_at DESC
+        "#,
+        SUBSCRIPTION_COLUMNS
+    ))
+    .bind(auth_user.user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch subscriptions", e))?;
+
+    Ok(Json(subscriptions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, insert_user, insert_vendor_product};
+    use sqlx::PgPool;
+
+    fn adjustment(change: i32) -> Json<CreateInventoryAdjustment> {
+        Json(CreateInventoryAdjustment { change, note: None })
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn adjustments_cannot_overflow_stock_or_touch_archived_products(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        let product_id = insert_vendor_product(&pool, vendor_id, "10.00", 5).await;
+        let vendor = || AuthUser { user_id: vendor_id, role: "vendor".into() };
+
+        let (status, _) = adjust_inventory(State(state.clone()), vendor(), Path(product_id), adjustment(i32::MAX))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        sqlx::query("UPDATE products SET archived_at = NOW() WHERE id = $1")
+            .bind(product_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let (status, _) = adjust_inventory(State(state), vendor(), Path(product_id), adjustment(3))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let stock: i32 = sqlx::query_scalar("SELECT stock FROM products WHERE id = $1")
+            .bind(product_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stock, 5);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn stock_held_by_other_checkouts_counts_as_sold_out(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        let product_id = insert_vendor_product(&pool, vendor_id, "10.00", 2).await;
+        let buyer_id = insert_user(&pool, "buyer", "customer").await;
+        let waiting_id = insert_user(&pool, "waiting", "customer").await;
+        let waiting = || AuthUser { user_id: waiting_id, role: "customer".into() };
+
+        let (status, _) = subscribe_to_restock(State(state.clone()), waiting(), Path(product_id))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        sqlx::query(
+            r#"
+            WITH cs AS (
+                INSERT INTO checkout_sessions (user_id, expires_at) VALUES ($1, NOW() + INTERVAL '15 minutes')
+                RETURNING id
+            )
+            INSERT INTO stock_holds (session_id, product_id, quantity) SELECT id, $2, 2 FROM cs
+            "#,
+        )
+        .bind(buyer_id)
+        .bind(product_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let (status, Json(subscription)) = subscribe_to_restock(State(state), waiting(), Path(product_id))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(subscription.product_id, product_id);
+    }
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    invoice,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct InvoiceQuery {
+    pub format: Option<String>,  // "html" (default) or "pdf"
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+/// Get the invoices and credit notes for an order as HTML or PDF
+/// Customer: every document for their own order
+/// Vendor: only the documents they issued
+pub async fn get_order_invoice(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(order_id): Path<Uuid>,
+    Query(params): Query<InvoiceQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let format = params.format.unwrap_or_else(|| "html".into());
+    if format != "html" && format != "pdf" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("Invalid format '{}'. Valid formats are: html, pdf", format),
+            }),
+        ));
+    }
+
+    let owner_id: Option<Uuid> = sqlx::query_scalar("SELECT user_id FROM orders WHERE id = $1")
+        .bind(order_id)
+        .fetch_optional(&*state.db)
+        .await
+        .map_err(|e| {
+            println!("Database error fetching order: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to fetch order".into(),
+                }),
+            )
+        })?;
+
+    let vendor_filter = match (owner_id, auth_user.role.as_str()) {
+        (None, _) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Order not found".into(),
+                }),
+            ))
+        }
+        (Some(owner_id), "customer") if owner_id == auth_user.user_id => None,
+        (Some(_), "vendor") => Some(auth_user.user_id),
+        _ => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(ErrorResponse {
+                    error: "You don't have permission to view this invoice".into(),
+                }),
+            ))
+        }
+    };
+
+    let mut conn = state.db.acquire().await.map_err(|e| {
+        println!("Failed to acquire connection: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch invoices".into(),
+            }),
+        )
+    })?;
+    let documents = invoice::fetch_documents(&mut conn, order_id, vendor_filter).await.map_err(|e| {
+        println!("Database error fetching invoices: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch invoices".into(),
+            }),
+        )
+    })?;
+
+    if documents.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "No invoices found for this order".into(),
+            }),
+        ));
+    }
+
+    if format == "pdf" {
+        let pdf = invoice::render_pdf(&documents).map_err(|e| {
+            println!("Failed to render invoice PDF: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to render invoice".into(),
+                }),
+            )
+        })?;
+        let disposition = format!("inline; filename=\"invoice-{}.pdf\"", order_id);
+        return Ok((
+            [(header::CONTENT_TYPE, "application/pdf".to_string()), (header::CONTENT_DISPOSITION, disposition)],
+            pdf,
+        )
+            .into_response());
+    }
+
+    Ok(Html(invoice::render_html(&documents)).into_response())
+}
@@ -0,0 +1,158 @@
+//! A vendor's side of the ledger: balance, statement and payouts
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use bigdecimal::BigDecimal;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    ledger,
+    models::Ledger::{Payout, Statement, StatementLine, StatementQuery, VendorBalance},
+};
+
+/// Default statement range, in days, ending today
+const DEFAULT_STATEMENT_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors have a ledger"));
+    }
+    Ok(())
+}
+
+/// What the platform owes the vendor, and how much of it the next payout run can pay
+pub async fn get_balance(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<VendorBalance>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let balance = ledger::vendor_balance(&*state.db, user_id).await
+        .map_err(|e| internal_error("Failed to fetch balance", e))?;
+
+    Ok(Json(balance))
+}
+
+/// Every ledger transaction in the range with its gross, commission and net,
+/// and the running balance after each
+pub async fn get_statement(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<StatementQuery>,
+) -> Result<Json<Statement>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    let to = params.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = params.from.unwrap_or(to - Duration::days(DEFAULT_STATEMENT_DAYS - 1));
+    if from > to {
+        return Err(error(StatusCode::BAD_REQUEST, "from must not be after to"));
+    }
+
+    let lines = sqlx::query_as::<_, StatementLine>(
+        r#"
+        WITH transactions AS (
+            SELECT transaction_id, kind, order_id, invoice_id, payout_id,
+                   MIN(created_at) AS created_at,
+                   COALESCE(SUM(amount) FILTER (WHERE account = 'platform_cash'), 0) AS gross,
+                   COALESCE(-SUM(amount) FILTER (WHERE account = 'commission_revenue'), 0) AS commission,
+                   COALESCE(-SUM(amount) FILTER (WHERE account = 'vendor_payable'), 0) AS net
+            FROM ledger_entries
+            WHERE vendor_id = $1
+            GROUP BY transaction_id, kind, order_id, invoice_id, payout_id
+        ),
+        running AS (
+            SELECT *, SUM(net) OVER (ORDER BY created_at, transaction_id) AS balance
+            FROM transactions
+        )
+        SELECT r.transaction_id, r.kind, r.order_id, i.document_number, p.reference AS payout_reference,
+               r.gross, r.commission, r.net, r.balance, r.created_at
+        FROM running r
+        LEFT JOIN invoices i ON i.id = r.invoice_id
+        LEFT JOIN payouts p ON p.id = r.payout_id
+        WHERE r.created_at >= $2::date::timestamp AT TIME ZONE 'UTC'
+          AND r.created_at < ($3::date + 1)::timestamp AT TIME ZONE 'UTC'
+        ORDER BY r.created_at, r.transaction_id
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch statement", e))?;
+
+    let opening_balance: BigDecimal = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(-SUM(amount), 0)
+        FROM ledger_entries
+        WHERE vendor_id = $1 AND account = 'vendor_payable'
+          AND created_at < $2::date::timestamp AT TIME ZONE 'UTC'
+        "#,
+    )
+    .bind(user_id)
+    .bind(from)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch statement", e))?;
+
+    let closing_balance = lines
+        .last()
+        .map(|line| line.balance.clone())
+        .unwrap_or_else(|| opening_balance.clone());
+
+    Ok(Json(Statement {
+        from,
+        to,
+        opening_balance,
+        closing_balance,
+        lines,
+    }))
+}
+
+/// The vendor's payouts, newest first
+pub async fn get_payouts(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Vec<Payout>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let payouts = sqlx::query_as::<_, Payout>(
+        r#"
+        SELECT id, vendor_id, reference, amount, payout_method, payout_account_last4, settlement_date, created_at
+        FROM payouts
+        WHERE vendor_id = $1
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch payouts", e))?;
+
+    Ok(Json(payouts))
+}
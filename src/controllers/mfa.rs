@@ -0,0 +1,711 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, Transaction};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth::issue_token, auth_guard::AuthUser},
+    jwt::KeyRing,
+    login_attempts, mfa,
+    models::Mfa::{
+        MfaChallenge, MfaCode, MfaEnrollment, MfaEnrollmentRequest, MfaLoginRequest, MfaLoginResponse,
+        RecoveryCodes,
+    },
+    tokens,
+};
+
+/// How long the second step of login can take
+const VERIFY_TTL_MINUTES: i64 = 5;
+/// Enrolling during login also means setting up an authenticator app
+const ENROLL_TTL_MINUTES: i64 = 15;
+/// Wrong codes allowed per login challenge before the password has to be entered again
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    message: String,
+}
+
+type ApiError = (StatusCode, Json<ErrorResponse>);
+
+fn error(status: StatusCode, message: &str) -> ApiError {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> ApiError {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+#[derive(FromRow)]
+struct MfaStatus {
+    enabled: Option<bool>,
+    required: bool,
+}
+
+async fn mfa_status(db: &PgPool, user_id: Uuid, role: &str) -> Result<MfaStatus, sqlx::Error> {
+    sqlx::query_as::<_, MfaStatus>(
+        r#"
+        SELECT (SELECT enabled_at IS NOT NULL FROM user_mfa WHERE user_id = $1) AS enabled,
+               COALESCE((SELECT required FROM mfa_policies WHERE role = $2), FALSE) AS required
+        "#,
+    )
+    .bind(user_id)
+    .bind(role)
+    .fetch_one(db)
+    .await
+}
+
+/// Called by login once the password checks out. Returns a challenge when the
+/// user has to enter a code, or enroll because their role requires it.
+pub async fn login_challenge(db: &PgPool, user_id: Uuid, role: &str) -> Result<Option<MfaChallenge>, sqlx::Error> {
+    let status = mfa_status(db, user_id, role).await?;
+    let (purpose, ttl_minutes) = match (status.enabled.unwrap_or(false), status.required) {
+        (true, _) => ("verify", VERIFY_TTL_MINUTES),
+        (false, true) => ("enroll", ENROLL_TTL_MINUTES),
+        (false, false) => return Ok(None),
+    };
+
+    let token = tokens::generate();
+    sqlx::query("INSERT INTO mfa_challenges (user_id, purpose, token_hash, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(purpose)
+        .bind(tokens::hash(&token))
+        .bind(Utc::now() + Duration::minutes(ttl_minutes))
+        .execute(db)
+        .await?;
+
+    Ok(Some(MfaChallenge {
+        mfa_required: true,
+        enrollment_required: purpose == "enroll",
+        mfa_token: token,
+        expires_in: ttl_minutes * 60,
+    }))
+}
+
+#[derive(FromRow)]
+struct TotpRow {
+    secret: String,
+    last_used_step: Option<i64>,
+}
+
+/// Check a TOTP code against the user's confirmed secret, or their pending one
+/// while enrolling. An accepted code can't be used again.
+async fn verify_totp(conn: &mut PgConnection, user_id: Uuid, code: &str, pending: bool) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query_as::<_, TotpRow>(
+        "SELECT secret, last_used_step FROM user_mfa WHERE user_id = $1 AND (enabled_at IS NULL) = $2 FOR UPDATE",
+    )
+    .bind(user_id)
+    .bind(pending)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(step) = row.and_then(|row| mfa::matching_step(&row.secret, code, row.last_used_step)) else {
+        return Ok(false);
+    };
+    sqlx::query("UPDATE user_mfa SET last_used_step = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(step)
+        .execute(&mut *conn)
+        .await?;
+    Ok(true)
+}
+
+/// Accept a TOTP code, or use up one of the recovery codes
+async fn verify_second_factor(conn: &mut PgConnection, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error> {
+    if verify_totp(conn, user_id, code, false).await? {
+        return Ok(true);
+    }
+    let used = sqlx::query(
+        r#"
+        UPDATE mfa_recovery_codes
+        SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+          AND EXISTS (SELECT 1 FROM user_mfa WHERE user_id = $1 AND enabled_at IS NOT NULL)
+        "#,
+    )
+    .bind(user_id)
+    .bind(mfa::hash_recovery_code(code))
+    .execute(&mut *conn)
+    .await?;
+    Ok(used.rows_affected() > 0)
+}
+
+/// Replace the user's recovery codes, returning the new plaintext codes
+async fn replace_recovery_codes(conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let codes = mfa::generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| mfa::hash_recovery_code(code)).collect();
+    sqlx::query("INSERT INTO mfa_recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::VARCHAR[])")
+        .bind(user_id)
+        .bind(&hashes)
+        .execute(&mut *conn)
+        .await?;
+    Ok(codes)
+}
+
+/// Turn on a pending enrollment whose first code has been confirmed. Sessions
+/// signed in with the password alone are signed out.
+async fn enable(conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query("UPDATE user_mfa SET enabled_at = NOW() WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE users SET session_version = session_version + 1 WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    replace_recovery_codes(conn, user_id).await
+}
+
+#[derive(FromRow)]
+struct EnrollmentCandidate {
+    email: String,
+    enabled: Option<bool>,
+}
+
+/// Store a new pending secret, replacing any earlier unconfirmed one
+async fn begin_enrollment(db: &PgPool, user_id: Uuid) -> Result<MfaEnrollment, ApiError> {
+    let user = sqlx::query_as::<_, EnrollmentCandidate>(
+        r#"
+        SELECT u.email, (SELECT enabled_at IS NOT NULL FROM user_mfa WHERE user_id = u.id) AS enabled
+        FROM users u
+        WHERE u.id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| internal_error("Failed to start enrollment", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+
+    if user.enabled.unwrap_or(false) {
+        return Err(error(StatusCode::CONFLICT, "Two-factor authentication is already enabled"));
+    }
+
+    let secret = mfa::generate_secret();
+    let otpauth_uri = mfa::provisioning_uri(&secret, &user.email)
+        .ok_or_else(|| error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create provisioning URI"))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO user_mfa (user_id, secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+        WHERE user_mfa.enabled_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(&secret)
+    .execute(db)
+    .await
+    .map_err(|e| internal_error("Failed to start enrollment", e))?;
+
+    Ok(MfaEnrollment { secret, otpauth_uri })
+}
+
+#[derive(FromRow)]
+struct Challenge {
+    id: Uuid,
+    user_id: Uuid,
+}
+
+/// Lock a live login challenge for the given purpose
+async fn find_challenge(conn: &mut PgConnection, token: &str, purpose: &str) -> Result<Challenge, ApiError> {
+    sqlx::query_as::<_, Challenge>(
+        r#"
+        SELECT id, user_id
+        FROM mfa_challenges
+        WHERE token_hash = $1 AND purpose = $2
+          AND used_at IS NULL AND expires_at > NOW() AND attempts < $3
+        FOR UPDATE
+        "#,
+    )
+    .bind(tokens::hash(token))
+    .bind(purpose)
+    .bind(MAX_CHALLENGE_ATTEMPTS)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| internal_error("Failed to check MFA token", e))?
+    .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Invalid or expired MFA token. Please log in again"))
+}
+
+/// Count a wrong code against the challenge and commit that, so the limit holds.
+/// It also counts as a failed login for the account.
+async fn reject_code(db: &PgPool, mut tx: Transaction<'_, Postgres>, challenge: &Challenge, ip: &str) -> ApiError {
+    let counted = sqlx::query("UPDATE mfa_challenges SET attempts = attempts + 1 WHERE id = $1")
+        .bind(challenge.id)
+        .execute(&mut *tx)
+        .await;
+    if let Err(e) = counted {
+        return internal_error("Failed to record MFA attempt", e);
+    }
+    if let Err(e) = tx.commit().await {
+        return internal_error("Failed to commit transaction", e);
+    }
+    record_failed_code(db, challenge.user_id, ip).await;
+
+    error(StatusCode::UNAUTHORIZED, "Invalid code")
+}
+
+async fn login_email(db: &PgPool, user_id: Uuid) -> Result<String, sqlx::Error> {
+    let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(db)
+        .await?;
+    Ok(login_attempts::normalize_email(&email))
+}
+
+/// Count a wrong code as a failed login for the account
+async fn record_failed_code(db: &PgPool, user_id: Uuid, ip: &str) {
+    let recorded = match login_email(db, user_id).await {
+        Ok(email) => login_attempts::record_failure(db, &email, Some(user_id), ip).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = recorded {
+        println!("Failed to record login attempt: {:?}", e);
+    }
+}
+
+/// Refuse codes for signed-in users while their failed logins are throttled,
+/// so codes can't be guessed from an existing session either
+async fn check_throttle(db: &PgPool, user_id: Uuid, ip: &str) -> Result<(), ApiError> {
+    let email = login_email(db, user_id).await
+        .map_err(|e| internal_error("Failed to check login attempts", e))?;
+    match login_attempts::retry_after(db, &email, ip).await {
+        Ok(None) => Ok(()),
+        Ok(Some(secs)) => Err(error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &format!("Too many failed attempts. Try again in {} seconds", secs),
+        )),
+        Err(e) => Err(internal_error("Failed to check login attempts", e)),
+    }
+}
+
+#[derive(FromRow)]
+struct SessionRow {
+    email: String,
+    role: String,
+    session_version: i32,
+}
+
+/// The session token a completed challenge stands in for
+struct CompletedLogin {
+    token: String,
+    email: String,
+}
+
+/// Record the successful login once the challenge's transaction has committed
+async fn record_login(db: &PgPool, login: &CompletedLogin, user_id: Uuid, ip: &str) {
+    let email = login_attempts::normalize_email(&login.email);
+    if let Err(e) = login_attempts::record_success(db, &email, user_id, ip).await {
+        println!("Failed to record login attempt: {:?}", e);
+    }
+}
+
+/// Use up the challenge and sign the session token it was standing in for
+async fn complete_challenge(
+    keys: &KeyRing,
+    conn: &mut PgConnection,
+    challenge: &Challenge,
+) -> Result<CompletedLogin, ApiError> {
+    sqlx::query("UPDATE mfa_challenges SET used_at = NOW() WHERE id = $1")
+        .bind(challenge.id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| internal_error("Failed to complete login", e))?;
+
+    let session = sqlx::query_as::<_, SessionRow>("SELECT email, role, session_version FROM users WHERE id = $1")
+        .bind(challenge.user_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| internal_error("Failed to complete login", e))?;
+
+    let token = issue_token(keys, challenge.user_id, &session.role, session.session_version)
+        .map_err(|message| error(StatusCode::INTERNAL_SERVER_ERROR, message))?;
+    Ok(CompletedLogin {
+        token,
+        email: session.email,
+    })
+}
+
+/// Second step of login: exchange the MFA token and a TOTP or recovery code for a session token
+pub async fn login_mfa(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<MfaLoginRequest>,
+) -> Result<Json<MfaLoginResponse>, ApiError> {
+    let ip = login_attempts::client_ip(&headers, addr);
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let challenge = find_challenge(&mut tx, &payload.mfa_token, "verify").await?;
+    let valid = verify_second_factor(&mut tx, challenge.user_id, &payload.code).await
+        .map_err(|e| internal_error("Failed to verify code", e))?;
+    if !valid {
+        return Err(reject_code(&state.db, tx, &challenge, &ip).await);
+    }
+
+    let login = complete_challenge(&state.keys, &mut tx, &challenge).await?;
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+    record_login(&state.db, &login, challenge.user_id, &ip).await;
+
+    Ok(Json(MfaLoginResponse {
+        token: login.token,
+        recovery_codes: None,
+    }))
+}
+
+/// Start the enrollment a role policy requires, using the MFA token from login
+pub async fn start_login_enrollment(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MfaEnrollmentRequest>,
+) -> Result<Json<MfaEnrollment>, ApiError> {
+    let mut conn = state.db.acquire().await
+        .map_err(|e| internal_error("Failed to start enrollment", e))?;
+    let challenge = find_challenge(&mut conn, &payload.mfa_token, "enroll").await?;
+    drop(conn);
+
+    begin_enrollment(&state.db, challenge.user_id).await.map(Json)
+}
+
+/// Confirm the enrollment started during login; returns the session token and recovery codes
+pub async fn confirm_login_enrollment(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<MfaLoginRequest>,
+) -> Result<Json<MfaLoginResponse>, ApiError> {
+    let ip = login_attempts::client_ip(&headers, addr);
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let challenge = find_challenge(&mut tx, &payload.mfa_token, "enroll").await?;
+    let valid = verify_totp(&mut tx, challenge.user_id, &payload.code, true).await
+        .map_err(|e| internal_error("Failed to verify code", e))?;
+    if !valid {
+        return Err(reject_code(&state.db, tx, &challenge, &ip).await);
+    }
+
+    let recovery_codes = enable(&mut tx, challenge.user_id).await
+        .map_err(|e| internal_error("Failed to enable two-factor authentication", e))?;
+    let login = complete_challenge(&state.keys, &mut tx, &challenge).await?;
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+    record_login(&state.db, &login, challenge.user_id, &ip).await;
+
+    Ok(Json(MfaLoginResponse {
+        token: login.token,
+        recovery_codes: Some(recovery_codes),
+    }))
+}
+
+/// Start enrolling the authenticated user; the secret takes effect once confirmed
+pub async fn start_enrollment(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<Json<MfaEnrollment>, ApiError> {
+    begin_enrollment(&state.db, auth_user.user_id).await.map(Json)
+}
+
+/// Confirm enrollment with a code from the authenticator app. Other sessions are
+/// signed out, so a session token for this one comes with the recovery codes.
+pub async fn confirm_enrollment(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Json(payload): Json<MfaCode>,
+) -> Result<Json<MfaLoginResponse>, ApiError> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let valid = verify_totp(&mut tx, auth_user.user_id, &payload.code, true).await
+        .map_err(|e| internal_error("Failed to verify code", e))?;
+    if !valid {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid code, or no enrollment in progress"));
+    }
+
+    let recovery_codes = enable(&mut tx, auth_user.user_id).await
+        .map_err(|e| internal_error("Failed to enable two-factor authentication", e))?;
+    let session_version: i32 = sqlx::query_scalar("SELECT session_version FROM users WHERE id = $1")
+        .bind(auth_user.user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to enable two-factor authentication", e))?;
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    let token = issue_token(&state.keys, auth_user.user_id, &auth_user.role, session_version)
+        .map_err(|message| error(StatusCode::INTERNAL_SERVER_ERROR, message))?;
+    Ok(Json(MfaLoginResponse {
+        token,
+        recovery_codes: Some(recovery_codes),
+    }))
+}
+
+/// Turn off two-factor authentication, unless the user's role requires it
+pub async fn disable_mfa(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    auth_user: AuthUser,
+    Json(payload): Json<MfaCode>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let ip = login_attempts::client_ip(&headers, addr);
+    let status = mfa_status(&state.db, auth_user.user_id, &auth_user.role).await
+        .map_err(|e| internal_error("Failed to disable two-factor authentication", e))?;
+    if !status.enabled.unwrap_or(false) {
+        return Err(error(StatusCode::CONFLICT, "Two-factor authentication is not enabled"));
+    }
+    if status.required {
+        return Err(error(StatusCode::FORBIDDEN, "Two-factor authentication is required for your role"));
+    }
+
+    check_throttle(&state.db, auth_user.user_id, &ip).await?;
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let valid = verify_second_factor(&mut tx, auth_user.user_id, &payload.code).await
+        .map_err(|e| internal_error("Failed to verify code", e))?;
+    if !valid {
+        drop(tx);
+        record_failed_code(&state.db, auth_user.user_id, &ip).await;
+        return Err(error(StatusCode::UNAUTHORIZED, "Invalid code"));
+    }
+
+    sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+        .bind(auth_user.user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to disable two-factor authentication", e))?;
+    sqlx::query("DELETE FROM user_mfa WHERE user_id = $1")
+        .bind(auth_user.user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to disable two-factor authentication", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(MessageResponse {
+        message: "Two-factor authentication disabled".into(),
+    }))
+}
+
+/// Replace the recovery codes, invalidating the old ones
+pub async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    auth_user: AuthUser,
+    Json(payload): Json<MfaCode>,
+) -> Result<Json<RecoveryCodes>, ApiError> {
+    let ip = login_attempts::client_ip(&headers, addr);
+    check_throttle(&state.db, auth_user.user_id, &ip).await?;
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let valid = verify_totp(&mut tx, auth_user.user_id, &payload.code, false).await
+        .map_err(|e| internal_error("Failed to verify code", e))?;
+    if !valid {
+        drop(tx);
+        record_failed_code(&state.db, auth_user.user_id, &ip).await;
+        return Err(error(StatusCode::UNAUTHORIZED, "Invalid code, or two-factor authentication is not enabled"));
+    }
+
+    let recovery_codes = replace_recovery_codes(&mut tx, auth_user.user_id).await
+        .map_err(|e| internal_error("Failed to generate recovery codes", e))?;
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::auth::Claims;
+    use crate::test_support::{app_state, insert_user};
+    use totp_rs::{Algorithm, Secret, TOTP};
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    /// The code an authenticator app shows now
+    fn current_code() -> String {
+        let bytes = Secret::Encoded(SECRET.into()).to_bytes().unwrap();
+        TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, bytes, None, String::new()).generate_current().unwrap()
+    }
+
+    fn wrong_code() -> String {
+        if current_code() == "000000" { "111111" } else { "000000" }.into()
+    }
+
+    fn client() -> (ConnectInfo<SocketAddr>, HeaderMap) {
+        (ConnectInfo(SocketAddr::from(([203, 0, 113, 7], 443))), HeaderMap::new())
+    }
+
+    /// A customer with two-factor authentication on, using `SECRET`
+    async fn mfa_user(pool: &PgPool) -> AuthUser {
+        let user_id = insert_user(pool, "alice", "customer").await;
+        sqlx::query("INSERT INTO user_mfa (user_id, secret, enabled_at) VALUES ($1, $2, NOW())")
+            .bind(user_id)
+            .bind(SECRET)
+            .execute(pool)
+            .await
+            .unwrap();
+        AuthUser { user_id, role: "customer".into() }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn challenge_is_refused_after_too_many_wrong_codes(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user = mfa_user(&pool).await;
+        let challenge = login_challenge(&pool, user.user_id, &user.role).await.unwrap().unwrap();
+        let attempt = |code: String| {
+            let (addr, headers) = client();
+            let payload = MfaLoginRequest { mfa_token: challenge.mfa_token.clone(), code };
+            login_mfa(State(state.clone()), addr, headers, Json(payload))
+        };
+
+        for _ in 0..MAX_CHALLENGE_ATTEMPTS {
+            let Err((status, _)) = attempt(wrong_code()).await else {
+                panic!("a wrong code was accepted");
+            };
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let Err((status, _)) = attempt(current_code()).await else {
+            panic!("the challenge was still usable after too many wrong codes");
+        };
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let failures: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM login_attempts WHERE user_id = $1 AND NOT succeeded")
+            .bind(user.user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(failures, MAX_CHALLENGE_ATTEMPTS as i64);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn challenge_accepts_the_current_code_once(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user = mfa_user(&pool).await;
+        let challenge = login_challenge(&pool, user.user_id, &user.role).await.unwrap().unwrap();
+        let attempt = || {
+            let (addr, headers) = client();
+            let payload = MfaLoginRequest { mfa_token: challenge.mfa_token.clone(), code: current_code() };
+            login_mfa(State(state.clone()), addr, headers, Json(payload))
+        };
+
+        let Json(login) = attempt().await.unwrap();
+        assert!(state.keys.verify::<Claims>(&login.token).is_ok());
+        let Err((status, _)) = attempt().await else {
+            panic!("the challenge was used twice");
+        };
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn disabling_is_throttled_after_wrong_codes(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user = mfa_user(&pool).await;
+        let disable = |code: String| {
+            let (addr, headers) = client();
+            let user = AuthUser { user_id: user.user_id, role: user.role.clone() };
+            disable_mfa(State(state.clone()), addr, headers, user, Json(MfaCode { code }))
+        };
+
+        for _ in 0..3 {
+            let Err((status, _)) = disable(wrong_code()).await else {
+                panic!("a wrong code disabled two-factor authentication");
+            };
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        // Even the right code has to wait now
+        let Err((status, _)) = disable(current_code()).await else {
+            panic!("disabling wasn't throttled");
+        };
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        let enabled: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM user_mfa WHERE user_id = $1)")
+            .bind(user.user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(enabled);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn regenerating_recovery_codes_is_throttled_after_wrong_codes(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user = mfa_user(&pool).await;
+        let regenerate = |code: String| {
+            let (addr, headers) = client();
+            let user = AuthUser { user_id: user.user_id, role: user.role.clone() };
+            regenerate_recovery_codes(State(state.clone()), addr, headers, user, Json(MfaCode { code }))
+        };
+
+        for _ in 0..3 {
+            let Err((status, _)) = regenerate(wrong_code()).await else {
+                panic!("a wrong code regenerated the recovery codes");
+            };
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let Err((status, _)) = regenerate(current_code()).await else {
+            panic!("regenerating wasn't throttled");
+        };
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn enabling_signs_out_other_sessions(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user_id = insert_user(&pool, "alice", "customer").await;
+        sqlx::query("INSERT INTO user_mfa (user_id, secret) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(SECRET)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let user = AuthUser { user_id, role: "customer".into() };
+        let Json(enabled) = confirm_enrollment(State(state.clone()), user, Json(MfaCode { code: current_code() }))
+            .await
+            .unwrap();
+
+        let session_version: i32 = sqlx::query_scalar("SELECT session_version FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(session_version, 1);
+        assert_eq!(state.keys.verify::<Claims>(&enabled.token).unwrap().ver, session_version);
+        assert_eq!(enabled.recovery_codes.map(|codes| codes.len()), Some(mfa::RECOVERY_CODE_COUNT));
+    }
+}
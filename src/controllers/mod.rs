@@ -1,10 +1,21 @@
+pub mod admin;
+pub mod analytics;
+pub mod api_keys;
 pub mod auth;
 pub mod auth_guard;
 pub mod cart;
+pub mod checkout;
+pub mod inventory;
+pub mod invoice;
+pub mod ledger;
+pub mod mfa;
 pub mod order;
+pub mod password;
 pub mod product;
-
-pub use auth::*;
-pub use cart::*;
-pub use order::*;
-pub use product::*;
\ No newline at end of file
+pub mod product_import;
+pub mod returns;
+pub mod shipping;
+pub mod users;
+pub mod vendors;
+pub mod verification;
+pub mod webhooks;
@@ -6,18 +6,27 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashSet;
 use std::sync::Arc;
 use uuid::Uuid;
 use bigdecimal::BigDecimal;
 
 use crate::{
     app_state::AppState,
-    controllers::auth_guard::AuthUser,
+    payment,
+    checkout,
+    invoice::{self, LineInput},
+    inventory::{self, Movement},
+    events::{self, DomainEvent},
+    controllers::{auth_guard::AuthUser, verification},
     models::Order::{
         Order, OrderItem, OrderDetails, OrderItemDetails, OrderSummary,
         CreateOrderRequest, UpdateOrderStatus, OrderCreationResponse,
-        CartItemWithProduct,
+        CartItemWithProduct, CancelOrderRequest, CancelOrderItemRequest,
+        OrderCancellationResponse,
     },
+    models::Shipping::{OrderShipping, ShippingProfile},
+    controllers::shipping::{vendor_cart_totals, vendor_shipping_cost},
 };
 
 #[derive(Deserialize)]
@@ -36,7 +45,7 @@ pub struct ErrorResponse {
 pub async fn get_all_orders(
     State(state): State<Arc<AppState>>,
     auth_user: AuthUser,
-    Query(params): Query<OrderQueryParams>,
+    Query(_params): Query<OrderQueryParams>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     
     
@@ -72,7 +81,7 @@ pub async fn get_all_orders(
 pub async fn create_order(
     State(state): State<Arc<AppState>>,
     auth_user: AuthUser,
-    Json(_payload): Json<CreateOrderRequest>,
+    Json(payload): Json<CreateOrderRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     
     // Only customers can create orders
@@ -85,6 +94,17 @@ pub async fn create_order(
         ));
     }
 
+    let unverified = verification::is_blocked(&state.db, auth_user.user_id, "orders").await
+        .map_err(|e| internal_error("Failed to check email verification", e))?;
+    if unverified {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Verify your email address before placing orders".into(),
+            }),
+        ));
+    }
+
     // Start transaction
     let mut tx = state.db.begin().await.map_err(|e| {
         println!("Failed to start transaction: {:?}", e);
@@ -96,16 +116,23 @@ pub async fn create_order(
         )
     })?;
 
-    // Get cart items with product details
-    let cart_items = sqlx::query_as::<_, CartItemWithProduct>(
+    // Get cart items with product details. Stock is what's left after other
+    // customers' checkouts, and none for archived products; the rows stay
+    // locked so it can't change before the decrement.
+    let cart_items = sqlx::query_as::<_, CartItemWithProduct>(&format!(
         r#"
         SELECT ci.product_id, ci.quantity, ci.user_id,
-               p.name as product_name, p.price, p.vendor_id, p.stock
+               p.name as product_name, p.price, p.vendor_id,
+               CASE WHEN p.archived_at IS NULL THEN p.stock - {} ELSE 0 END AS stock,
+               p.weight_kg, p.length_cm, p.width_cm, p.height_cm
         FROM cart_items ci
         JOIN products p ON ci.product_id = p.id
         WHERE ci.user_id = $1
+        ORDER BY ci.product_id
+        FOR UPDATE OF p
         "#,
-    )
+        checkout::held_stock("$1")
+    ))
     .bind(auth_user.user_id)
     .fetch_all(&mut *tx)
     .await
@@ -128,6 +155,30 @@ pub async fn create_order(
         ));
     }
 
+    // Items from stores suspended since they were added to the cart can't be ordered
+    let suspended = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT p.name
+        FROM cart_items ci
+        JOIN products p ON ci.product_id = p.id
+        JOIN vendor_profiles vp ON vp.vendor_id = p.vendor_id
+        WHERE ci.user_id = $1 AND vp.status = 'suspended'
+        LIMIT 1
+        "#,
+    )
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to check vendor status", e))?;
+    if let Some(product_name) = suspended {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: format!("{} is from a suspended store. Remove it from your cart to order", product_name),
+            }),
+        ));
+    }
+
     // Check stock availability and calculate total
     let mut total = BigDecimal::from(0);
     for item in &cart_items {
@@ -145,18 +196,70 @@ pub async fn create_order(
         total += &item.price * BigDecimal::from(item.quantity);
     }
 
+    // Price the shipping method chosen for each vendor group
+    let vendor_groups = vendor_cart_totals(&cart_items);
+    if let Some(selection) = payload.shipping.iter().find(|s| !vendor_groups.contains_key(&s.vendor_id)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("Cart has no items from vendor {}", selection.vendor_id),
+            }),
+        ));
+    }
+
+    let mut shipments: Vec<OrderShipping> = Vec::new();
+    let mut shipping_total = BigDecimal::from(0);
+    for (vendor_id, totals) in &vendor_groups {
+        let profiles = sqlx::query_as::<_, ShippingProfile>(
+            r#"
+            SELECT id, vendor_id, name, method, base_rate, per_kg_rate, free_threshold, created_at
+            FROM shipping_profiles
+            WHERE vendor_id = $1
+            "#,
+        )
+        .bind(vendor_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            println!("Failed to fetch shipping profiles: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to fetch shipping profiles".into(),
+                }),
+            )
+        })?;
+
+        let selected = payload.shipping.iter().find(|s| s.vendor_id == *vendor_id).map(|s| s.shipping_profile_id);
+        let Some((profile, cost)) = vendor_shipping_cost(*vendor_id, &profiles, selected, totals)
+            .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?
+        else {
+            continue;
+        };
+        shipping_total += &cost;
+        shipments.push(OrderShipping {
+            vendor_id: *vendor_id,
+            shipping_profile_id: Some(profile.id),
+            method_name: profile.name.clone(),
+            cost,
+        });
+    }
+    total += &shipping_total;
+
     // Create the order
     let order_id = Uuid::new_v4();
     let order = sqlx::query_as::<_, Order>(
         r#"
-        INSERT INTO orders (id, user_id, total, status)
-        VALUES ($1, $2, $3, 'pending')
-        RETURNING id, user_id, total, status, created_at
+        INSERT INTO orders (id, user_id, total, shipping_total, shipping_address, status)
+        VALUES ($1, $2, $3, $4, $5, 'pending')
+        RETURNING id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at
         "#,
     )
     .bind(order_id)
     .bind(auth_user.user_id)
     .bind(&total)
+    .bind(&shipping_total)
+    .bind(&payload.shipping_address)
     .fetch_one(&mut *tx)
     .await
     .map_err(|e| {
@@ -196,24 +299,61 @@ pub async fn create_order(
         })?;
 
         // Update product stock
+        let movement = Movement {
+            product_id: item.product_id,
+            change: -item.quantity,
+            reason: "sale",
+            order_id: Some(order_id),
+            ..Default::default()
+        };
+        inventory::apply(&mut tx, movement).await.map_err(|e| {
+            println!("Failed to update product stock: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to update product stock".into(),
+                }),
+            )
+        })?;
+    }
+
+    // Record the shipping method and cost for each vendor group
+    for shipment in &shipments {
         sqlx::query(
-            "UPDATE products SET stock = stock - $1 WHERE id = $2"
+            r#"
+            INSERT INTO order_shipping (order_id, vendor_id, shipping_profile_id, method_name, cost)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
         )
-        .bind(item.quantity)
-        .bind(item.product_id)
+        .bind(order.id)
+        .bind(shipment.vendor_id)
+        .bind(shipment.shipping_profile_id)
+        .bind(&shipment.method_name)
+        .bind(&shipment.cost)
         .execute(&mut *tx)
         .await
         .map_err(|e| {
-            println!("Failed to update product stock: {:?}", e);
+            println!("Failed to record order shipping: {:?}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse {
-                    error: "Failed to update product stock".into(),
+                    error: "Failed to record order shipping".into(),
                 }),
             )
         })?;
     }
 
+    // Issue one invoice per vendor
+    invoice::issue_order_invoices(&mut tx, order.id).await.map_err(|e| {
+        println!("Failed to issue invoices: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to issue invoices".into(),
+            }),
+        )
+    })?;
+
     // Clear the cart
     sqlx::query("DELETE FROM cart_items WHERE user_id = $1")
         .bind(auth_user.user_id)
@@ -229,6 +369,53 @@ pub async fn create_order(
             )
         })?;
 
+    // Simulate payment (always succeeds for now). A failed payment rolls the
+    // order back and gives the checkout's held stock back to other customers.
+    if let Err(e) = payment::charge(&mut tx, order.id, &order.total).await {
+        println!("Failed to record payment: {:?}", e);
+        drop(tx);
+        if let Err(e) = checkout::release(&*state.db, auth_user.user_id, "failed").await {
+            println!("Failed to release checkout: {:?}", e);
+        }
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to process payment".into(),
+            }),
+        ));
+    }
+
+    // The stock decrement above replaces the checkout's holds
+    checkout::complete(&mut tx, auth_user.user_id, order.id).await.map_err(|e| {
+        println!("Failed to complete checkout: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to create order".into(),
+            }),
+        )
+    })?;
+
+    // Announce the order once it commits (confirmation email, integrations)
+    let mut vendor_ids: Vec<Uuid> = cart_items.iter().map(|item| item.vendor_id).collect();
+    vendor_ids.sort();
+    vendor_ids.dedup();
+    let event = DomainEvent::OrderPlaced {
+        order_id: order.id,
+        user_id: auth_user.user_id,
+        total: order.total.clone(),
+        vendor_ids,
+    };
+    events::record(&mut tx, event).await.map_err(|e| {
+        println!("Failed to record order event: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to create order".into(),
+            }),
+        )
+    })?;
+
     // Commit transaction
     tx.commit().await.map_err(|e| {
         println!("Failed to commit transaction: {:?}", e);
@@ -240,12 +427,10 @@ pub async fn create_order(
         )
     })?;
 
-    // Simulate payment (always succeeds for now)
-    println!("Payment simulated successfully for order {}", order.id);
-
     let response = OrderCreationResponse {
         order_id: order.id,
         total: order.total,
+        shipping_total: order.shipping_total,
         status: order.status,
         message: "Order created successfully! Payment processed.".to_string(),
     };
@@ -264,7 +449,7 @@ pub async fn get_order_by_id(
     
     // First get the order
     let order = sqlx::query_as::<_, Order>(
-        "SELECT id, user_id, total, status, created_at FROM orders WHERE id = $1"
+        "SELECT id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at FROM orders WHERE id = $1"
     )
     .bind(order_id)
     .fetch_optional(&*state.db)
@@ -319,10 +504,10 @@ pub async fn get_order_by_id(
     }
 
     // Get order items with product details
-    let order_items = sqlx::query_as::<_, (Uuid, Uuid, String, Uuid, i32, BigDecimal)>(
+    let order_items = sqlx::query_as::<_, (Uuid, Uuid, String, Uuid, i32, i32, BigDecimal)>(
         r#"
         SELECT oi.id, oi.product_id, p.name as product_name, 
-               oi.vendor_id, oi.quantity, oi.price
+               oi.vendor_id, oi.quantity, oi.cancelled_quantity, oi.price
         FROM order_items oi
         JOIN products p ON oi.product_id = p.id
         WHERE oi.order_id = $1
@@ -344,127 +529,468 @@ pub async fn get_order_by_id(
 
     let items: Vec<OrderItemDetails> = order_items
         .into_iter()
-        .map(|(id, product_id, product_name, vendor_id, quantity, price)| {
-            let subtotal = &price * BigDecimal::from(quantity);
+        .map(|(id, product_id, product_name, vendor_id, quantity, cancelled_quantity, price)| {
+            let subtotal = &price * BigDecimal::from(quantity - cancelled_quantity);
             OrderItemDetails {
                 id,
                 product_id,
                 product_name,
                 vendor_id,
                 quantity,
+                cancelled_quantity,
                 price,
                 subtotal,
             }
         })
         .collect();
 
+    let shipping = sqlx::query_as::<_, OrderShipping>(
+        r#"
+        SELECT vendor_id, shipping_profile_id, method_name, cost
+        FROM order_shipping
+        WHERE order_id = $1
+        "#,
+    )
+    .bind(order_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| {
+        println!("Database error fetching order shipping: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch order shipping".into(),
+            }),
+        )
+    })?;
+
+    let mut conn = state.db.acquire().await.map_err(|e| {
+        println!("Failed to acquire connection: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch order refunds".into(),
+            }),
+        )
+    })?;
+    let refunded_total = payment::refunded_total(&mut conn, order_id).await.map_err(|e| {
+        println!("Database error fetching order refunds: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch order refunds".into(),
+            }),
+        )
+    })?;
+
     let order_details = OrderDetails {
         id: order.id,
         user_id: order.user_id,
         total: order.total,
+        shipping_total: order.shipping_total,
+        refunded_total,
         status: order.status,
+        cancellation_reason: order.cancellation_reason,
+        cancelled_at: order.cancelled_at,
         created_at: order.created_at,
         items,
+        shipping,
     };
 
     Ok(Json(order_details).into_response())
 }
 
-/// Delete order by ID (admin only - could be restricted further)
+/// Log a database error and turn it into a 500 response
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+/// Cancel a whole order (customer only, pending orders only)
+/// The order is kept with a "cancelled" status, stock is restored and
+/// whatever is still charged is refunded
+pub async fn cancel_order(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(order_id): Path<Uuid>,
+    payload: Option<Json<CancelOrderRequest>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    // The reason is optional, so the body may be left out entirely
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let response = cancel_whole_order(&state, &auth_user, order_id, payload.reason).await?;
+    Ok(Json(response).into_response())
+}
+
+/// Delete order by ID - kept for existing clients, cancels the order instead of deleting it
 pub async fn delete_order_by_id(
     State(state): State<Arc<AppState>>,
     auth_user: AuthUser,
     Path(order_id): Path<Uuid>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    
-    // Only allow customers to delete their own orders (and only if pending)
+    cancel_whole_order(&state, &auth_user, order_id, None).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn cancel_whole_order(
+    state: &AppState,
+    auth_user: &AuthUser,
+    order_id: Uuid,
+    reason: Option<String>,
+) -> Result<OrderCancellationResponse, (StatusCode, Json<ErrorResponse>)> {
     if auth_user.role != "customer" {
         return Err((
             StatusCode::FORBIDDEN,
             Json(ErrorResponse {
-                error: "Only customers can delete orders".into(),
+                error: "Only customers can cancel orders".into(),
             }),
         ));
     }
 
-    // Check if order exists and belongs to user
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
     let order = sqlx::query_as::<_, Order>(
-        "SELECT id, user_id, total, status, created_at FROM orders WHERE id = $1 AND user_id = $2"
+        r#"
+        SELECT id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at
+        FROM orders
+        WHERE id = $1 AND user_id = $2
+        FOR UPDATE
+        "#,
     )
     .bind(order_id)
     .bind(auth_user.user_id)
-    .fetch_optional(&*state.db)
+    .fetch_optional(&mut *tx)
     .await
-    .map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ErrorResponse {
-            error: "Failed to fetch order".into(),
-        }),
-    ))?
+    .map_err(|e| internal_error("Failed to fetch order", e))?
     .ok_or((
         StatusCode::NOT_FOUND,
         Json(ErrorResponse {
-            error: "Order not found or you don't have permission to delete it".into(),
+            error: "Order not found or you don't have permission to cancel it".into(),
         }),
     ))?;
 
-    // Only allow deletion of pending orders
     if order.status != "pending" {
         return Err((
             StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: format!("Cannot delete order with status '{}'. Only pending orders can be deleted", order.status),
+                error: format!("Cannot cancel order with status '{}'. Only pending orders can be cancelled", order.status),
             }),
         ));
     }
 
-    // Start transaction to restore stock and delete order
-    let mut tx = state.db.begin().await.map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
+    // Everything still active gets credited, plus shipping for the vendors it belongs to.
+    // A vendor whose last line was already cancelled had their shipping credited then.
+    let mut credit_lines = invoice::active_item_lines(&mut tx, order_id).await
+        .map_err(|e| internal_error("Failed to fetch order items", e))?;
+    let active_vendors: HashSet<Uuid> = credit_lines.iter().map(|(vendor_id, _)| *vendor_id).collect();
+    let shipping = invoice::shipping_lines(&mut tx, order_id, None).await
+        .map_err(|e| internal_error("Failed to fetch order shipping", e))?;
+    credit_lines.extend(shipping.into_iter().filter(|(vendor_id, _)| active_vendors.contains(vendor_id)));
+
+    // Restore stock for everything not already cancelled
+    let restock: Vec<(Uuid, i32)> = sqlx::query_as(
+        r#"
+        SELECT product_id, quantity - cancelled_quantity
+        FROM order_items
+        WHERE order_id = $1 AND cancelled_quantity < quantity
+        "#,
+    )
+    .bind(order_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to restore product stock", e))?;
+
+    for (product_id, change) in restock {
+        let movement = Movement {
+            product_id,
+            change,
+            reason: "cancellation",
+            order_id: Some(order_id),
+            ..Default::default()
+        };
+        inventory::apply(&mut tx, movement).await
+            .map_err(|e| internal_error("Failed to restore product stock", e))?;
+    }
+
+    sqlx::query("UPDATE order_items SET cancelled_quantity = quantity WHERE order_id = $1")
+        .bind(order_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to cancel order items", e))?;
+
+    let already_refunded = payment::refunded_total(&mut tx, order_id).await
+        .map_err(|e| internal_error("Failed to fetch order refunds", e))?;
+    let refund_amount = &order.total - already_refunded;
+
+    sqlx::query(
+        r#"
+        INSERT INTO order_cancellations (order_id, amount, reason, cancelled_by)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(order_id)
+    .bind(&refund_amount)
+    .bind(&reason)
+    .bind(auth_user.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to record cancellation", e))?;
+
+    sqlx::query(
+        r#"
+        UPDATE orders
+        SET status = 'cancelled', cancellation_reason = $2, cancelled_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(order_id)
+    .bind(&reason)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to cancel order", e))?;
+
+    let event = DomainEvent::OrderStatusChanged {
+        order_id,
+        user_id: order.user_id,
+        previous_status: order.status.clone(),
+        status: "cancelled".into(),
+    };
+    events::record(&mut tx, event).await
+        .map_err(|e| internal_error("Failed to record order event", e))?;
+
+    payment::refund(&mut tx, order_id, &refund_amount, reason.as_deref()).await
+        .map_err(|e| internal_error("Failed to process refund", e))?;
+
+    invoice::issue_credit_notes(&mut tx, order_id, credit_lines, reason.as_deref()).await
+        .map_err(|e| internal_error("Failed to issue credit notes", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(OrderCancellationResponse {
+        order_id,
+        status: "cancelled".into(),
+        refunded_amount: refund_amount,
+        message: "Order cancelled. Refund issued.".to_string(),
+    })
+}
+
+/// Cancel some or all units of one line item
+/// Customer: items in their own order
+/// Vendor: items from their own store
+pub async fn cancel_order_item(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path((order_id, item_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<CancelOrderItemRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let order = sqlx::query_as::<_, Order>(
+        r#"
+        SELECT id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at
+        FROM orders
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(order_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to fetch order", e))?
+    .ok_or((
+        StatusCode::NOT_FOUND,
         Json(ErrorResponse {
-            error: "Failed to start transaction".into(),
+            error: "Order not found".into(),
         }),
     ))?;
 
-    // Restore product stock
-    sqlx::query(
+    let item = sqlx::query_as::<_, OrderItem>(
         r#"
-        UPDATE products 
-        SET stock = stock + oi.quantity
-        FROM order_items oi
-        WHERE products.id = oi.product_id AND oi.order_id = $1
+        SELECT id, order_id, product_id, vendor_id, quantity, price, cancelled_quantity
+        FROM order_items
+        WHERE id = $1 AND order_id = $2
         "#,
     )
+    .bind(item_id)
     .bind(order_id)
-    .execute(&mut *tx)
+    .fetch_optional(&mut *tx)
     .await
-    .map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
+    .map_err(|e| internal_error("Failed to fetch order item", e))?
+    .ok_or((
+        StatusCode::NOT_FOUND,
         Json(ErrorResponse {
-            error: "Failed to restore product stock".into(),
+            error: "Order item not found".into(),
         }),
     ))?;
 
-    // Delete order (order_items will cascade)
-    sqlx::query("DELETE FROM orders WHERE id = $1")
+    let can_cancel = match auth_user.role.as_str() {
+        "customer" => order.user_id == auth_user.user_id,
+        "vendor" => item.vendor_id == auth_user.user_id,
+        _ => false,
+    };
+
+    if !can_cancel {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "You don't have permission to cancel this item".into(),
+            }),
+        ));
+    }
+
+    if order.status != "pending" {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: format!("Cannot cancel items of an order with status '{}'. Only pending orders can be changed", order.status),
+            }),
+        ));
+    }
+
+    let remaining = item.quantity - item.cancelled_quantity;
+    let quantity = payload.quantity.unwrap_or(remaining);
+    if remaining == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "This item has already been cancelled".into(),
+            }),
+        ));
+    }
+    if quantity <= 0 || quantity > remaining {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("Quantity must be between 1 and {}", remaining),
+            }),
+        ));
+    }
+
+    sqlx::query("UPDATE order_items SET cancelled_quantity = cancelled_quantity + $1 WHERE id = $2")
+        .bind(quantity)
+        .bind(item_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to cancel order item", e))?;
+
+    let movement = Movement {
+        product_id: item.product_id,
+        change: quantity,
+        reason: "cancellation",
+        order_id: Some(order_id),
+        ..Default::default()
+    };
+    inventory::apply(&mut tx, movement).await
+        .map_err(|e| internal_error("Failed to restore product stock", e))?;
+
+    let (active_items, vendor_active_items): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), COUNT(*) FILTER (WHERE vendor_id = $2)
+        FROM order_items
+        WHERE order_id = $1 AND cancelled_quantity < quantity
+        "#,
+    )
+    .bind(order_id)
+    .bind(item.vendor_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to fetch order items", e))?;
+
+    let product_name: String = sqlx::query_scalar("SELECT name FROM products WHERE id = $1")
+        .bind(item.product_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to fetch product", e))?;
+
+    let mut refund_amount = &item.price * BigDecimal::from(quantity);
+    let mut status = order.status;
+    let mut credit_lines = vec![(item.vendor_id, LineInput {
+        description: product_name,
+        quantity,
+        unit_price: item.price.clone(),
+    })];
+
+    // Nothing left for this vendor to ship: their shipping is refunded too.
+    // Other vendors' shipping was credited when their last line was cancelled.
+    if vendor_active_items == 0 {
+        let shipping = invoice::shipping_lines(&mut tx, order_id, Some(item.vendor_id)).await
+            .map_err(|e| internal_error("Failed to fetch order shipping", e))?;
+        for (_, line) in &shipping {
+            refund_amount += &line.unit_price;
+        }
+        credit_lines.extend(shipping);
+    }
+
+    // Nothing left to ship at all: refund everything still charged
+    if active_items == 0 {
+        let already_refunded = payment::refunded_total(&mut tx, order_id).await
+            .map_err(|e| internal_error("Failed to fetch order refunds", e))?;
+        refund_amount = &order.total - already_refunded;
+
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET status = 'cancelled', cancellation_reason = $2, cancelled_at = NOW()
+            WHERE id = $1
+            "#,
+        )
         .bind(order_id)
+        .bind(&payload.reason)
         .execute(&mut *tx)
         .await
-        .map_err(|_| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to delete order".into(),
-            }),
-        ))?;
+        .map_err(|e| internal_error("Failed to cancel order", e))?;
 
-    tx.commit().await.map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ErrorResponse {
-            error: "Failed to commit transaction".into(),
-        }),
-    ))?;
+        let event = DomainEvent::OrderStatusChanged {
+            order_id,
+            user_id: order.user_id,
+            previous_status: status,
+            status: "cancelled".into(),
+        };
+        events::record(&mut tx, event).await
+            .map_err(|e| internal_error("Failed to record order event", e))?;
+        status = "cancelled".into();
+    }
 
-    Ok(StatusCode::NO_CONTENT.into_response())
+    sqlx::query(
+        r#"
+        INSERT INTO order_cancellations (order_id, order_item_id, quantity, amount, reason, cancelled_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(order_id)
+    .bind(item_id)
+    .bind(quantity)
+    .bind(&refund_amount)
+    .bind(&payload.reason)
+    .bind(auth_user.user_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to record cancellation", e))?;
+
+    payment::refund(&mut tx, order_id, &refund_amount, payload.reason.as_deref()).await
+        .map_err(|e| internal_error("Failed to process refund", e))?;
+
+    invoice::issue_credit_notes(&mut tx, order_id, credit_lines, payload.reason.as_deref()).await
+        .map_err(|e| internal_error("Failed to issue credit notes", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    let response = OrderCancellationResponse {
+        order_id,
+        status,
+        refunded_amount: refund_amount,
+        message: format!("Cancelled {} unit(s). Refund issued.", quantity),
+    };
+
+    Ok(Json(response).into_response())
 }
 
 /// Update order by ID (status updates for vendors)
@@ -519,31 +1045,167 @@ pub async fn update_order_by_id(
         ));
     }
 
-    // Update order status
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let previous_status: String = sqlx::query_scalar("SELECT status FROM orders WHERE id = $1 FOR UPDATE")
+        .bind(order_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to fetch order", e))?;
+
+    // Cancelled orders are final
+    if previous_status == "cancelled" {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "Cancelled orders cannot be updated".into(),
+            }),
+        ));
+    }
+
     let updated_order = sqlx::query_as::<_, Order>(
         r#"
         UPDATE orders 
         SET status = $1
         WHERE id = $2
-        RETURNING id, user_id, total, status, created_at
+        RETURNING id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at
         "#,
     )
     .bind(&payload.status)
     .bind(order_id)
-    .fetch_optional(&*state.db)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ErrorResponse {
-            error: "Failed to update order status".into(),
-        }),
-    ))?
-    .ok_or((
-        StatusCode::NOT_FOUND,
-        Json(ErrorResponse {
-            error: "Order not found".into(),
-        }),
-    ))?;
+    .map_err(|e| internal_error("Failed to update order status", e))?;
+
+    if previous_status != updated_order.status {
+        let event = DomainEvent::OrderStatusChanged {
+            order_id,
+            user_id: updated_order.user_id,
+            previous_status,
+            status: updated_order.status.clone(),
+        };
+        events::record(&mut tx, event).await
+            .map_err(|e| internal_error("Failed to record order event", e))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
 
     Ok(Json(updated_order).into_response())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::{cart::add_cart_item, product::get_product_by_id};
+    use crate::test_support::{
+        app_state, insert_order_item, insert_user, insert_vendor_product, insert_vendor_profile,
+    };
+    use sqlx::PgPool;
+
+    /// Add one line of a new product, and the vendor's shipping, to an order
+    async fn insert_vendor_group(pool: &PgPool, order_id: Uuid, vendor_id: Uuid, price: &str, shipping: &str) -> Uuid {
+        sqlx::query(
+            "INSERT INTO order_shipping (order_id, vendor_id, method_name, cost) VALUES ($1, $2, 'Standard', $3::DECIMAL)",
+        )
+        .bind(order_id)
+        .bind(vendor_id)
+        .bind(shipping)
+        .execute(pool)
+        .await
+        .unwrap();
+        let product_id = insert_vendor_product(pool, vendor_id, price, 5).await;
+        insert_order_item(pool, order_id, product_id, 1).await
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn cancelling_after_a_vendor_was_cancelled_credits_only_what_is_refunded(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let customer_id = insert_user(&pool, "customer", "customer").await;
+        let first_vendor = insert_user(&pool, "first", "vendor").await;
+        let second_vendor = insert_user(&pool, "second", "vendor").await;
+        let order_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO orders (user_id, total, shipping_total) VALUES ($1, 45.00, 10.00) RETURNING id",
+        )
+        .bind(customer_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let first_item = insert_vendor_group(&pool, order_id, first_vendor, "20.00", "4.00").await;
+        insert_vendor_group(&pool, order_id, second_vendor, "15.00", "6.00").await;
+        let mut tx = pool.begin().await.unwrap();
+        payment::charge(&mut tx, order_id, &"45.00".parse().unwrap()).await.unwrap();
+        invoice::issue_order_invoices(&mut tx, order_id).await.unwrap();
+        tx.commit().await.unwrap();
+        let customer = || AuthUser { user_id: customer_id, role: "customer".into() };
+
+        let request = CancelOrderItemRequest { quantity: None, reason: None };
+        cancel_order_item(State(state.clone()), customer(), Path((order_id, first_item)), Json(request))
+            .await
+            .unwrap();
+        cancel_order(State(state), customer(), Path(order_id), None).await.unwrap();
+
+        let (credited, refunded): (BigDecimal, BigDecimal) = sqlx::query_as(
+            r#"
+            SELECT (SELECT SUM(total) FROM invoices WHERE order_id = $1 AND kind = 'credit_note'),
+                   (SELECT SUM(amount) FROM payments WHERE order_id = $1 AND kind = 'refund')
+            "#,
+        )
+        .bind(order_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(refunded, "45.00".parse().unwrap());
+        assert_eq!(credited, refunded);
+
+        // Each vendor's shipping is credited once
+        let shipping_credits: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM invoice_lines l
+            JOIN invoices i ON i.id = l.invoice_id
+            WHERE i.order_id = $1 AND i.kind = 'credit_note' AND l.description LIKE 'Shipping:%'
+            "#,
+        )
+        .bind(order_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(shipping_credits, 2);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn suspended_stores_products_cannot_be_bought(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let customer_id = insert_user(&pool, "customer", "customer").await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        insert_vendor_profile(&pool, vendor_id, "bikes").await;
+        let product_id = insert_vendor_product(&pool, vendor_id, "20.00", 5).await;
+        let customer = || AuthUser { user_id: customer_id, role: "customer".into() };
+        let Json(cart) = add_cart_item(State(state.clone()), customer(), Path(product_id)).await.unwrap();
+        assert_eq!(cart.len(), 1);
+
+        sqlx::query("UPDATE vendor_profiles SET status = 'suspended' WHERE vendor_id = $1")
+            .bind(vendor_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let Err((status, _)) = add_cart_item(State(state.clone()), customer(), Path(product_id)).await else {
+            panic!("a suspended store's product was added to the cart");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        let payload = CreateOrderRequest { shipping: vec![], shipping_address: None };
+        let Err((status, _)) = create_order(State(state.clone()), customer(), Json(payload)).await else {
+            panic!("a suspended store's product was ordered");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        let Err((status, _)) = get_product_by_id(Path(product_id), State(state)).await else {
+            panic!("a suspended store's product is shown");
+        };
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let orders: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM orders").fetch_one(&pool).await.unwrap();
+        assert_eq!(orders, 0);
+    }
+}
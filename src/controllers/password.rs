@@ -0,0 +1,348 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth::issue_token, auth_guard::AuthUser},
+    email_templates, jobs,
+    notifier::Recipient,
+    tokens,
+};
+
+const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+/// Minimum wait between reset emails for one account
+const RESET_COOLDOWN_SECS: f64 = 60.0;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// A fresh token replacing the ones invalidated by the change
+#[derive(Debug, Serialize)]
+pub struct ChangePasswordResponse {
+    message: String,
+    token: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn hash_password(password: &str) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| {
+            println!("Password hashing error: {:?}", e);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Internal error hashing password")
+        })
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+#[derive(sqlx::FromRow)]
+struct ResetCandidate {
+    id: Uuid,
+    username: String,
+    email: String,
+    last_sent_secs: Option<f64>,
+}
+
+/// Email a password reset code
+/// Always answers the same way so it can't be used to find out which emails are registered
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<(StatusCode, Json<MessageResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let accepted = (
+        StatusCode::ACCEPTED,
+        Json(MessageResponse {
+            message: "If an account exists for that email, a reset code has been sent".into(),
+        }),
+    );
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let user = sqlx::query_as::<_, ResetCandidate>(
+        r#"
+        SELECT u.id, u.username, u.email,
+               (SELECT EXTRACT(EPOCH FROM NOW() - MAX(t.created_at))::FLOAT8
+                FROM password_reset_tokens t WHERE t.user_id = u.id) AS last_sent_secs
+        FROM users u
+        WHERE u.email = $1
+        FOR UPDATE OF u
+        "#,
+    )
+    .bind(&payload.email)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to request password reset", e))?;
+
+    let Some(user) = user else {
+        return Ok(accepted);
+    };
+    // Quietly skip repeat requests so the inbox isn't flooded
+    if user.last_sent_secs.is_some_and(|secs| secs < RESET_COOLDOWN_SECS) {
+        return Ok(accepted);
+    }
+
+    let token = tokens::generate();
+    sqlx::query("INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+        .bind(user.id)
+        .bind(tokens::hash(&token))
+        .bind(Utc::now() + Duration::minutes(RESET_TOKEN_TTL_MINUTES))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to request password reset", e))?;
+
+    let message = email_templates::password_reset(
+        &Recipient {
+            username: user.username,
+            email: user.email,
+        },
+        &token,
+    );
+    let job_payload = serde_json::to_value(message).expect("EmailMessage serializes");
+    jobs::enqueue(&mut *tx, "email.send", job_payload, None).await
+        .map_err(|e| internal_error("Failed to request password reset", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(accepted)
+}
+
+/// Choose a new password with a code from the reset email
+/// Signs the account out everywhere
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let password_hash = hash_password(&payload.new_password)?;
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let user_id: Uuid = sqlx::query_scalar(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+    )
+    .bind(tokens::hash(&payload.token))
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to reset password", e))?
+    .ok_or_else(|| error(StatusCode::BAD_REQUEST, "Invalid or expired reset code"))?;
+
+    // Receiving the code also proves the user owns the email address
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = $2,
+            session_version = session_version + 1,
+            email_verified_at = COALESCE(email_verified_at, NOW())
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(&password_hash)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to reset password", e))?;
+
+    sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to reset password", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(MessageResponse {
+        message: "Password has been reset. Please log in with your new password".into(),
+    }))
+}
+
+#[derive(sqlx::FromRow)]
+struct PasswordRow {
+    password_hash: String,
+    role: String,
+}
+
+/// Change the password of the authenticated user
+/// Every existing session is signed out; the response carries a new token for this one
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<ChangePasswordResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let current = sqlx::query_as::<_, PasswordRow>("SELECT password_hash, role FROM users WHERE id = $1 FOR UPDATE")
+        .bind(auth_user.user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to change password", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+
+    if !verify_password(&payload.current_password, &current.password_hash) {
+        return Err(error(StatusCode::UNAUTHORIZED, "Current password is incorrect"));
+    }
+
+    let password_hash = hash_password(&payload.new_password)?;
+    let session_version: i32 = sqlx::query_scalar(
+        r#"
+        UPDATE users
+        SET password_hash = $2, session_version = session_version + 1
+        WHERE id = $1
+        RETURNING session_version
+        "#,
+    )
+    .bind(auth_user.user_id)
+    .bind(&password_hash)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to change password", e))?;
+
+    // Outstanding reset codes were requested for the old password
+    sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+        .bind(auth_user.user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to change password", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    let token = issue_token(&state.keys, auth_user.user_id, &current.role, session_version)
+        .map_err(|message| error(StatusCode::INTERNAL_SERVER_ERROR, message))?;
+
+    Ok(Json(ChangePasswordResponse {
+        message: "Password changed. Other sessions have been signed out".into(),
+        token,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, password_hash, register_alice, PASSWORD};
+    use sqlx::PgPool;
+
+    const NEW_PASSWORD: &str = "a different horse entirely";
+
+    /// Store a reset code for the user that expires `minutes` from now
+    async fn insert_reset_token(pool: &PgPool, user_id: Uuid, minutes: i64) -> String {
+        let token = tokens::generate();
+        sqlx::query("INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+            .bind(user_id)
+            .bind(tokens::hash(&token))
+            .bind(Utc::now() + Duration::minutes(minutes))
+            .execute(pool)
+            .await
+            .unwrap();
+        token
+    }
+
+    fn reset(token: &str) -> Json<ResetPasswordRequest> {
+        Json(ResetPasswordRequest {
+            token: token.into(),
+            new_password: NEW_PASSWORD.into(),
+        })
+    }
+
+    async fn session_version(pool: &PgPool, user_id: Uuid) -> i32 {
+        sqlx::query_scalar("SELECT session_version FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn a_reset_code_works_once_and_signs_out_everywhere(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user_id = register_alice(state.clone(), "customer").await;
+        let token = insert_reset_token(&pool, user_id, RESET_TOKEN_TTL_MINUTES).await;
+        let version = session_version(&pool, user_id).await;
+
+        let Json(reset_response) = reset_password(State(state.clone()), reset(&token)).await.unwrap();
+        assert!(reset_response.message.contains("log in"));
+        let hash = password_hash(&pool, "alice@example.com").await;
+        assert!(verify_password(NEW_PASSWORD, &hash));
+        assert!(!verify_password(PASSWORD, &hash));
+        assert_eq!(session_version(&pool, user_id).await, version + 1);
+
+        let (status, _) = reset_password(State(state), reset(&token)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(session_version(&pool, user_id).await, version + 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn an_expired_reset_code_is_refused(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user_id = register_alice(state.clone(), "customer").await;
+        let token = insert_reset_token(&pool, user_id, -1).await;
+        let version = session_version(&pool, user_id).await;
+
+        let (status, _) = reset_password(State(state), reset(&token)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(verify_password(PASSWORD, &password_hash(&pool, "alice@example.com").await));
+        assert_eq!(session_version(&pool, user_id).await, version);
+    }
+}
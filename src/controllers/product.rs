@@ -4,11 +4,17 @@ use axum::{
     Json,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
 use uuid::Uuid;
 use std::sync::Arc;
 use crate::{
     app_state::AppState,
-    models::Product::{Product, CreateProduct, UpdateProduct},
+    models::Product::{Product, ProductRow, ProductWithVendor, CreateProduct, UpdateProduct},
+    checkout,
+    jobs,
+    inventory::{self, Movement},
+    controllers::{vendors, verification},
 };
 use crate::controllers::auth_guard::AuthUser;
 
@@ -23,6 +29,11 @@ pub struct ProductQuery {
     pub offset: Option<i32>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeleteProductQuery {
+    pub purge: Option<bool>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     error: String,
@@ -44,70 +55,185 @@ pub async fn create_product(
         ));
     }
 
+    match verification::is_blocked(&state.db, user_id, "products").await {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(ErrorResponse {
+                    error: "Verify your email address before listing products.".into(),
+                }),
+            ));
+        }
+        Err(e) => {
+            eprintln!("Error while checking email verification: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to create product.".into(),
+                }),
+            ));
+        }
+    }
+
+    let approval_error = match vendors::vendor_status(&state.db, user_id).await {
+        Ok(Some(status)) if status == "approved" => None,
+        Ok(Some(status)) if status == "suspended" => Some("Your store is suspended and can't list products."),
+        Ok(Some(_)) => Some("Your store is waiting for approval. You can list products once an admin approves it."),
+        Ok(None) => Some("Submit your store profile at /vendors/me and wait for approval before listing products."),
+        Err(e) => {
+            eprintln!("Error while checking vendor approval: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to create product.".into(),
+                }),
+            ));
+        }
+    };
+    if let Some(message) = approval_error {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: message.into(),
+            }),
+        ));
+    }
+
     // Use actual authenticated user ID
     let vendor_id = user_id; 
 
+    let sku = payload.sku.as_deref().map(str::trim).filter(|sku| !sku.is_empty());
+    if let Some(sku) = sku {
+        match sku_taken(&state.db, vendor_id, sku, None).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(ErrorResponse {
+                        error: format!("You already have a product with SKU {}", sku),
+                    }),
+                ));
+            }
+            Err(e) => {
+                eprintln!("Error while checking SKU: {:?}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: "Failed to create product.".into(),
+                    }),
+                ));
+            }
+        }
+    }
     
     let query = sqlx::query_as!(
         Product,
         r#"
-        INSERT INTO products (id, vendor_id, name, description, price, stock, category, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), NOW())
-        RETURNING id, vendor_id, name, description, price, stock, category, created_at, updated_at
+        INSERT INTO products (id, vendor_id, sku, name, description, price, stock, category, weight_kg, length_cm, width_cm, height_cm, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW(), NOW())
+        RETURNING id, vendor_id, sku, name, description, price, stock, category, weight_kg, length_cm, width_cm, height_cm, created_at, updated_at, archived_at
         "#,
         Uuid::new_v4(),
         vendor_id,
+        sku,
         payload.name,
         payload.description,
         payload.price,
         payload.stock,
         payload.category,
+        payload.weight_kg,
+        payload.length_cm,
+        payload.width_cm,
+        payload.height_cm,
     );
     
-    match query.fetch_one(&*state.db).await {
-        Ok(product) => Ok((StatusCode::CREATED, Json(product))),
-        Err(err) => {
-            eprintln!("Error while creating product: {:?}", err);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to create product.".into(),
-                }),
-            ))
-        }
-    }
+    let create_failed = |err: sqlx::Error| {
+        eprintln!("Error while creating product: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to create product.".into(),
+            }),
+        )
+    };
+
+    // The starting stock is the first entry of the product's inventory history
+    let mut tx = state.db.begin().await.map_err(create_failed)?;
+    let product = query.fetch_one(&mut *tx).await.map_err(create_failed)?;
+    inventory::record_initial(&mut tx, product.id, product.stock, "initial", user_id).await.map_err(create_failed)?;
+    tx.commit().await.map_err(create_failed)?;
+
+    Ok((StatusCode::CREATED, Json(product)))
 }
 
-pub async fn get_all_products(
-    State(state): State<Arc<AppState>>,
-    Query(params): Query<ProductQuery>,
-) -> Result<Json<Vec<Product>>, (StatusCode, Json<ErrorResponse>)> {
-    println!("getting all product....");
-    let mut query_str = String::from("SELECT id, vendor_id, name, description, price, stock, category, created_at, updated_at FROM products WHERE 1=1");
+/// Whether the vendor has another product (not `except`) with this SKU
+pub async fn sku_taken(db: &PgPool, vendor_id: Uuid, sku: &str, except: Option<Uuid>) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM products WHERE vendor_id = $1 AND sku = $2 AND id IS DISTINCT FROM $3)",
+    )
+    .bind(vendor_id)
+    .bind(sku)
+    .bind(except)
+    .fetch_one(db)
+    .await
+}
+
+/// Product columns plus the vendor's store, for a query joining
+/// products `p` with vendor_profiles `vp`, and the stock not held by checkouts
+fn product_with_vendor_columns() -> String {
+    format!(
+        "p.id, p.vendor_id, p.sku, p.name, p.description, p.price, p.stock, p.category, \
+         p.weight_kg, p.length_cm, p.width_cm, p.height_cm, p.created_at, p.updated_at, p.archived_at, \
+         vp.store_name, vp.slug AS store_slug, vp.logo_url AS store_logo_url, \
+         GREATEST(p.stock - {}, 0) AS available_stock",
+        checkout::held_stock("NULL")
+    )
+}
+
+/// Catalog products matching the filters, optionally from one vendor only.
+/// Archived products and those of closed accounts and suspended stores are left out.
+pub async fn list_products(
+    db: &PgPool,
+    params: &ProductQuery,
+    vendor_id: Option<Uuid>,
+) -> Result<Vec<ProductWithVendor>, sqlx::Error> {
+    let mut query_str = format!(
+        "SELECT {} FROM products p LEFT JOIN vendor_profiles vp ON vp.vendor_id = p.vendor_id \
+         WHERE p.archived_at IS NULL \
+         AND p.vendor_id NOT IN (SELECT id FROM users WHERE deleted_at IS NOT NULL) \
+         AND vp.status IS DISTINCT FROM 'suspended'",
+        product_with_vendor_columns()
+    );
     let mut bind_count = 0;
     
     // Build dynamic WHERE clause
+    if vendor_id.is_some() {
+        bind_count += 1;
+        query_str.push_str(&format!(" AND p.vendor_id = ${}", bind_count));
+    }
+
     if params.search.is_some() {
         bind_count += 1;
-        query_str.push_str(&format!(" AND (name ILIKE ${} OR description ILIKE ${})", bind_count, bind_count));
+        query_str.push_str(&format!(" AND (p.name ILIKE ${} OR p.description ILIKE ${})", bind_count, bind_count));
     }
     
     if params.min_price.is_some() {
         bind_count += 1;
-        query_str.push_str(&format!(" AND price >= ${}", bind_count));
+        query_str.push_str(&format!(" AND p.price >= ${}", bind_count));
     }
     
     if params.max_price.is_some() {
         bind_count += 1;
-        query_str.push_str(&format!(" AND price <= ${}", bind_count));
+        query_str.push_str(&format!(" AND p.price <= ${}", bind_count));
     }
     
     if params.category.is_some() {
         bind_count += 1;
-        query_str.push_str(&format!(" AND category ILIKE ${}", bind_count));
+        query_str.push_str(&format!(" AND p.category ILIKE ${}", bind_count));
     }
     
-    query_str.push_str(" ORDER BY name");
+    query_str.push_str(" ORDER BY p.name");
     
     // Add pagination
     let limit = params.limit.unwrap_or(50);
@@ -118,7 +244,11 @@ pub async fn get_all_products(
     query_str.push_str(&format!(" OFFSET ${}", bind_count));
     
     // Build the query with dynamic binding
-    let mut query = sqlx::query_as::<_, Product>(&query_str);
+    let mut query = sqlx::query_as::<_, ProductRow>(&query_str);
+
+    if let Some(vendor_id) = vendor_id {
+        query = query.bind(vendor_id);
+    }
     
     if let Some(search) = &params.search {
         let search_term = format!("%{}%", search);
@@ -139,7 +269,16 @@ pub async fn get_all_products(
     
     query = query.bind(limit).bind(offset);
     
-    match query.fetch_all(&*state.db).await {
+    let rows = query.fetch_all(db).await?;
+    Ok(rows.into_iter().map(ProductWithVendor::from).collect())
+}
+
+pub async fn get_all_products(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ProductQuery>,
+) -> Result<Json<Vec<ProductWithVendor>>, (StatusCode, Json<ErrorResponse>)> {
+    println!("getting all product....");
+    match list_products(&state.db, &params, None).await {
         Ok(products) => Ok(Json(products)),
         Err(e) => {
             eprintln!("Error fetching products: {:?}", e);
@@ -156,22 +295,23 @@ pub async fn get_all_products(
 pub async fn get_product_by_id(
     Path(id): Path<Uuid>,
     State(state): State<Arc<AppState>>, 
-) -> Result<Json<Product>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ProductWithVendor>, (StatusCode, Json<ErrorResponse>)> {
     println!("Product ID requested: {}", id);
     println!("HIT get_product_by_id with id: {}", id);
 
-    let query = sqlx::query_as!(
-        Product,
+    let query_str = format!(
         r#"
-        SELECT id, vendor_id, name, description, price, stock, category, created_at, updated_at 
-        FROM products
-        WHERE id = $1
+        SELECT {}
+        FROM products p
+        LEFT JOIN vendor_profiles vp ON vp.vendor_id = p.vendor_id
+        WHERE p.id = $1 AND vp.status IS DISTINCT FROM 'suspended'
         "#,
-        id
+        product_with_vendor_columns()
     );
+    let query = sqlx::query_as::<_, ProductRow>(&query_str).bind(id);
     
     match query.fetch_optional(&*state.db).await {
-        Ok(Some(product)) => Ok(Json(product)),
+        Ok(Some(product)) => Ok(Json(ProductWithVendor::from(product))),
         Ok(None) => Err((
             StatusCode::NOT_FOUND,
             Json(ErrorResponse {
@@ -196,6 +336,62 @@ pub async fn update_product_by_id(
     State(state): State<Arc<AppState>>, 
     Json(payload): Json<UpdateProduct>,
 ) -> Result<Json<Product>, (StatusCode, Json<ErrorResponse>)> {
+    let update_failed = |e: sqlx::Error| {
+        eprintln!("Update error: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to update product.".into(),
+            }),
+        )
+    };
+
+    let vendor_id = owned_product_vendor(&state.db, id, user_id, &role).await?;
+    if payload.stock.is_some_and(|stock| stock < 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Stock can't be negative.".into(),
+            }),
+        ));
+    }
+
+    let sku = payload.sku.as_deref().map(str::trim).filter(|sku| !sku.is_empty());
+    // SKUs are unique per vendor, so check against the (now authorized) owner's products
+    if let Some(sku) = sku {
+        if sku_taken(&state.db, vendor_id, sku, Some(id)).await.map_err(update_failed)? {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    error: format!("This vendor already has a product with SKU {}", sku),
+                }),
+            ));
+        }
+    }
+
+    let mut tx = state.db.begin().await.map_err(update_failed)?;
+
+    // A new stock level is recorded as an adjustment; lock the row so the change
+    // is computed against the value it replaces
+    let previous_stock: Option<i32> = match payload.stock {
+        Some(_) => sqlx::query_scalar("SELECT stock FROM products WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(update_failed)?,
+        None => None,
+    };
+    if let (Some(stock), Some(previous_stock)) = (payload.stock, previous_stock) {
+        let movement = Movement {
+            product_id: id,
+            change: stock - previous_stock,
+            reason: "adjustment",
+            actor_id: Some(user_id),
+            ..Default::default()
+        };
+        inventory::apply(&mut tx, movement).await.map_err(update_failed)?;
+    }
+
     let query = sqlx::query_as!(
         Product,
         r#"
@@ -203,59 +399,104 @@ pub async fn update_product_by_id(
         SET name = COALESCE($1, name), 
             description = COALESCE($2, description), 
             price = COALESCE($3, price), 
-            stock = COALESCE($4, stock),
-            category = COALESCE($5, category),
+            category = COALESCE($4, category),
+            weight_kg = COALESCE($5, weight_kg),
+            length_cm = COALESCE($6, length_cm),
+            width_cm = COALESCE($7, width_cm),
+            height_cm = COALESCE($8, height_cm),
+            sku = COALESCE($10, sku),
             updated_at = NOW()
-        WHERE id = $6
-        RETURNING id, vendor_id, name, description, price, stock, category, created_at, updated_at
+        WHERE id = $9 AND archived_at IS NULL
+        RETURNING id, vendor_id, sku, name, description, price, stock, category, weight_kg, length_cm, width_cm, height_cm, created_at, updated_at, archived_at
         "#,
         payload.name,
         payload.description,
         payload.price,
-        payload.stock,
         payload.category,
-        id
+        payload.weight_kg,
+        payload.length_cm,
+        payload.width_cm,
+        payload.height_cm,
+        id,
+        sku
     );
     
-    match query.fetch_optional(&*state.db).await {
-        Ok(Some(product)) => Ok(Json(product)),
+    match query.fetch_optional(&mut *tx).await {
+        Ok(Some(product)) => {
+            tx.commit().await.map_err(update_failed)?;
+            Ok(Json(product))
+        }
+        // The product exists (its owner was checked), so it's archived
         Ok(None) => Err((
-            StatusCode::NOT_FOUND,
+            StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: "Product not found for update.".into(),
+                error: "Archived products can't be edited. Restore it first.".into(),
             }),
         )),
-        Err(e) => {
-            eprintln!("Update error: {:?}", e);
-            Err((
+        Err(e) => Err(update_failed(e)),
+    }
+}
+
+/// The product's vendor, if the caller is that vendor or an admin
+async fn owned_product_vendor(db: &PgPool, id: Uuid, user_id: Uuid, role: &str) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    let vendor_id: Option<Uuid> = sqlx::query_scalar("SELECT vendor_id FROM products WHERE id = $1")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| {
+            eprintln!("Error fetching product: {:?}", e);
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse {
-                    error: "Failed to update product.".into(),
+                    error: "Failed to fetch product.".into(),
                 }),
-            ))
-        }
+            )
+        })?;
+    match vendor_id {
+        Some(vendor_id) if vendor_id == user_id || role == "admin" => Ok(vendor_id),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Only the product's vendor can manage it.".into(),
+            }),
+        )),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Product not found.".into(),
+            }),
+        )),
     }
 }
 
+/// Archive the product: it leaves the catalog and can't be bought, but stays
+/// in order history and can be restored. With `?purge=true` it is deleted for
+/// good instead, which only works if it was never ordered.
 pub async fn delete_product_by_id(
     AuthUser { user_id, role }: AuthUser,
     Path(id): Path<Uuid>,
     State(state): State<Arc<AppState>>,  
+    Query(params): Query<DeleteProductQuery>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    let query = sqlx::query!(
-        r#"
-        DELETE FROM products
-        WHERE id = $1
-        "#,
-        id
-    );
+    owned_product_vendor(&state.db, id, user_id, &role).await?;
+
+    let query = if params.purge.unwrap_or(false) {
+        sqlx::query(
+            r#"
+            DELETE FROM products p
+            WHERE p.id = $1 AND NOT EXISTS (SELECT 1 FROM order_items oi WHERE oi.product_id = p.id)
+            "#,
+        )
+    } else {
+        sqlx::query("UPDATE products SET archived_at = COALESCE(archived_at, NOW()), updated_at = NOW() WHERE id = $1")
+    };
     
-    match query.execute(&*state.db).await {
+    match query.bind(id).execute(&*state.db).await {
         Ok(result) if result.rows_affected() > 0 => Ok(StatusCode::NO_CONTENT),
         Ok(_) => Err((
-            StatusCode::NOT_FOUND,
+            StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: "Product not found.".into(),
+                error: "This product has been ordered, so it can only be archived.".into(),
             }),
         )),
         Err(e) => {
@@ -268,4 +509,96 @@ pub async fn delete_product_by_id(
             ))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Put an archived product back in the catalog
+pub async fn restore_product(
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Product>, (StatusCode, Json<ErrorResponse>)> {
+    owned_product_vendor(&state.db, id, user_id, &role).await?;
+
+    let restore_failed = |e: sqlx::Error| {
+        eprintln!("Restore error: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to restore product.".into(),
+            }),
+        )
+    };
+
+    let mut tx = state.db.begin().await.map_err(restore_failed)?;
+    let product = sqlx::query_as!(
+        Product,
+        r#"
+        UPDATE products
+        SET archived_at = NULL, updated_at = NOW()
+        WHERE id = $1 AND archived_at IS NOT NULL
+        RETURNING id, vendor_id, sku, name, description, price, stock, category, weight_kg, length_cm, width_cm, height_cm, created_at, updated_at, archived_at
+        "#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(restore_failed)?
+    .ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "This product is not archived.".into(),
+            }),
+        )
+    })?;
+
+    // Customers who asked to be told about a restock while it was archived
+    if product.stock > 0 {
+        jobs::enqueue(&mut *tx, "inventory.back_in_stock", json!({ "product_id": id }), None)
+            .await
+            .map_err(restore_failed)?;
+    }
+    tx.commit().await.map_err(restore_failed)?;
+
+    Ok(Json(product))
+}
+
+/// Archived products, most recently archived first: the caller's own, or
+/// every vendor's for an admin
+pub async fn get_archived_products(
+    AuthUser { user_id, role }: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ProductWithVendor>>, (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" && role != "admin" {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Only vendors have archived products.".into(),
+            }),
+        ));
+    }
+    let vendor_id = if role == "admin" { None } else { Some(user_id) };
+
+    let query_str = format!(
+        r#"
+        SELECT {}
+        FROM products p
+        LEFT JOIN vendor_profiles vp ON vp.vendor_id = p.vendor_id
+        WHERE p.archived_at IS NOT NULL AND ($1::UUID IS NULL OR p.vendor_id = $1)
+        ORDER BY p.archived_at DESC
+        "#,
+        product_with_vendor_columns()
+    );
+    match sqlx::query_as::<_, ProductRow>(&query_str).bind(vendor_id).fetch_all(&*state.db).await {
+        Ok(rows) => Ok(Json(rows.into_iter().map(ProductWithVendor::from).collect())),
+        Err(e) => {
+            eprintln!("Error fetching archived products: {:?}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to fetch products.".into(),
+                }),
+            ))
+        }
+    }
+}
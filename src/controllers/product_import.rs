@@ -0,0 +1,245 @@
+//! Bulk product upload and download for vendors
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth_guard::AuthUser, vendors, verification},
+    jobs,
+    models::ProductImport::{ExportQuery, ImportQuery, ProductImport, ProductRecord},
+    product_import::{self, COLUMNS, IMPORT_COLUMNS, IMPORT_FORMATS, MAX_ROWS},
+};
+
+/// How many imports the history lists, newest first
+const IMPORT_HISTORY: i64 = 50;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors can import and export products"));
+    }
+    Ok(())
+}
+
+/// Importing creates products, so it needs the same approved store and
+/// verified email as creating them one by one
+async fn require_listing_allowed(state: &AppState, vendor_id: Uuid) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if verification::is_blocked(&state.db, vendor_id, "products").await
+        .map_err(|e| internal_error("Failed to import products", e))?
+    {
+        return Err(error(StatusCode::FORBIDDEN, "Verify your email address before listing products"));
+    }
+    match vendors::vendor_status(&state.db, vendor_id).await
+        .map_err(|e| internal_error("Failed to import products", e))?
+        .as_deref()
+    {
+        Some("approved") => Ok(()),
+        Some("suspended") => Err(error(StatusCode::FORBIDDEN, "Your store is suspended and can't list products")),
+        _ => Err(error(StatusCode::FORBIDDEN, "Your store must be approved before you can list products")),
+    }
+}
+
+/// Create or update products from a CSV or JSON file, matched by SKU. Small
+/// files are processed right away (200 with the report); larger ones are
+/// queued (202) and their report is at `GET /vendor/products/imports/:id`.
+pub async fn import_products(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<ProductImport>), (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let format = match params.format.as_deref() {
+        Some(format) => format.to_lowercase(),
+        None if content_type.contains("csv") => "csv".into(),
+        None if content_type.contains("json") => "json".into(),
+        None => {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                "Send the file as text/csv or application/json, or set format=csv or format=json",
+            ))
+        }
+    };
+    if !IMPORT_FORMATS.contains(&format.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid format. Valid formats are: {}", IMPORT_FORMATS.join(", ")),
+        ));
+    }
+    let dry_run = params.dry_run.unwrap_or(false);
+
+    require_listing_allowed(&state, user_id).await?;
+
+    let rows = product_import::parse(&format, &body).map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    if rows.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "The file has no products"));
+    }
+    if rows.len() > MAX_ROWS {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("A file can have at most {} products", MAX_ROWS),
+        ));
+    }
+    let queued = rows.len() > product_import::async_rows();
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let import = sqlx::query_as::<_, ProductImport>(&format!(
+        r#"
+        INSERT INTO product_imports (vendor_id, format, dry_run, payload, total_rows)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        IMPORT_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(&format)
+    .bind(dry_run)
+    .bind(if queued { Some(&body) } else { None })
+    .bind(rows.len() as i32)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to import products", e))?;
+
+    let (status, import) = if queued {
+        jobs::enqueue(&mut *tx, "products.import", json!({ "import_id": import.id }), None).await
+            .map_err(|e| internal_error("Failed to queue import", e))?;
+        (StatusCode::ACCEPTED, import)
+    } else {
+        let report = product_import::process(&mut tx, user_id, import.id, rows, dry_run).await
+            .map_err(|e| internal_error("Failed to import products", e))?;
+        let import = product_import::finish(&mut tx, import.id, report).await
+            .map_err(|e| internal_error("Failed to import products", e))?;
+        (StatusCode::OK, import)
+    };
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok((status, Json(import)))
+}
+
+/// The vendor's recent imports, newest first
+pub async fn get_imports(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Vec<ProductImport>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let imports = sqlx::query_as::<_, ProductImport>(&format!(
+        "SELECT {} FROM product_imports WHERE vendor_id = $1 ORDER BY created_at DESC LIMIT $2",
+        IMPORT_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(IMPORT_HISTORY)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch imports", e))?;
+
+    Ok(Json(imports))
+}
+
+/// One import with its per-row report once processed
+pub async fn get_import(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(import_id): Path<Uuid>,
+) -> Result<Json<ProductImport>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let import = sqlx::query_as::<_, ProductImport>(&format!(
+        "SELECT {} FROM product_imports WHERE id = $1 AND vendor_id = $2",
+        IMPORT_COLUMNS
+    ))
+    .bind(import_id)
+    .bind(user_id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch import", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Import not found"))?;
+
+    Ok(Json(import))
+}
+
+/// The vendor's products as a file the import accepts. Archived products are
+/// left out, since the import can't update them.
+pub async fn export_products(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Query(params): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    let format = params.format.as_deref().unwrap_or("csv").to_lowercase();
+    if !IMPORT_FORMATS.contains(&format.as_str()) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid format. Valid formats are: {}", IMPORT_FORMATS.join(", ")),
+        ));
+    }
+
+    let records = sqlx::query_as::<_, ProductRecord>(&format!(
+        "SELECT {} FROM products WHERE vendor_id = $1 AND archived_at IS NULL ORDER BY sku NULLS LAST, name",
+        COLUMNS.join(", ")
+    ))
+    .bind(user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to export products", e))?;
+
+    if format == "json" {
+        return Ok(Json(records).into_response());
+    }
+
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    let written = writer
+        .write_record(COLUMNS)
+        .and_then(|_| records.iter().try_for_each(|record| writer.serialize(record)));
+    let csv = written.map_err(|e| e.to_string()).and_then(|_| writer.into_inner().map_err(|e| e.to_string()));
+    let csv = csv.map_err(|e| {
+        println!("Failed to write product export: {:?}", e);
+        error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export products")
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"products.csv\""),
+        ],
+        csv,
+    )
+        .into_response())
+}
@@ -0,0 +1,523 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use sqlx::PgConnection;
+use std::collections::HashSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    payment,
+    invoice::{self, LineInput},
+    inventory::{self, Movement},
+    controllers::auth_guard::AuthUser,
+    models::{
+        Order::{Order, OrderItem},
+        Return::{
+            ApproveReturnRequest, CreateReturnRequest, RejectReturnRequest, ReturnDetails,
+            ReturnItemDetails, ReturnRequest, ShipReturnRequest,
+        },
+    },
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+/// Log a database error and turn it into a 500 response
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<ErrorResponse>) {
+    (status, Json(ErrorResponse { error: message.into() }))
+}
+
+async fn fetch_return(
+    conn: &mut PgConnection,
+    return_id: Uuid,
+) -> Result<ReturnRequest, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        SELECT id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+               approved_amount, refunded_at, created_at, updated_at
+        FROM returns
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(return_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch return", e))?
+    .ok_or(error(StatusCode::NOT_FOUND, "Return not found"))
+}
+
+async fn fetch_return_items(
+    conn: &mut PgConnection,
+    return_id: Uuid,
+) -> Result<Vec<ReturnItemDetails>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, ReturnItemDetails>(
+        r#"
+        SELECT ri.order_item_id, oi.product_id, p.name as product_name, ri.quantity, oi.price
+        FROM return_items ri
+        JOIN order_items oi ON ri.order_item_id = oi.id
+        JOIN products p ON oi.product_id = p.id
+        WHERE ri.return_id = $1
+        "#,
+    )
+    .bind(return_id)
+    .fetch_all(conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch return items", e))
+}
+
+/// Make sure the vendor reviewing a return owns it and it is in the expected state
+fn check_vendor_transition(
+    auth_user: &AuthUser,
+    request: &ReturnRequest,
+    allowed: &[&str],
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if auth_user.role != "vendor" || request.vendor_id != auth_user.user_id {
+        return Err(error(StatusCode::FORBIDDEN, "Only the vendor who sold these items can review this return"));
+    }
+    if !allowed.contains(&request.status.as_str()) {
+        return Err(error(
+            StatusCode::CONFLICT,
+            format!("Return is '{}' and cannot be changed this way", request.status),
+        ));
+    }
+    Ok(())
+}
+
+/// Open a return for items from a delivered order (customer only)
+/// All items must come from the same vendor
+pub async fn create_return(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Json(payload): Json<CreateReturnRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    if auth_user.role != "customer" {
+        return Err(error(StatusCode::FORBIDDEN, "Only customers can open returns"));
+    }
+    if payload.items.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Select at least one item to return"));
+    }
+    if payload.reason.trim().is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "A reason is required"));
+    }
+    let mut seen = HashSet::new();
+    if let Some(input) = payload.items.iter().find(|input| !seen.insert(input.order_item_id)) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            format!("Order item {} is listed more than once", input.order_item_id),
+        ));
+    }
+
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    // Locking the order serializes return requests for it, so two at once
+    // can't both count the same returnable units
+    let order = sqlx::query_as::<_, Order>(
+        r#"
+        SELECT id, user_id, total, shipping_total, status, cancellation_reason, cancelled_at, created_at
+        FROM orders
+        WHERE id = $1 AND user_id = $2
+        FOR UPDATE
+        "#,
+    )
+    .bind(payload.order_id)
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to fetch order", e))?
+    .ok_or(error(StatusCode::NOT_FOUND, "Order not found"))?;
+
+    if order.status != "delivered" {
+        return Err(error(
+            StatusCode::CONFLICT,
+            format!("Cannot return items from an order with status '{}'. Only delivered orders can be returned", order.status),
+        ));
+    }
+
+    let mut vendor_id: Option<Uuid> = None;
+    for input in &payload.items {
+        let item = sqlx::query_as::<_, OrderItem>(
+            r#"
+            SELECT id, order_id, product_id, vendor_id, quantity, price, cancelled_quantity
+            FROM order_items
+            WHERE id = $1 AND order_id = $2
+            "#,
+        )
+        .bind(input.order_item_id)
+        .bind(order.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to fetch order item", e))?
+        .ok_or(error(StatusCode::NOT_FOUND, format!("Order item {} not found in this order", input.order_item_id)))?;
+
+        if *vendor_id.get_or_insert(item.vendor_id) != item.vendor_id {
+            return Err(error(StatusCode::BAD_REQUEST, "Items from different vendors must be returned separately"));
+        }
+
+        // Units already in an open or completed return can't be returned twice
+        let already_returned: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(ri.quantity), 0)
+            FROM return_items ri
+            JOIN returns r ON ri.return_id = r.id
+            WHERE ri.order_item_id = $1 AND r.status <> 'rejected'
+            "#,
+        )
+        .bind(item.id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to fetch returned quantity", e))?;
+
+        let returnable = (item.quantity - item.cancelled_quantity) as i64 - already_returned;
+        if input.quantity <= 0 || input.quantity as i64 > returnable {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                format!("Can return between 1 and {} unit(s) of order item {}", returnable, item.id),
+            ));
+        }
+    }
+
+    let request = sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        INSERT INTO returns (order_id, user_id, vendor_id, reason)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+                  approved_amount, refunded_at, created_at, updated_at
+        "#,
+    )
+    .bind(order.id)
+    .bind(auth_user.user_id)
+    .bind(vendor_id)
+    .bind(&payload.reason)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to create return", e))?;
+
+    for input in &payload.items {
+        sqlx::query("INSERT INTO return_items (return_id, order_item_id, quantity) VALUES ($1, $2, $3)")
+            .bind(request.id)
+            .bind(input.order_item_id)
+            .bind(input.quantity)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| internal_error("Failed to create return item", e))?;
+    }
+
+    let items = fetch_return_items(&mut tx, request.id).await?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok((StatusCode::CREATED, Json(ReturnDetails { request, items })).into_response())
+}
+
+/// List returns
+/// Customer: returns they opened
+/// Vendor: returns for items they sold
+pub async fn get_all_returns(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let owner_column = match auth_user.role.as_str() {
+        "vendor" => "vendor_id",
+        _ => "user_id",
+    };
+
+    let returns = sqlx::query_as::<_, ReturnRequest>(&format!(
+        r#"
+        SELECT id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+               approved_amount, refunded_at, created_at, updated_at
+        FROM returns
+        WHERE {} = $1
+        ORDER BY created_at DESC
+        "#,
+        owner_column
+    ))
+    .bind(auth_user.user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch returns", e))?;
+
+    Ok(Json(returns).into_response())
+}
+
+/// Get a return with its items (the customer who opened it or the vendor reviewing it)
+pub async fn get_return_by_id(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(return_id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut conn = state.db.acquire().await
+        .map_err(|e| internal_error("Failed to fetch return", e))?;
+
+    let request = fetch_return(&mut conn, return_id).await?;
+    if request.user_id != auth_user.user_id && request.vendor_id != auth_user.user_id {
+        return Err(error(StatusCode::FORBIDDEN, "You don't have permission to view this return"));
+    }
+
+    let items = fetch_return_items(&mut conn, return_id).await?;
+    Ok(Json(ReturnDetails { request, items }).into_response())
+}
+
+/// Approve a requested return (vendor only)
+pub async fn approve_return(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(return_id): Path<Uuid>,
+    Json(payload): Json<ApproveReturnRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let request = fetch_return(&mut tx, return_id).await?;
+    check_vendor_transition(&auth_user, &request, &["requested"])?;
+
+    let items = fetch_return_items(&mut tx, return_id).await?;
+    let full_amount = items
+        .iter()
+        .fold(BigDecimal::from(0), |sum, item| sum + &item.price * BigDecimal::from(item.quantity));
+
+    let amount = payload.amount.unwrap_or_else(|| full_amount.clone());
+    if amount < BigDecimal::from(0) || amount > full_amount {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            format!("Refund amount must be between 0 and {}", full_amount),
+        ));
+    }
+
+    let request = sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        UPDATE returns
+        SET status = 'approved', approved_amount = $2, vendor_note = $3, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+                  approved_amount, refunded_at, created_at, updated_at
+        "#,
+    )
+    .bind(return_id)
+    .bind(&amount)
+    .bind(&payload.note)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to approve return", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(ReturnDetails { request, items }).into_response())
+}
+
+/// Reject a requested return (vendor only)
+pub async fn reject_return(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(return_id): Path<Uuid>,
+    Json(payload): Json<RejectReturnRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let request = fetch_return(&mut tx, return_id).await?;
+    check_vendor_transition(&auth_user, &request, &["requested"])?;
+
+    let request = sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        UPDATE returns
+        SET status = 'rejected', vendor_note = $2, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+                  approved_amount, refunded_at, created_at, updated_at
+        "#,
+    )
+    .bind(return_id)
+    .bind(&payload.note)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to reject return", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(request).into_response())
+}
+
+/// Mark an approved return as shipped back to the vendor (customer only)
+pub async fn ship_return(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(return_id): Path<Uuid>,
+    Json(payload): Json<ShipReturnRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let request = fetch_return(&mut tx, return_id).await?;
+    if request.user_id != auth_user.user_id {
+        return Err(error(StatusCode::FORBIDDEN, "Only the customer who opened this return can ship it"));
+    }
+    if request.status != "approved" {
+        return Err(error(
+            StatusCode::CONFLICT,
+            format!("Return is '{}'. Only approved returns can be shipped", request.status),
+        ));
+    }
+
+    let request = sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        UPDATE returns
+        SET status = 'shipped', tracking_number = $2, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+                  approved_amount, refunded_at, created_at, updated_at
+        "#,
+    )
+    .bind(return_id)
+    .bind(&payload.tracking_number)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to update return", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(request).into_response())
+}
+
+/// Confirm the returned items arrived (vendor only)
+/// Restocks the returned units and refunds the approved amount
+pub async fn receive_return(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+    Path(return_id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let request = fetch_return(&mut tx, return_id).await?;
+    check_vendor_transition(&auth_user, &request, &["approved", "shipped"])?;
+
+    let items = fetch_return_items(&mut tx, return_id).await?;
+    for item in &items {
+        let movement = Movement {
+            product_id: item.product_id,
+            change: item.quantity,
+            reason: "return",
+            order_id: Some(request.order_id),
+            return_id: Some(return_id),
+            ..Default::default()
+        };
+        inventory::apply(&mut tx, movement).await
+            .map_err(|e| internal_error("Failed to restock returned items", e))?;
+    }
+
+    let amount = request.approved_amount.clone().unwrap_or_else(|| BigDecimal::from(0));
+    let reason = format!("Return {}", return_id);
+    payment::refund(&mut tx, request.order_id, &amount, Some(&reason)).await
+        .map_err(|e| internal_error("Failed to process refund", e))?;
+
+    // Credit the returned items, or a single line if the vendor approved less than full price
+    let full_amount = items
+        .iter()
+        .fold(BigDecimal::from(0), |sum, item| sum + &item.price * BigDecimal::from(item.quantity));
+    let credit_lines = if amount == full_amount {
+        items
+            .iter()
+            .map(|item| (request.vendor_id, LineInput {
+                description: item.product_name.clone(),
+                quantity: item.quantity,
+                unit_price: item.price.clone(),
+            }))
+            .collect()
+    } else {
+        vec![(request.vendor_id, LineInput {
+            description: "Partial refund for returned items".into(),
+            quantity: 1,
+            unit_price: amount.clone(),
+        })]
+    };
+    invoice::issue_credit_notes(&mut tx, request.order_id, credit_lines, Some(&reason)).await
+        .map_err(|e| internal_error("Failed to issue credit notes", e))?;
+
+    let request = sqlx::query_as::<_, ReturnRequest>(
+        r#"
+        UPDATE returns
+        SET status = 'received', refunded_at = NOW(), updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, order_id, user_id, vendor_id, status, reason, vendor_note, tracking_number,
+                  approved_amount, refunded_at, created_at, updated_at
+        "#,
+    )
+    .bind(return_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to update return", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(ReturnDetails { request, items }).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::Return::ReturnItemInput,
+        test_support::{app_state, insert_order_item, insert_user, insert_vendor_product},
+    };
+    use sqlx::PgPool;
+
+    #[sqlx::test(migrations = false)]
+    async fn listing_an_item_twice_is_rejected(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let customer_id = insert_user(&pool, "customer", "customer").await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        let product_id = insert_vendor_product(&pool, vendor_id, "5.00", 0).await;
+        let order_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO orders (user_id, total, status) VALUES ($1, 10.00, 'delivered') RETURNING id",
+        )
+        .bind(customer_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let item_id = insert_order_item(&pool, order_id, product_id, 2).await;
+
+        // Each entry alone is within the 2 returnable units
+        let payload = CreateReturnRequest {
+            order_id,
+            reason: "Damaged".into(),
+            items: vec![
+                ReturnItemInput { order_item_id: item_id, quantity: 1 },
+                ReturnItemInput { order_item_id: item_id, quantity: 1 },
+            ],
+        };
+        let customer = AuthUser { user_id: customer_id, role: "customer".into() };
+        let Err((status, _)) = create_return(State(state), customer, Json(payload)).await else {
+            panic!("return with a duplicated item was accepted");
+        };
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let returns: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM returns").fetch_one(&pool).await.unwrap();
+        assert_eq!(returns, 0);
+    }
+}
@@ -0,0 +1,536 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    models::{
+        Order::CartItemWithProduct,
+        Shipping::{
+            CreateShippingProfile, ShippingOption, ShippingProfile, UpdateShippingProfile,
+            VendorShippingQuote, SHIPPING_METHODS,
+        },
+    },
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+/// Cubic centimetres per kilogram when pricing a parcel by its size
+const VOLUMETRIC_DIVISOR: i32 = 5000;
+
+/// Subtotal and total chargeable weight of the cart items sold by one vendor
+pub struct VendorCartTotals {
+    pub subtotal: BigDecimal,
+    pub weight_kg: BigDecimal,
+}
+
+/// Weight one unit is charged at: the larger of its actual weight and its
+/// volumetric weight, so light but bulky items aren't shipped at a loss.
+/// Products without all three dimensions are charged at their actual weight.
+fn chargeable_weight(item: &CartItemWithProduct) -> BigDecimal {
+    let actual = item.weight_kg.clone().unwrap_or_else(|| BigDecimal::from(0));
+    let volumetric = match (&item.length_cm, &item.width_cm, &item.height_cm) {
+        (Some(length), Some(width), Some(height)) => length * width * height / BigDecimal::from(VOLUMETRIC_DIVISOR),
+        _ => BigDecimal::from(0),
+    };
+    actual.max(volumetric)
+}
+
+/// Group cart items by vendor, summing price and chargeable weight for each group
+pub fn vendor_cart_totals(items: &[CartItemWithProduct]) -> BTreeMap<Uuid, VendorCartTotals> {
+    let mut groups: BTreeMap<Uuid, VendorCartTotals> = BTreeMap::new();
+    for item in items {
+        let quantity = BigDecimal::from(item.quantity);
+        let group = groups.entry(item.vendor_id).or_insert_with(|| VendorCartTotals {
+            subtotal: BigDecimal::from(0),
+            weight_kg: BigDecimal::from(0),
+        });
+        group.subtotal += &item.price * &quantity;
+        group.weight_kg += chargeable_weight(item) * &quantity;
+    }
+    groups
+}
+
+/// Shipping cost of a vendor group under the given profile
+pub fn calculate_shipping_cost(
+    profile: &ShippingProfile,
+    subtotal: &BigDecimal,
+    weight_kg: &BigDecimal,
+) -> BigDecimal {
+    match profile.method.as_str() {
+        "weight_based" => {
+            let per_kg = profile.per_kg_rate.clone().unwrap_or_else(|| BigDecimal::from(0));
+            (&profile.base_rate + per_kg * weight_kg).round(2)
+        }
+        "free_over_threshold" => match &profile.free_threshold {
+            Some(threshold) if subtotal >= threshold => BigDecimal::from(0),
+            _ => profile.base_rate.clone(),
+        },
+        _ => profile.base_rate.clone(),
+    }
+}
+
+/// The profile chosen for a vendor group and what it costs. Vendors without
+/// shipping profiles ship for free, so there's nothing to choose (None).
+pub fn vendor_shipping_cost<'a>(
+    vendor_id: Uuid,
+    profiles: &'a [ShippingProfile],
+    selected: Option<Uuid>,
+    totals: &VendorCartTotals,
+) -> Result<Option<(&'a ShippingProfile, BigDecimal)>, String> {
+    if profiles.is_empty() {
+        return Ok(None);
+    }
+    let selected = selected.ok_or_else(|| format!("Choose a shipping method for items from vendor {}", vendor_id))?;
+    let profile = profiles
+        .iter()
+        .find(|p| p.id == selected)
+        .ok_or_else(|| format!("Shipping method {} is not offered by vendor {}", selected, vendor_id))?;
+    Ok(Some((profile, calculate_shipping_cost(profile, &totals.subtotal, &totals.weight_kg))))
+}
+
+/// Check that a profile carries the rates its method needs
+fn validate_profile(
+    method: &str,
+    base_rate: &BigDecimal,
+    per_kg_rate: &Option<BigDecimal>,
+    free_threshold: &Option<BigDecimal>,
+) -> Result<(), String> {
+    if !SHIPPING_METHODS.contains(&method) {
+        return Err(format!(
+            "Invalid shipping method '{}'. Valid methods are: flat_rate, weight_based, free_over_threshold",
+            method
+        ));
+    }
+    let rates = [
+        ("base_rate", Some(base_rate)),
+        ("per_kg_rate", per_kg_rate.as_ref()),
+        ("free_threshold", free_threshold.as_ref()),
+    ];
+    for (field, value) in rates {
+        if value.is_some_and(|value| *value < BigDecimal::from(0)) {
+            return Err(format!("{} must not be negative", field));
+        }
+    }
+    if method == "weight_based" && per_kg_rate.is_none() {
+        return Err("per_kg_rate is required for weight_based shipping".into());
+    }
+    if method == "free_over_threshold" && free_threshold.is_none() {
+        return Err("free_threshold is required for free_over_threshold shipping".into());
+    }
+    Ok(())
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "Only vendors can manage shipping profiles".into(),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+/// Create a shipping profile for the authenticated vendor
+pub async fn create_shipping_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Json(payload): Json<CreateShippingProfile>,
+) -> Result<(StatusCode, Json<ShippingProfile>), (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    validate_profile(&payload.method, &payload.base_rate, &payload.per_kg_rate, &payload.free_threshold)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+
+    let profile = sqlx::query_as::<_, ShippingProfile>(
+        r#"
+        INSERT INTO shipping_profiles (vendor_id, name, method, base_rate, per_kg_rate, free_threshold)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, vendor_id, name, method, base_rate, per_kg_rate, free_threshold, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(&payload.name)
+    .bind(&payload.method)
+    .bind(&payload.base_rate)
+    .bind(&payload.per_kg_rate)
+    .bind(&payload.free_threshold)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Error while creating shipping profile: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to create shipping profile".into(),
+            }),
+        )
+    })?;
+
+    Ok((StatusCode::CREATED, Json(profile)))
+}
+
+/// List the authenticated vendor's shipping profiles
+pub async fn get_my_shipping_profiles(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Vec<ShippingProfile>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    fetch_vendor_profiles(&state, user_id).await.map(Json)
+}
+
+/// List the shipping profiles offered by a vendor (public)
+pub async fn get_vendor_shipping_profiles(
+    State(state): State<Arc<AppState>>,
+    Path(vendor_id): Path<Uuid>,
+) -> Result<Json<Vec<ShippingProfile>>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_vendor_profiles(&state, vendor_id).await.map(Json)
+}
+
+async fn fetch_vendor_profiles(
+    state: &AppState,
+    vendor_id: Uuid,
+) -> Result<Vec<ShippingProfile>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, ShippingProfile>(
+        r#"
+        SELECT id, vendor_id, name, method, base_rate, per_kg_rate, free_threshold, created_at
+        FROM shipping_profiles
+        WHERE vendor_id = $1
+        ORDER BY name
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching shipping profiles: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch shipping profiles".into(),
+            }),
+        )
+    })
+}
+
+/// Update one of the authenticated vendor's shipping profiles
+pub async fn update_shipping_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateShippingProfile>,
+) -> Result<Json<ShippingProfile>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let existing = sqlx::query_as::<_, ShippingProfile>(
+        r#"
+        SELECT id, vendor_id, name, method, base_rate, per_kg_rate, free_threshold, created_at
+        FROM shipping_profiles
+        WHERE id = $1 AND vendor_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Error fetching shipping profile: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch shipping profile".into(),
+            }),
+        )
+    })?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "Shipping profile not found".into(),
+        }),
+    ))?;
+
+    let name = payload.name.unwrap_or(existing.name);
+    let method = payload.method.unwrap_or(existing.method);
+    let base_rate = payload.base_rate.unwrap_or(existing.base_rate);
+    let per_kg_rate = payload.per_kg_rate.or(existing.per_kg_rate);
+    let free_threshold = payload.free_threshold.or(existing.free_threshold);
+
+    validate_profile(&method, &base_rate, &per_kg_rate, &free_threshold)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+
+    let profile = sqlx::query_as::<_, ShippingProfile>(
+        r#"
+        UPDATE shipping_profiles
+        SET name = $1, method = $2, base_rate = $3, per_kg_rate = $4, free_threshold = $5
+        WHERE id = $6
+        RETURNING id, vendor_id, name, method, base_rate, per_kg_rate, free_threshold, created_at
+        "#,
+    )
+    .bind(&name)
+    .bind(&method)
+    .bind(&base_rate)
+    .bind(&per_kg_rate)
+    .bind(&free_threshold)
+    .bind(id)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Error updating shipping profile: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to update shipping profile".into(),
+            }),
+        )
+    })?;
+
+    Ok(Json(profile))
+}
+
+/// Delete one of the authenticated vendor's shipping profiles
+pub async fn delete_shipping_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let result = sqlx::query("DELETE FROM shipping_profiles WHERE id = $1 AND vendor_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&*state.db)
+        .await
+        .map_err(|e| {
+            eprintln!("Error deleting shipping profile: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Failed to delete shipping profile".into(),
+                }),
+            )
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Shipping profile not found".into(),
+            }),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Quote shipping for the current cart, one group per vendor
+pub async fn get_cart_shipping_quote(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<Vec<VendorShippingQuote>>, (StatusCode, Json<ErrorResponse>)> {
+    let cart_items = sqlx::query_as::<_, CartItemWithProduct>(
+        r#"
+        SELECT ci.product_id, ci.quantity, ci.user_id,
+               p.name as product_name, p.price, p.vendor_id, p.stock,
+               p.weight_kg, p.length_cm, p.width_cm, p.height_cm
+        FROM cart_items ci
+        JOIN products p ON ci.product_id = p.id
+        WHERE ci.user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| {
+        eprintln!("Failed to fetch cart items: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Failed to fetch cart items".into(),
+            }),
+        )
+    })?;
+
+    let mut quotes = Vec::new();
+    for (vendor_id, totals) in vendor_cart_totals(&cart_items) {
+        let options = fetch_vendor_profiles(&state, vendor_id)
+            .await?
+            .into_iter()
+            .map(|profile| ShippingOption {
+                cost: calculate_shipping_cost(&profile, &totals.subtotal, &totals.weight_kg),
+                shipping_profile_id: profile.id,
+                name: profile.name,
+                method: profile.method,
+            })
+            .collect();
+
+        quotes.push(VendorShippingQuote {
+            vendor_id,
+            subtotal: totals.subtotal,
+            total_weight_kg: totals.weight_kg,
+            options,
+        });
+    }
+
+    Ok(Json(quotes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn profile(method: &str, base_rate: &str, per_kg_rate: Option<&str>, free_threshold: Option<&str>) -> ShippingProfile {
+        ShippingProfile {
+            id: Uuid::new_v4(),
+            vendor_id: Uuid::nil(),
+            name: method.into(),
+            method: method.into(),
+            base_rate: dec(base_rate),
+            per_kg_rate: per_kg_rate.map(dec),
+            free_threshold: free_threshold.map(dec),
+            created_at: None,
+        }
+    }
+
+    fn item(quantity: i32, price: &str, weight_kg: Option<&str>, dimensions_cm: Option<[&str; 3]>) -> CartItemWithProduct {
+        CartItemWithProduct {
+            product_id: Uuid::new_v4(),
+            quantity,
+            user_id: Uuid::nil(),
+            product_name: "Item".into(),
+            price: dec(price),
+            vendor_id: Uuid::nil(),
+            stock: 100,
+            weight_kg: weight_kg.map(dec),
+            length_cm: dimensions_cm.map(|d| dec(d[0])),
+            width_cm: dimensions_cm.map(|d| dec(d[1])),
+            height_cm: dimensions_cm.map(|d| dec(d[2])),
+        }
+    }
+
+    #[test]
+    fn negative_rates_are_rejected() {
+        let ok = validate_profile("weight_based", &dec("0"), &Some(dec("1.25")), &Some(dec("50.00")));
+        assert_eq!(ok, Ok(()));
+        let cases = [
+            ("base_rate", dec("-0.01"), Some(dec("1.25")), None),
+            ("per_kg_rate", dec("3.00"), Some(dec("-1")), None),
+            ("free_threshold", dec("3.00"), None, Some(dec("-50.00"))),
+        ];
+        for (field, base_rate, per_kg_rate, free_threshold) in cases {
+            assert_eq!(
+                validate_profile("flat_rate", &base_rate, &per_kg_rate, &free_threshold),
+                Err(format!("{} must not be negative", field))
+            );
+        }
+    }
+
+    #[test]
+    fn cost_per_method() {
+        let flat = profile("flat_rate", "4.99", None, None);
+        let by_weight = profile("weight_based", "3.00", Some("1.25"), None);
+        let free_over = profile("free_over_threshold", "5.00", None, Some("50.00"));
+        // (profile, subtotal, weight_kg, expected cost)
+        let cases = [
+            (&flat, "10.00", "0", "4.99"),
+            (&flat, "500.00", "30", "4.99"),
+            (&by_weight, "10.00", "0", "3.00"),
+            (&by_weight, "10.00", "2", "5.50"),
+            (&by_weight, "10.00", "0.333", "3.42"),
+            (&free_over, "49.99", "1", "5.00"),
+            (&free_over, "50.00", "1", "0"),
+            (&free_over, "80.00", "1", "0"),
+        ];
+        for (profile, subtotal, weight_kg, expected) in cases {
+            assert_eq!(
+                calculate_shipping_cost(profile, &dec(subtotal), &dec(weight_kg)),
+                dec(expected),
+                "{} for subtotal {} and {}kg",
+                profile.method,
+                subtotal,
+                weight_kg
+            );
+        }
+    }
+
+    #[test]
+    fn bulky_items_are_charged_by_volume() {
+        // (items, expected chargeable weight in kg)
+        let cases = [
+            // 2kg actual; 10x10x10cm is 0.2kg volumetric
+            (vec![item(1, "1", Some("2"), Some(["10", "10", "10"]))], "2"),
+            // 0.5kg actual; 50x40x30cm is 12kg volumetric
+            (vec![item(1, "1", Some("0.5"), Some(["50", "40", "30"]))], "12"),
+            // No weight, only dimensions
+            (vec![item(2, "1", None, Some(["50", "40", "30"]))], "24"),
+            // A missing dimension means no volumetric weight
+            (vec![item(3, "1", Some("1.5"), None)], "4.5"),
+            (vec![item(1, "1", None, None)], "0"),
+            (vec![item(1, "1", Some("1"), None), item(2, "1", Some("0.1"), Some(["20", "25", "40"]))], "9"),
+        ];
+        for (items, expected) in cases {
+            let totals = vendor_cart_totals(&items);
+            assert_eq!(totals[&Uuid::nil()].weight_kg, dec(expected));
+        }
+    }
+
+    #[test]
+    fn volumetric_weight_is_priced_by_weight_based_profiles() {
+        let by_weight = profile("weight_based", "2.00", Some("1.00"), None);
+        let by_weight_id = by_weight.id;
+        let items = [item(1, "20.00", Some("0.5"), Some(["50", "40", "30"]))];
+        let totals = &vendor_cart_totals(&items)[&Uuid::nil()];
+
+        let (_, cost) = vendor_shipping_cost(Uuid::nil(), &[by_weight], Some(by_weight_id), totals)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(totals.subtotal, dec("20.00"));
+        assert_eq!(cost, dec("14.00"));
+    }
+
+    #[test]
+    fn vendors_without_profiles_ship_for_free() {
+        let totals = VendorCartTotals {
+            subtotal: dec("10"),
+            weight_kg: dec("1"),
+        };
+        assert!(vendor_shipping_cost(Uuid::nil(), &[], None, &totals).unwrap().is_none());
+        assert!(vendor_shipping_cost(Uuid::nil(), &[], Some(Uuid::new_v4()), &totals).unwrap().is_none());
+    }
+
+    #[test]
+    fn vendors_with_profiles_need_one_of_theirs_chosen() {
+        let flat = profile("flat_rate", "4.99", None, None);
+        let totals = VendorCartTotals {
+            subtotal: dec("10"),
+            weight_kg: dec("1"),
+        };
+        let profiles = [flat.clone()];
+
+        let missing = vendor_shipping_cost(Uuid::nil(), &profiles, None, &totals).unwrap_err();
+        assert!(missing.starts_with("Choose a shipping method"), "{}", missing);
+        let unknown = vendor_shipping_cost(Uuid::nil(), &profiles, Some(Uuid::new_v4()), &totals).unwrap_err();
+        assert!(unknown.contains("is not offered by vendor"), "{}", unknown);
+
+        let (chosen, cost) = vendor_shipping_cost(Uuid::nil(), &profiles, Some(flat.id), &totals).unwrap().unwrap();
+        assert_eq!(chosen.id, flat.id);
+        assert_eq!(cost, dec("4.99"));
+    }
+}
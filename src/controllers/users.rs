@@ -0,0 +1,399 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Json,
+};
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth_guard::AuthUser, password::verify_password, verification},
+    inventory::{self, Movement},
+    ledger,
+    models::User::{
+        AccountStats, CustomerStats, Dashboard, DeleteAccount, UpdateProfile, UserProfile, VendorStats,
+    },
+    notifier::Recipient,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+async fn load_profile<'e>(executor: impl PgExecutor<'e>, user_id: Uuid) -> Result<Option<UserProfile>, sqlx::Error> {
+    sqlx::query_as::<_, UserProfile>(
+        r#"
+        SELECT u.id, u.username, u.email, u.display_name, u.role,
+               u.email_verified_at IS NOT NULL AS email_verified, u.pending_email,
+               EXISTS (SELECT 1 FROM user_mfa m WHERE m.user_id = u.id AND m.enabled_at IS NOT NULL) AS mfa_enabled,
+               u.created_at AT TIME ZONE 'UTC' AS created_at
+        FROM users u
+        WHERE u.id = $1 AND u.deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+}
+
+async fn account_stats(db: &PgPool, user_id: Uuid, role: &str) -> Result<AccountStats, sqlx::Error> {
+    if role == "vendor" {
+        let stats = sqlx::query_as::<_, VendorStats>(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM products WHERE vendor_id = $1 AND archived_at IS NULL) AS products,
+                (SELECT COUNT(*) FROM products WHERE vendor_id = $1 AND archived_at IS NULL AND stock = 0) AS out_of_stock_products,
+                (SELECT COUNT(DISTINCT o.id) FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                 WHERE oi.vendor_id = $1 AND o.status = 'pending' AND oi.quantity > oi.cancelled_quantity) AS open_orders,
+                (SELECT COALESCE(SUM(oi.quantity - oi.cancelled_quantity), 0)::BIGINT
+                 FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                 WHERE oi.vendor_id = $1 AND o.status <> 'cancelled') AS units_sold,
+                (SELECT COALESCE(SUM((oi.quantity - oi.cancelled_quantity) * oi.price), 0)
+                 FROM order_items oi JOIN orders o ON o.id = oi.order_id
+                 WHERE oi.vendor_id = $1 AND o.status <> 'cancelled') AS revenue
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(db)
+        .await?;
+        return Ok(AccountStats::Vendor(stats));
+    }
+
+    let stats = sqlx::query_as::<_, CustomerStats>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM orders WHERE user_id = $1) AS orders,
+            (SELECT COUNT(*) FROM orders WHERE user_id = $1 AND status IN ('pending', 'shipped')) AS open_orders,
+            (SELECT COALESCE(SUM(total), 0) FROM orders WHERE user_id = $1 AND status <> 'cancelled') AS total_spent,
+            (SELECT COALESCE(SUM(quantity), 0)::BIGINT FROM cart_items WHERE user_id = $1) AS cart_items
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+    Ok(AccountStats::Customer(stats))
+}
+
+/// Get the authenticated user's profile
+pub async fn get_me(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, .. }: AuthUser,
+) -> Result<Json<UserProfile>, (StatusCode, Json<ErrorResponse>)> {
+    let profile = load_profile(&*state.db, user_id)
+        .await
+        .map_err(|e| internal_error("Failed to fetch profile", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+
+    Ok(Json(profile))
+}
+
+/// The profile plus order, cart or sales stats for the user's role
+pub async fn dashboard(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Dashboard>, (StatusCode, Json<ErrorResponse>)> {
+    let profile = load_profile(&*state.db, user_id)
+        .await
+        .map_err(|e| internal_error("Failed to fetch dashboard", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+    let stats = account_stats(&state.db, user_id, &role)
+        .await
+        .map_err(|e| internal_error("Failed to fetch dashboard", e))?;
+
+    Ok(Json(Dashboard { profile, stats }))
+}
+
+#[derive(sqlx::FromRow)]
+struct Account {
+    username: String,
+    email: String,
+    password_hash: String,
+    pending_email: Option<String>,
+}
+
+async fn lock_account(
+    conn: &mut sqlx::PgConnection,
+    user_id: Uuid,
+) -> Result<Account, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, Account>(
+        "SELECT username, email, password_hash, pending_email FROM users WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+    )
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| internal_error("Failed to fetch user", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))
+}
+
+/// Update the username, display name or email
+/// A new email needs the current password and only replaces the old one once
+/// the link sent to it is opened; until then it is shown as `pending_email`
+pub async fn update_me(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<UpdateProfile>,
+) -> Result<Json<UserProfile>, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+    let account = lock_account(&mut tx, user_id).await?;
+    let mut username = account.username.clone();
+
+    if let Some(new_username) = &payload.username {
+        let new_username = new_username.trim();
+        if new_username.is_empty() || new_username.len() > 255 {
+            return Err(error(StatusCode::BAD_REQUEST, "username must be between 1 and 255 characters"));
+        }
+        sqlx::query("UPDATE users SET username = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(new_username)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                if e.to_string().contains("duplicate key value") {
+                    error(StatusCode::CONFLICT, "Username already taken")
+                } else {
+                    internal_error("Failed to update profile", e)
+                }
+            })?;
+        username = new_username.to_string();
+    }
+
+    if let Some(display_name) = &payload.display_name {
+        let display_name = display_name.trim();
+        if display_name.len() > 100 {
+            return Err(error(StatusCode::BAD_REQUEST, "display_name must be at most 100 characters"));
+        }
+        sqlx::query("UPDATE users SET display_name = NULLIF($2, '') WHERE id = $1")
+            .bind(user_id)
+            .bind(display_name)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| internal_error("Failed to update profile", e))?;
+    }
+
+    if let Some(email) = &payload.email {
+        let email = email.trim();
+        if email == account.email {
+            // Asking for the current address cancels a pending change
+            sqlx::query("UPDATE users SET pending_email = NULL WHERE id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| internal_error("Failed to update profile", e))?;
+        } else if account.pending_email.as_deref() != Some(email) {
+            if !email.contains('@') || email.len() > 255 {
+                return Err(error(StatusCode::BAD_REQUEST, "Invalid email address"));
+            }
+            let password = payload.current_password.as_deref().unwrap_or_default();
+            if !verify_password(password, &account.password_hash) {
+                return Err(error(StatusCode::UNAUTHORIZED, "Current password is incorrect"));
+            }
+            let taken: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)")
+                .bind(email)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| internal_error("Failed to update profile", e))?;
+            if taken {
+                return Err(error(StatusCode::CONFLICT, "Email already registered"));
+            }
+
+            sqlx::query("UPDATE users SET pending_email = $2 WHERE id = $1")
+                .bind(user_id)
+                .bind(email)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| internal_error("Failed to update profile", e))?;
+            let recipient = Recipient {
+                username,
+                email: email.to_string(),
+            };
+            verification::send_verification_email(&mut tx, user_id, &recipient).await
+                .map_err(|e| internal_error("Failed to send verification email", e))?;
+        }
+    }
+
+    let profile = load_profile(&mut *tx, user_id)
+        .await
+        .map_err(|e| internal_error("Failed to fetch profile", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(profile))
+}
+
+#[derive(sqlx::FromRow)]
+struct OpenActivity {
+    open_orders: i64,
+    open_sales: i64,
+    open_returns: i64,
+}
+
+/// Close the authenticated user's account
+/// Refused while the user has orders, sales or returns in progress. Order
+/// history has to outlive the account, so the user row is anonymized rather
+/// than deleted: the email and username are replaced, the password can no
+/// longer be used and every session ends. A vendor's products that were never
+/// ordered are deleted; the rest are kept for past orders with no stock.
+pub async fn delete_me(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Json(payload): Json<DeleteAccount>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+    let account = lock_account(&mut tx, user_id).await?;
+
+    if !verify_password(&payload.password, &account.password_hash) {
+        return Err(error(StatusCode::UNAUTHORIZED, "Password is incorrect"));
+    }
+
+    let activity = sqlx::query_as::<_, OpenActivity>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM orders WHERE user_id = $1 AND status IN ('pending', 'shipped')) AS open_orders,
+            (SELECT COUNT(DISTINCT o.id) FROM order_items oi JOIN orders o ON o.id = oi.order_id
+             WHERE oi.vendor_id = $1 AND o.status IN ('pending', 'shipped')
+               AND oi.quantity > oi.cancelled_quantity) AS open_sales,
+            (SELECT COUNT(*) FROM returns
+             WHERE (user_id = $1 OR vendor_id = $1) AND status IN ('requested', 'approved', 'shipped')) AS open_returns
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to delete account", e))?;
+
+    if activity.open_orders > 0 {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "You have orders that haven't been delivered yet. Wait for them or cancel them first",
+        ));
+    }
+    if activity.open_sales > 0 {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "You have orders to fulfil. Ship or cancel them before closing the account",
+        ));
+    }
+    if activity.open_returns > 0 {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "You have returns in progress. Close them before closing the account",
+        ));
+    }
+
+    // Payouts go to the details on the vendor profile, which is deleted below
+    let owed = ledger::vendor_balance(&mut *tx, user_id).await
+        .map_err(|e| internal_error("Failed to delete account", e))?;
+    if owed.balance > BigDecimal::from(0) {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "Your store still has an unpaid balance. Wait for it to be paid out before closing the account",
+        ));
+    }
+
+    let cleanup = [
+        "DELETE FROM products p WHERE p.vendor_id = $1 AND NOT EXISTS (SELECT 1 FROM order_items oi WHERE oi.product_id = p.id)",
+        "UPDATE products SET archived_at = NOW() WHERE vendor_id = $1 AND archived_at IS NULL",
+        "DELETE FROM cart_items WHERE user_id = $1",
+        "UPDATE checkout_sessions SET status = 'released', ended_at = NOW() WHERE user_id = $1 AND status = 'active'",
+        "DELETE FROM stock_subscriptions WHERE user_id = $1",
+        "DELETE FROM shipping_profiles WHERE vendor_id = $1",
+        "DELETE FROM vendor_profiles WHERE vendor_id = $1",
+        "DELETE FROM webhook_endpoints WHERE vendor_id = $1",
+        "DELETE FROM api_keys WHERE vendor_id = $1",
+        "DELETE FROM user_mfa WHERE user_id = $1",
+        "DELETE FROM mfa_recovery_codes WHERE user_id = $1",
+        "DELETE FROM mfa_challenges WHERE user_id = $1",
+        "DELETE FROM email_verification_tokens WHERE user_id = $1",
+        "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        r#"
+        UPDATE users
+        SET username = 'deleted-' || id,
+            email = 'deleted-' || id || '@deleted.invalid',
+            password_hash = '!',
+            display_name = NULL,
+            pending_email = NULL,
+            email_verified_at = NULL,
+            session_version = session_version + 1,
+            deleted_at = NOW()
+        WHERE id = $1
+        "#,
+    ];
+    for statement in cleanup {
+        sqlx::query(statement)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| internal_error("Failed to delete account", e))?;
+    }
+
+    // Products that were ordered stay for order history, but can't be bought any more
+    let stocked: Vec<(Uuid, i32)> = sqlx::query_as("SELECT id, stock FROM products WHERE vendor_id = $1 AND stock > 0")
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to delete account", e))?;
+    for (product_id, stock) in stocked {
+        let movement = Movement {
+            product_id,
+            change: -stock,
+            reason: "adjustment",
+            actor_id: Some(user_id),
+            note: Some("Account closed".into()),
+            ..Default::default()
+        };
+        inventory::apply(&mut tx, movement).await
+            .map_err(|e| internal_error("Failed to delete account", e))?;
+    }
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    println!("Closed account {}", user_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_state, assert_no_hash_material, password_hash, register_alice, response_body};
+
+    #[sqlx::test(migrations = false)]
+    async fn profile_responses_have_no_hash_material(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let user_id = register_alice(state.clone(), "vendor").await;
+        let password_hash = password_hash(&pool, "alice@example.com").await;
+
+        let me = get_me(State(state.clone()), AuthUser { user_id, role: "vendor".into() }).await;
+        let me = response_body(me).await;
+        let dashboard = dashboard(State(state), AuthUser { user_id, role: "vendor".into() }).await;
+        let dashboard = response_body(dashboard).await;
+
+        for body in [me, dashboard] {
+            assert!(body.contains("alice@example.com"), "unexpected profile response: {}", body);
+            assert_no_hash_material(&body, &password_hash);
+        }
+    }
+}
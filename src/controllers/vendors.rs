@@ -0,0 +1,368 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{auth_guard::AuthUser, product::{list_products, ProductQuery}},
+    models::Product::ProductWithVendor,
+    models::Vendor::{
+        CreateVendorRating, UpsertVendorProfile, VendorProfile, VendorRating, VendorStorefront, PAYOUT_METHODS,
+    },
+};
+
+/// Profile columns, for a query that aliases vendor_profiles as `p`
+pub const VENDOR_PROFILE_COLUMNS: &str = "p.vendor_id, p.store_name, p.slug, p.description, p.logo_url, \
+     p.contact_email, p.contact_phone, p.payout_method, RIGHT(p.payout_account, 4) AS payout_account_last4, \
+     p.status, p.status_reason, p.reviewed_at, p.created_at, p.updated_at";
+
+/// Slugs that would clash with fixed routes under /vendors
+const RESERVED_SLUGS: [&str; 1] = ["me"];
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors have a store profile"));
+    }
+    Ok(())
+}
+
+/// The vendor's review state, or None if they haven't submitted a profile
+pub async fn vendor_status(db: &PgPool, vendor_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT status FROM vendor_profiles WHERE vendor_id = $1")
+        .bind(vendor_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// "Bob's Bikes & Co." becomes "bob-s-bikes-co"
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(50).collect();
+    slug.trim_end_matches('-').to_string()
+}
+
+fn validate_slug(slug: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let valid_chars = slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if slug.len() < 3 || slug.len() > 50 || !valid_chars || slug.starts_with('-') || slug.ends_with('-') {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "slug must be 3 to 50 lowercase letters, digits or hyphens",
+        ));
+    }
+    if RESERVED_SLUGS.contains(&slug) {
+        return Err(error(StatusCode::BAD_REQUEST, "That slug is reserved"));
+    }
+    Ok(())
+}
+
+/// Trimmed optional text, with empty strings treated as absent
+fn optional(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// The authenticated vendor's store profile and approval state
+pub async fn get_my_vendor_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<VendorProfile>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let profile = sqlx::query_as::<_, VendorProfile>(&format!(
+        "SELECT {} FROM vendor_profiles p WHERE p.vendor_id = $1",
+        VENDOR_PROFILE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch store profile", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "You haven't submitted a store profile yet"))?;
+
+    Ok(Json(profile))
+}
+
+/// Submit the store profile for review, or update it
+/// A new profile starts as pending until an admin approves it; updates keep
+/// the current status
+pub async fn upsert_my_vendor_profile(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Json(payload): Json<UpsertVendorProfile>,
+) -> Result<Json<VendorProfile>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let store_name = payload.store_name.trim();
+    if store_name.is_empty() || store_name.len() > 100 {
+        return Err(error(StatusCode::BAD_REQUEST, "store_name must be between 1 and 100 characters"));
+    }
+    let slug = match optional(&payload.slug) {
+        Some(slug) => slug.to_string(),
+        None => slugify(store_name),
+    };
+    validate_slug(&slug)?;
+
+    let description = optional(&payload.description);
+    if description.is_some_and(|d| d.len() > 2000) {
+        return Err(error(StatusCode::BAD_REQUEST, "description must be at most 2000 characters"));
+    }
+    let logo_url = optional(&payload.logo_url);
+    if logo_url.is_some_and(|url| !(url.starts_with("https://") || url.starts_with("http://")) || url.len() > 500) {
+        return Err(error(StatusCode::BAD_REQUEST, "logo_url must be an http(s) URL of at most 500 characters"));
+    }
+    let contact_email = optional(&payload.contact_email);
+    if contact_email.is_some_and(|email| !email.contains('@') || email.len() > 255) {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid contact_email"));
+    }
+    let contact_phone = optional(&payload.contact_phone);
+    if contact_phone.is_some_and(|phone| phone.len() > 50) {
+        return Err(error(StatusCode::BAD_REQUEST, "contact_phone must be at most 50 characters"));
+    }
+    let payout_method = optional(&payload.payout_method);
+    if let Some(method) = payout_method {
+        if !PAYOUT_METHODS.contains(&method) {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid payout_method. Valid methods are: {}", PAYOUT_METHODS.join(", ")),
+            ));
+        }
+    }
+    let payout_account = optional(&payload.payout_account);
+    if payout_account.is_some_and(|account| account.len() < 4 || account.len() > 255) {
+        return Err(error(StatusCode::BAD_REQUEST, "payout_account must be between 4 and 255 characters"));
+    }
+    if payout_account.is_some() && payout_method.is_none() {
+        return Err(error(StatusCode::BAD_REQUEST, "Set payout_method along with payout_account"));
+    }
+
+    let profile = sqlx::query_as::<_, VendorProfile>(&format!(
+        r#"
+        INSERT INTO vendor_profiles AS p (vendor_id, store_name, slug, description, logo_url, contact_email,
+                                          contact_phone, payout_method, payout_account)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (vendor_id) DO UPDATE
+        SET store_name = EXCLUDED.store_name,
+            slug = EXCLUDED.slug,
+            description = EXCLUDED.description,
+            logo_url = EXCLUDED.logo_url,
+            contact_email = EXCLUDED.contact_email,
+            contact_phone = EXCLUDED.contact_phone,
+            payout_method = EXCLUDED.payout_method,
+            payout_account = CASE WHEN EXCLUDED.payout_account IS NOT NULL THEN EXCLUDED.payout_account
+                                  WHEN EXCLUDED.payout_method = p.payout_method THEN p.payout_account END,
+            updated_at = NOW()
+        RETURNING {}
+        "#,
+        VENDOR_PROFILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(store_name)
+    .bind(&slug)
+    .bind(description)
+    .bind(logo_url)
+    .bind(contact_email)
+    .bind(contact_phone)
+    .bind(payout_method)
+    .bind(payout_account)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("duplicate key value") {
+            error(StatusCode::CONFLICT, "That slug is already taken")
+        } else {
+            internal_error("Failed to save store profile", e)
+        }
+    })?;
+
+    Ok(Json(profile))
+}
+
+/// The approved store with this slug; pending and suspended stores aren't public
+async fn storefront_vendor_id(db: &PgPool, slug: &str) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar("SELECT vendor_id FROM vendor_profiles WHERE slug = $1 AND status = 'approved'")
+        .bind(slug)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| internal_error("Failed to fetch store", e))?
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Store not found"))
+}
+
+/// A vendor's public store page with its product count and rating
+pub async fn get_storefront(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Result<Json<VendorStorefront>, (StatusCode, Json<ErrorResponse>)> {
+    let storefront = sqlx::query_as::<_, VendorStorefront>(
+        r#"
+        SELECT p.vendor_id, p.store_name, p.slug, p.description, p.logo_url, p.contact_email, p.contact_phone,
+               u.id, u.username, u.display_name,
+               (SELECT COUNT(*) FROM products pr WHERE pr.vendor_id = p.vendor_id AND pr.archived_at IS NULL) AS product_count,
+               (SELECT ROUND(AVG(r.rating), 2)::FLOAT8 FROM vendor_ratings r WHERE r.vendor_id = p.vendor_id) AS rating,
+               (SELECT COUNT(*) FROM vendor_ratings r WHERE r.vendor_id = p.vendor_id) AS rating_count,
+               p.created_at
+        FROM vendor_profiles p
+        JOIN users u ON u.id = p.vendor_id
+        WHERE p.slug = $1 AND p.status = 'approved'
+        "#,
+    )
+    .bind(&slug)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch store", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Store not found"))?;
+
+    Ok(Json(storefront))
+}
+
+/// A vendor's products, with the same filters as `GET /products`
+pub async fn get_storefront_products(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    Query(params): Query<ProductQuery>,
+) -> Result<Json<Vec<ProductWithVendor>>, (StatusCode, Json<ErrorResponse>)> {
+    let vendor_id = storefront_vendor_id(&state.db, &slug).await?;
+
+    let products = list_products(&state.db, &params, Some(vendor_id))
+        .await
+        .map_err(|e| internal_error("Failed to fetch products", e))?;
+
+    Ok(Json(products))
+}
+
+/// A store's ratings, newest first
+pub async fn get_vendor_ratings(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Result<Json<Vec<VendorRating>>, (StatusCode, Json<ErrorResponse>)> {
+    let vendor_id = storefront_vendor_id(&state.db, &slug).await?;
+
+    let ratings = sqlx::query_as::<_, VendorRating>(
+        r#"
+        SELECT id, vendor_id, order_id, rating, comment, created_at
+        FROM vendor_ratings
+        WHERE vendor_id = $1
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#,
+    )
+    .bind(vendor_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch ratings", e))?;
+
+    Ok(Json(ratings))
+}
+
+/// Rate a store from 1 to 5, once per delivered order that included its products
+pub async fn rate_vendor(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, .. }: AuthUser,
+    Path(slug): Path<String>,
+    Json(payload): Json<CreateVendorRating>,
+) -> Result<(StatusCode, Json<VendorRating>), (StatusCode, Json<ErrorResponse>)> {
+    if !(1..=5).contains(&payload.rating) {
+        return Err(error(StatusCode::BAD_REQUEST, "rating must be between 1 and 5"));
+    }
+    let comment = optional(&payload.comment);
+    if comment.is_some_and(|c| c.len() > 2000) {
+        return Err(error(StatusCode::BAD_REQUEST, "comment must be at most 2000 characters"));
+    }
+    let vendor_id = storefront_vendor_id(&state.db, &slug).await?;
+
+    let eligible: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM orders o
+            JOIN order_items oi ON oi.order_id = o.id
+            WHERE o.id = $1 AND o.user_id = $2 AND o.status = 'delivered' AND oi.vendor_id = $3
+        )
+        "#,
+    )
+    .bind(payload.order_id)
+    .bind(user_id)
+    .bind(vendor_id)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to rate store", e))?;
+    if !eligible {
+        return Err(error(
+            StatusCode::FORBIDDEN,
+            "You can only rate a store for your delivered orders that included its products",
+        ));
+    }
+
+    let rating = sqlx::query_as::<_, VendorRating>(
+        r#"
+        INSERT INTO vendor_ratings (vendor_id, user_id, order_id, rating, comment)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, vendor_id, order_id, rating, comment, created_at
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(user_id)
+    .bind(payload.order_id)
+    .bind(payload.rating)
+    .bind(comment)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("duplicate key value") {
+            error(StatusCode::CONFLICT, "You already rated this store for this order")
+        } else {
+            internal_error("Failed to rate store", e)
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(rating)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{
+        app_state, assert_no_hash_material, insert_vendor_profile, password_hash, register_alice, response_body,
+    };
+
+    #[sqlx::test(migrations = false)]
+    async fn storefront_has_no_hash_material(pool: PgPool) {
+        let state = app_state(pool.clone()).await;
+        let vendor_id = register_alice(state.clone(), "vendor").await;
+        insert_vendor_profile(&pool, vendor_id, "alices").await;
+
+        let storefront = response_body(get_storefront(State(state), Path("alices".into())).await).await;
+
+        assert!(storefront.contains(r#""username":"alice""#), "unexpected storefront: {}", storefront);
+        assert!(!storefront.contains("alice@example.com"), "storefront shows the vendor's email: {}", storefront);
+        assert_no_hash_material(&storefront, &password_hash(&pool, "alice@example.com").await);
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    email_templates, jobs,
+    notifier::Recipient,
+    tokens,
+};
+
+const TOKEN_TTL_HOURS: i64 = 24;
+/// Minimum wait between verification emails for one account
+const RESEND_COOLDOWN_SECS: i64 = 60;
+/// Most verification emails one account can get in 24 hours
+const MAX_SENDS_PER_DAY: i64 = 5;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// Whether `REQUIRE_VERIFIED_EMAIL` (a comma-separated list such as "orders,products")
+/// restricts the given action to verified accounts
+fn is_required_for(action: &str) -> bool {
+    env::var("REQUIRE_VERIFIED_EMAIL")
+        .map(|actions| actions.split(',').any(|a| a.trim() == action))
+        .unwrap_or(false)
+}
+
+/// True when the action needs a verified email and this user hasn't verified theirs
+pub async fn is_blocked(db: &PgPool, user_id: Uuid, action: &str) -> Result<bool, sqlx::Error> {
+    if !is_required_for(action) {
+        return Ok(false);
+    }
+    let verified: Option<bool> = sqlx::query_scalar("SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await?;
+    Ok(!verified.unwrap_or(false))
+}
+
+/// Create a verification token for the recipient's address and queue the
+/// email, in the caller's transaction
+pub async fn send_verification_email(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    recipient: &Recipient,
+) -> Result<(), sqlx::Error> {
+    let token = tokens::generate();
+    sqlx::query(
+        "INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id)
+    .bind(&recipient.email)
+    .bind(tokens::hash(&token))
+    .bind(Utc::now() + Duration::hours(TOKEN_TTL_HOURS))
+    .execute(&mut *conn)
+    .await?;
+
+    let message = email_templates::email_verification(recipient, &token);
+    let payload = serde_json::to_value(message).expect("EmailMessage serializes");
+    jobs::enqueue(&mut *conn, "email.send", payload, None).await
+}
+
+#[derive(sqlx::FromRow)]
+struct VerifiedToken {
+    user_id: Uuid,
+    email: String,
+}
+
+/// Confirm an email address with the token from the verification email.
+/// A token for a pending address change makes that address the account's email.
+pub async fn verify_email(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    let VerifiedToken { user_id, email } = sqlx::query_as::<_, VerifiedToken>(
+        r#"
+        UPDATE email_verification_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id, email
+        "#,
+    )
+    .bind(tokens::hash(&payload.token))
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to verify email", e))?
+    .ok_or_else(|| error(StatusCode::BAD_REQUEST, "Invalid or expired verification token"))?;
+
+    // Links for an address that was since replaced by another change no longer apply
+    let result = sqlx::query(
+        r#"
+        UPDATE users
+        SET email = $2,
+            pending_email = NULL,
+            email_verified_at = CASE WHEN email = $2 THEN COALESCE(email_verified_at, NOW()) ELSE NOW() END
+        WHERE id = $1 AND deleted_at IS NULL AND (email = $2 OR pending_email = $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(&email)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("duplicate key value") {
+            error(StatusCode::CONFLICT, "Email already registered")
+        } else {
+            internal_error("Failed to verify email", e)
+        }
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(error(StatusCode::BAD_REQUEST, "Invalid or expired verification token"));
+    }
+
+    // Older links for the same account are no longer needed
+    sqlx::query("UPDATE email_verification_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| internal_error("Failed to verify email", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok(Json(MessageResponse {
+        message: "Email address verified".into(),
+    }))
+}
+
+#[derive(sqlx::FromRow)]
+struct VerificationStatus {
+    username: String,
+    email: String,
+    pending_email: Option<String>,
+    verified: bool,
+    last_sent_secs: Option<f64>,
+    sent_today: i64,
+}
+
+/// Send a new verification email to the authenticated user, or to the new
+/// address of a pending email change
+/// Limited to one per minute and five per day
+pub async fn resend_verification(
+    State(state): State<Arc<AppState>>,
+    auth_user: AuthUser,
+) -> Result<(StatusCode, Json<MessageResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let mut tx = state.db.begin().await
+        .map_err(|e| internal_error("Failed to start transaction", e))?;
+
+    // Lock the user row so concurrent requests can't both pass the limits
+    let status = sqlx::query_as::<_, VerificationStatus>(
+        r#"
+        SELECT u.username, u.email, u.pending_email, u.email_verified_at IS NOT NULL AS verified,
+               (SELECT EXTRACT(EPOCH FROM NOW() - MAX(t.created_at))::FLOAT8
+                FROM email_verification_tokens t WHERE t.user_id = u.id) AS last_sent_secs,
+               (SELECT COUNT(*) FROM email_verification_tokens t
+                WHERE t.user_id = u.id AND t.created_at > NOW() - INTERVAL '24 hours') AS sent_today
+        FROM users u
+        WHERE u.id = $1
+        FOR UPDATE OF u
+        "#,
+    )
+    .bind(auth_user.user_id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| internal_error("Failed to fetch user", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+
+    if status.verified && status.pending_email.is_none() {
+        return Err(error(StatusCode::CONFLICT, "Email address is already verified"));
+    }
+    if status.last_sent_secs.is_some_and(|secs| secs < RESEND_COOLDOWN_SECS as f64) {
+        return Err(error(
+            StatusCode::TOO_MANY_REQUESTS,
+            &format!("Please wait {} seconds between verification emails", RESEND_COOLDOWN_SECS),
+        ));
+    }
+    if status.sent_today >= MAX_SENDS_PER_DAY {
+        return Err(error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many verification emails today. Please try again tomorrow",
+        ));
+    }
+
+    let recipient = Recipient {
+        username: status.username,
+        email: status.pending_email.unwrap_or(status.email),
+    };
+    send_verification_email(&mut tx, auth_user.user_id, &recipient).await
+        .map_err(|e| internal_error("Failed to send verification email", e))?;
+
+    tx.commit().await
+        .map_err(|e| internal_error("Failed to commit transaction", e))?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(MessageResponse {
+            message: "Verification email sent".into(),
+        }),
+    ))
+}
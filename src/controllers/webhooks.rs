@@ -0,0 +1,270 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth_guard::AuthUser,
+    models::Webhook::{
+        CreateWebhookEndpoint, CreatedWebhookEndpoint, UpdateWebhookEndpoint, WebhookDelivery,
+        WebhookDeliveryQuery, WebhookEndpoint, WEBHOOK_EVENT_TYPES,
+    },
+    webhooks,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    error: String,
+}
+
+const ENDPOINT_COLUMNS: &str =
+    "id, vendor_id, url, secret, event_types, is_active, consecutive_failures, disabled_at, created_at, updated_at";
+const DELIVERY_COLUMNS: &str =
+    "id, endpoint_id, event_id, event_type, payload, status, attempts, response_status, last_error, next_attempt_at, delivered_at, created_at";
+
+fn error(status: StatusCode, message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: message.into(),
+        }),
+    )
+}
+
+fn internal_error(message: &str, e: sqlx::Error) -> (StatusCode, Json<ErrorResponse>) {
+    println!("{}: {:?}", message, e);
+    error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+fn require_vendor(role: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if role != "vendor" {
+        return Err(error(StatusCode::FORBIDDEN, "Only vendors can manage webhooks"));
+    }
+    Ok(())
+}
+
+/// Check the URL is a public http(s) address and every event type is known
+async fn validate_endpoint(url: &str, event_types: &[String]) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    webhooks::check_destination(url)
+        .await
+        .map_err(|message| error(StatusCode::BAD_REQUEST, &message))?;
+    if event_types.is_empty() {
+        return Err(error(StatusCode::BAD_REQUEST, "Subscribe to at least one event type"));
+    }
+    if let Some(unknown) = event_types.iter().find(|t| !WEBHOOK_EVENT_TYPES.contains(&t.as_str())) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Invalid event type '{}'. Valid event types are: {}",
+                unknown,
+                WEBHOOK_EVENT_TYPES.join(", ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Fetch one of the vendor's endpoints, 404 if it belongs to someone else
+async fn fetch_endpoint(
+    state: &AppState,
+    vendor_id: Uuid,
+    id: Uuid,
+) -> Result<WebhookEndpoint, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, WebhookEndpoint>(&format!(
+        "SELECT {} FROM webhook_endpoints WHERE id = $1 AND vendor_id = $2",
+        ENDPOINT_COLUMNS
+    ))
+    .bind(id)
+    .bind(vendor_id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch webhook endpoint", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Webhook endpoint not found"))
+}
+
+/// Register a webhook endpoint (vendor only)
+/// The signing secret is returned in this response only
+pub async fn create_webhook_endpoint(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Json(payload): Json<CreateWebhookEndpoint>,
+) -> Result<(StatusCode, Json<CreatedWebhookEndpoint>), (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    validate_endpoint(&payload.url, &payload.event_types).await?;
+
+    let endpoint = sqlx::query_as::<_, WebhookEndpoint>(&format!(
+        r#"
+        INSERT INTO webhook_endpoints (vendor_id, url, secret, event_types)
+        VALUES ($1, $2, $3, $4)
+        RETURNING {}
+        "#,
+        ENDPOINT_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(&payload.url)
+    .bind(webhooks::generate_secret())
+    .bind(&payload.event_types)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to create webhook endpoint", e))?;
+
+    let secret = endpoint.secret.clone();
+    Ok((StatusCode::CREATED, Json(CreatedWebhookEndpoint { endpoint, secret })))
+}
+
+/// List the authenticated vendor's webhook endpoints
+pub async fn get_webhook_endpoints(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+) -> Result<Json<Vec<WebhookEndpoint>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let endpoints = sqlx::query_as::<_, WebhookEndpoint>(&format!(
+        "SELECT {} FROM webhook_endpoints WHERE vendor_id = $1 ORDER BY created_at",
+        ENDPOINT_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch webhook endpoints", e))?;
+
+    Ok(Json(endpoints))
+}
+
+/// Get one webhook endpoint
+pub async fn get_webhook_endpoint(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookEndpoint>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    Ok(Json(fetch_endpoint(&state, user_id, id).await?))
+}
+
+/// Update a webhook endpoint
+/// Setting is_active to true re-enables an endpoint that was disabled after failures
+pub async fn update_webhook_endpoint(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateWebhookEndpoint>,
+) -> Result<Json<WebhookEndpoint>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let existing = fetch_endpoint(&state, user_id, id).await?;
+    let url = payload.url.unwrap_or(existing.url);
+    let event_types = payload.event_types.unwrap_or(existing.event_types);
+    validate_endpoint(&url, &event_types).await?;
+
+    let endpoint = sqlx::query_as::<_, WebhookEndpoint>(&format!(
+        r#"
+        UPDATE webhook_endpoints
+        SET url = $3,
+            event_types = $4,
+            is_active = COALESCE($5, is_active),
+            consecutive_failures = CASE WHEN $5 THEN 0 ELSE consecutive_failures END,
+            disabled_at = CASE WHEN $5 THEN NULL ELSE disabled_at END,
+            updated_at = NOW()
+        WHERE id = $1 AND vendor_id = $2
+        RETURNING {}
+        "#,
+        ENDPOINT_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .bind(&url)
+    .bind(&event_types)
+    .bind(payload.is_active)
+    .fetch_one(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to update webhook endpoint", e))?;
+
+    Ok(Json(endpoint))
+}
+
+/// Delete a webhook endpoint and its delivery log
+pub async fn delete_webhook_endpoint(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+
+    let result = sqlx::query("DELETE FROM webhook_endpoints WHERE id = $1 AND vendor_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&*state.db)
+        .await
+        .map_err(|e| internal_error("Failed to delete webhook endpoint", e))?;
+
+    if result.rows_affected() == 0 {
+        return Err(error(StatusCode::NOT_FOUND, "Webhook endpoint not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delivery log for an endpoint, newest first
+/// Optional filter: ?status=pending|succeeded|failed, ?limit= (default 50, max 200)
+pub async fn get_webhook_deliveries(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path(id): Path<Uuid>,
+    Query(params): Query<WebhookDeliveryQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    fetch_endpoint(&state, user_id, id).await?;
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(&format!(
+        r#"
+        SELECT {}
+        FROM webhook_deliveries
+        WHERE endpoint_id = $1 AND ($2::text IS NULL OR status = $2)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+        DELIVERY_COLUMNS
+    ))
+    .bind(id)
+    .bind(&params.status)
+    .bind(limit)
+    .fetch_all(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to fetch webhook deliveries", e))?;
+
+    Ok(Json(deliveries))
+}
+
+/// Send a delivery again, whatever its current status
+/// The delivery goes back to pending and is picked up by the worker right away
+pub async fn replay_webhook_delivery(
+    State(state): State<Arc<AppState>>,
+    AuthUser { user_id, role }: AuthUser,
+    Path((id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<WebhookDelivery>, (StatusCode, Json<ErrorResponse>)> {
+    require_vendor(&role)?;
+    fetch_endpoint(&state, user_id, id).await?;
+
+    let delivery = sqlx::query_as::<_, WebhookDelivery>(&format!(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+        WHERE id = $1 AND endpoint_id = $2
+        RETURNING {}
+        "#,
+        DELIVERY_COLUMNS
+    ))
+    .bind(delivery_id)
+    .bind(id)
+    .fetch_optional(&*state.db)
+    .await
+    .map_err(|e| internal_error("Failed to replay webhook delivery", e))?
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Webhook delivery not found"))?;
+
+    Ok(Json(delivery))
+}
@@ -0,0 +1,267 @@
+//! Plain-text templates for transactional emails
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+use crate::notifier::{EmailMessage, Recipient};
+
+/// Public URL of the storefront, used for links in emails
+fn app_url() -> String {
+    std::env::var("APP_BASE_URL").unwrap_or_else(|_| "http://localhost:3000".into())
+}
+
+pub fn registration(to: &Recipient) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: "Welcome to the shop".into(),
+        body: format!(
+            "Hi {},\n\nYour account has been created. You can sign in at {} with this email address.\n\nThanks for joining!\n",
+            to.username,
+            app_url(),
+        ),
+    }
+}
+
+/// Sent on registration and when the user asks for a new link
+pub fn email_verification(to: &Recipient, token: &str) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: "Confirm your email address".into(),
+        body: format!(
+            "Hi {},\n\nPlease confirm this is your email address by opening the link below:\n\n{}/verify-email?token={}\n\nThe link expires in 24 hours. If you didn't create an account, you can ignore this email.\n",
+            to.username,
+            app_url(),
+            token,
+        ),
+    }
+}
+
+pub fn order_placed(to: &Recipient, order_id: Uuid, total: &BigDecimal) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Order {} received", order_id),
+        body: format!(
+            "Hi {},\n\nThanks for your order! We've received order {} and your payment of {}.\n\nWe'll email you again when it ships.\n",
+            to.username,
+            order_id,
+            total.with_scale(2),
+        ),
+    }
+}
+
+/// Sent when an order moves to "shipped" or "delivered"
+pub fn order_status_changed(to: &Recipient, order_id: Uuid, status: &str) -> EmailMessage {
+    let line = match status {
+        "shipped" => "is on its way",
+        "delivered" => "has been delivered",
+        _ => "has been updated",
+    };
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Order {} {}", order_id, status),
+        body: format!(
+            "Hi {},\n\nYour order {} {}.\n\nYou can check its status at {}/orders/{}\n",
+            to.username,
+            order_id,
+            line,
+            app_url(),
+            order_id,
+        ),
+    }
+}
+
+/// Vendor's summary of one day's sales
+pub fn daily_sales_report(to: &Recipient, date: NaiveDate, orders: i64, units: i64, revenue: &BigDecimal) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Your sales for {}", date),
+        body: format!(
+            "Hi {},\n\nHere's how your store did on {}:\n\nOrders: {}\nUnits sold: {}\nRevenue: {}\n\nCancelled items are not included.\n",
+            to.username,
+            date,
+            orders,
+            units,
+            revenue.with_scale(2),
+        ),
+    }
+}
+
+/// Sent when an admin approves or suspends a vendor's store
+pub fn vendor_status_changed(to: &Recipient, store_name: &str, status: &str, reason: Option<&str>) -> EmailMessage {
+    let line = match status {
+        "approved" => "has been approved. You can now list products",
+        "suspended" => "has been suspended. Its products are hidden from the catalog and you can't list new ones",
+        _ => "is waiting for review",
+    };
+    let note = reason.map(|reason| format!("\nNote from our team: {}\n", reason)).unwrap_or_default();
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Your store {} is {}", store_name, status),
+        body: format!(
+            "Hi {},\n\nYour store {} {}.\n{}\nYou can review your store profile at {}/vendors/me\n",
+            to.username,
+            store_name,
+            line,
+            note,
+            app_url(),
+        ),
+    }
+}
+
+pub fn payout_sent(to: &Recipient, reference: &str, amount: &BigDecimal, account_last4: Option<&str>) -> EmailMessage {
+    let account = account_last4.map(|last4| format!(" ending in {}", last4)).unwrap_or_default();
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Payout {} is on its way", reference),
+        body: format!(
+            "Hi {},\n\nWe've paid {} to your payout account{} (reference {}).\n\nYour statement is at {}/vendor/ledger/statement\n",
+            to.username,
+            amount.with_scale(2),
+            account,
+            reference,
+            app_url(),
+        ),
+    }
+}
+
+pub fn low_stock(to: &Recipient, product_id: Uuid, product_name: &str, stock: i32, threshold: i32) -> EmailMessage {
+    let level = if stock == 0 {
+        "is out of stock".to_string()
+    } else {
+        format!("is down to {} in stock (your alert is set at {})", stock, threshold)
+    };
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("Low stock: {}", product_name),
+        body: format!(
+            "Hi {},\n\n{} {}.\n\nRestock it at {}/products/{}/inventory\n",
+            to.username,
+            product_name,
+            level,
+            app_url(),
+            product_id,
+        ),
+    }
+}
+
+pub fn back_in_stock(to: &Recipient, product_id: Uuid, product_name: &str) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: format!("{} is back in stock", product_name),
+        body: format!(
+            "Hi {},\n\nGood news: {} is available again. Stock may be limited.\n\n{}/products/{}\n",
+            to.username,
+            product_name,
+            app_url(),
+            product_id,
+        ),
+    }
+}
+
+pub fn password_reset(to: &Recipient, reset_token: &str) -> EmailMessage {
+    EmailMessage {
+        to: to.email.clone(),
+        subject: "Reset your password".into(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to reset the password for your account. If that was you, use this code to choose a new password:\n\n{}\n\nThe code expires soon and can only be used once. If you didn't ask for this, you can ignore this email.\n",
+            to.username,
+            reset_token,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn alice() -> Recipient {
+        Recipient {
+            username: "alice".into(),
+            email: "alice@example.com".into(),
+        }
+    }
+
+    #[test]
+    fn emails_go_to_the_recipient_and_greet_them() {
+        let order_id = Uuid::new_v4();
+        let total = BigDecimal::from(5);
+        for message in [
+            registration(&alice()),
+            email_verification(&alice(), "token"),
+            order_placed(&alice(), order_id, &total),
+            order_status_changed(&alice(), order_id, "shipped"),
+            password_reset(&alice(), "code"),
+        ] {
+            assert_eq!(message.to, "alice@example.com");
+            assert!(message.body.starts_with("Hi alice,\n\n"), "unexpected greeting: {}", message.body);
+        }
+    }
+
+    #[test]
+    fn order_placed_shows_the_total_with_two_decimals() {
+        let order_id = Uuid::new_v4();
+        let message = order_placed(&alice(), order_id, &BigDecimal::from_str("19.5").unwrap());
+        assert_eq!(message.subject, format!("Order {} received", order_id));
+        assert!(message.body.contains(&format!("order {} and your payment of 19.50.", order_id)));
+    }
+
+    #[test]
+    fn order_status_changed_describes_the_status() {
+        let order_id = Uuid::new_v4();
+        for (status, line) in [
+            ("shipped", "is on its way"),
+            ("delivered", "has been delivered"),
+            ("cancelled", "has been updated"),
+        ] {
+            let message = order_status_changed(&alice(), order_id, status);
+            assert_eq!(message.subject, format!("Order {} {}", order_id, status));
+            assert!(message.body.contains(&format!("Your order {} {}.", order_id, line)));
+            assert!(message.body.contains(&format!("/orders/{}\n", order_id)));
+        }
+    }
+
+    #[test]
+    fn verification_and_reset_emails_carry_their_token() {
+        let verification = email_verification(&alice(), "abc123");
+        assert!(verification.body.contains("/verify-email?token=abc123\n"));
+        let reset = password_reset(&alice(), "xyz789");
+        assert!(reset.body.contains("\n\nxyz789\n\n"));
+    }
+
+    #[test]
+    fn vendor_status_changed_includes_the_reason_only_when_given() {
+        let suspended = vendor_status_changed(&alice(), "Bikes", "suspended", Some("Unpaid fees"));
+        assert_eq!(suspended.subject, "Your store Bikes is suspended");
+        assert!(suspended.body.contains("Your store Bikes has been suspended."));
+        assert!(suspended.body.contains("Note from our team: Unpaid fees"));
+
+        let approved = vendor_status_changed(&alice(), "Bikes", "approved", None);
+        assert!(approved.body.contains("has been approved. You can now list products."));
+        assert!(!approved.body.contains("Note from our team"));
+    }
+
+    #[test]
+    fn low_stock_tells_out_of_stock_apart() {
+        let product_id = Uuid::new_v4();
+        let out = low_stock(&alice(), product_id, "Bell", 0, 5);
+        assert!(out.body.contains("Bell is out of stock."));
+        let low = low_stock(&alice(), product_id, "Bell", 3, 5);
+        assert!(low.body.contains("Bell is down to 3 in stock (your alert is set at 5)."));
+        assert!(low.body.contains(&format!("/products/{}/inventory\n", product_id)));
+    }
+
+    #[test]
+    fn money_amounts_are_rounded_to_cents() {
+        let date = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let report = daily_sales_report(&alice(), date, 2, 3, &BigDecimal::from(42));
+        assert_eq!(report.subject, "Your sales for 2026-03-01");
+        assert!(report.body.contains("Orders: 2\nUnits sold: 3\nRevenue: 42.00\n"));
+
+        let payout = payout_sent(&alice(), "PO-1", &BigDecimal::from_str("10.5").unwrap(), Some("6789"));
+        assert!(payout.body.contains("We've paid 10.50 to your payout account ending in 6789 (reference PO-1)."));
+        let payout = payout_sent(&alice(), "PO-2", &BigDecimal::from(1), None);
+        assert!(payout.body.contains("payout account (reference PO-2)."));
+    }
+}
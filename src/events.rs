@@ -0,0 +1,422 @@
+//! Domain events and the transactional outbox.
+//!
+//! Controllers record an event with `record` inside the same transaction as the
+//! change it describes, so an event exists if and only if the change committed.
+//! A background dispatcher then hands each pending event to every subscriber,
+//! remembering which subscribers have handled it. A subscriber that fails is
+//! retried with backoff without re-delivering to the ones that succeeded.
+
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub type EventError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Give up on an event after this many failed delivery rounds
+const MAX_ATTEMPTS: i32 = 8;
+/// How many pending events the dispatcher claims at a time
+const BATCH_SIZE: i64 = 50;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Something that happened that other parts of the system react to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum DomainEvent {
+    UserRegistered {
+        user_id: Uuid,
+        username: String,
+        email: String,
+        role: String,
+    },
+    OrderPlaced {
+        order_id: Uuid,
+        user_id: Uuid,
+        total: BigDecimal,
+        vendor_ids: Vec<Uuid>,
+    },
+    OrderStatusChanged {
+        order_id: Uuid,
+        user_id: Uuid,
+        previous_status: String,
+        status: String,
+    },
+    ProductStockChanged {
+        product_id: Uuid,
+        vendor_id: Uuid,
+        change: i32,  // Positive when stock was added back
+        stock: i32,   // Stock level after the change
+        reason: String,  // An inventory movement reason: "sale", "cancellation", "return", "adjustment", ...
+    },
+    AccountLockedOut {
+        user_id: Uuid,
+        email: String,
+        ip_address: String,  // Where the attempt that triggered the lockout came from
+        failed_attempts: i64,
+        locked_until: DateTime<Utc>,
+    },
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::UserRegistered { .. } => "UserRegistered",
+            DomainEvent::OrderPlaced { .. } => "OrderPlaced",
+            DomainEvent::OrderStatusChanged { .. } => "OrderStatusChanged",
+            DomainEvent::ProductStockChanged { .. } => "ProductStockChanged",
+            DomainEvent::AccountLockedOut { .. } => "AccountLockedOut",
+        }
+    }
+
+    /// The user, order or product the event is about
+    pub fn aggregate_id(&self) -> Uuid {
+        match self {
+            DomainEvent::UserRegistered { user_id, .. } => *user_id,
+            DomainEvent::OrderPlaced { order_id, .. } => *order_id,
+            DomainEvent::OrderStatusChanged { order_id, .. } => *order_id,
+            DomainEvent::ProductStockChanged { product_id, .. } => *product_id,
+            DomainEvent::AccountLockedOut { user_id, .. } => *user_id,
+        }
+    }
+}
+
+/// An event as read back from the outbox
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub event: DomainEvent,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct OutboxRow {
+    id: Uuid,
+    payload: Json<DomainEvent>,
+    attempts: i32,
+    created_at: DateTime<Utc>,
+}
+
+/// Reacts to domain events. Handlers may see an event more than once if the
+/// process stops between handling it and recording the delivery, so they should
+/// be idempotent on `OutboxEvent::id` where a repeat would matter.
+#[async_trait]
+pub trait EventSubscriber: Send + Sync {
+    /// Stable name used to track deliveries; changing it re-delivers pending events
+    fn name(&self) -> &'static str;
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), EventError>;
+}
+
+/// Write an event to the outbox. Call with the transaction that makes the change.
+pub async fn record(conn: &mut PgConnection, event: DomainEvent) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO outbox (event_type, aggregate_id, payload) VALUES ($1, $2, $3) RETURNING id",
+    )
+    .bind(event.event_type())
+    .bind(event.aggregate_id())
+    .bind(Json(&event))
+    .fetch_one(conn)
+    .await
+}
+
+/// Start delivering outbox events to the subscribers on a background task
+pub fn spawn_dispatcher(db: Arc<PgPool>, subscribers: Vec<Arc<dyn EventSubscriber>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            // Keep draining while full batches come back, then wait for the next tick
+            loop {
+                match dispatch_batch(&db, &subscribers).await {
+                    Ok(count) if count as i64 == BATCH_SIZE => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        println!("Outbox dispatch failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Claim a batch of due events and deliver them. Rows stay locked until the
+/// batch commits, so several instances can run dispatchers side by side. The
+/// lock is NO KEY UPDATE so subscribers can still insert rows referencing the event.
+async fn dispatch_batch(db: &PgPool, subscribers: &[Arc<dyn EventSubscriber>]) -> Result<usize, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let rows = sqlx::query_as::<_, OutboxRow>(
+        r#"
+        SELECT id, payload, attempts, created_at
+        FROM outbox
+        WHERE processed_at IS NULL AND failed_at IS NULL AND next_attempt_at <= NOW()
+        ORDER BY created_at
+        LIMIT $1
+        FOR NO KEY UPDATE SKIP LOCKED
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for row in &rows {
+        let event = OutboxEvent {
+            id: row.id,
+            event: row.payload.0.clone(),
+            created_at: row.created_at,
+        };
+
+        let delivered: Vec<String> =
+            sqlx::query_scalar("SELECT subscriber FROM outbox_deliveries WHERE event_id = $1")
+                .bind(event.id)
+                .fetch_all(&mut *tx)
+                .await?;
+
+        let mut errors = Vec::new();
+        for subscriber in subscribers {
+            if delivered.iter().any(|name| name == subscriber.name()) {
+                continue;
+            }
+            match subscriber.handle(&event).await {
+                Ok(()) => {
+                    sqlx::query("INSERT INTO outbox_deliveries (event_id, subscriber) VALUES ($1, $2)")
+                        .bind(event.id)
+                        .bind(subscriber.name())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                Err(e) => errors.push(format!("{}: {}", subscriber.name(), e)),
+            }
+        }
+
+        if errors.is_empty() {
+            sqlx::query("UPDATE outbox SET processed_at = NOW(), last_error = NULL WHERE id = $1")
+                .bind(event.id)
+                .execute(&mut *tx)
+                .await?;
+            continue;
+        }
+
+        let attempts = row.attempts + 1;
+        let last_error = errors.join("; ");
+        if attempts >= MAX_ATTEMPTS {
+            println!(
+                "Giving up on {} event {} after {} attempts: {}",
+                event.event.event_type(), event.id, attempts, last_error
+            );
+            sqlx::query("UPDATE outbox SET attempts = $2, last_error = $3, failed_at = NOW() WHERE id = $1")
+                .bind(event.id)
+                .bind(attempts)
+                .bind(&last_error)
+                .execute(&mut *tx)
+                .await?;
+        } else {
+            println!(
+                "Delivery of {} event {} failed (attempt {}): {}",
+                event.event.event_type(), event.id, attempts, last_error
+            );
+            // Exponential backoff: 2s, 4s, 8s, ... capped at 10 minutes
+            let delay_secs = 2_i64.pow(attempts as u32).min(600);
+            sqlx::query(
+                r#"
+                UPDATE outbox
+                SET attempts = $2, last_error = $3, next_attempt_at = NOW() + make_interval(secs => $4)
+                WHERE id = $1
+                "#,
+            )
+            .bind(event.id)
+            .bind(attempts)
+            .bind(&last_error)
+            .bind(delay_secs as f64)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(rows.len())
+}
+
+/// Logs every event, handy when following what the system is doing
+pub struct LogSubscriber;
+
+#[async_trait]
+impl EventSubscriber for LogSubscriber {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), EventError> {
+        println!("Event {} {}: {:?}", event.event.event_type(), event.id, event.event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::load_schema;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts the events it sees, failing each one while `fails` is set
+    struct Counting {
+        name: &'static str,
+        fails: bool,
+        calls: AtomicUsize,
+    }
+
+    impl Counting {
+        fn new(name: &'static str, fails: bool) -> Arc<Self> {
+            Arc::new(Counting { name, fails, calls: AtomicUsize::new(0) })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl EventSubscriber for Counting {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn handle(&self, _event: &OutboxEvent) -> Result<(), EventError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.fails {
+                return Err("unavailable".into());
+            }
+            Ok(())
+        }
+    }
+
+    async fn record_event(pool: &PgPool) -> Uuid {
+        load_schema(pool).await;
+        let event = DomainEvent::UserRegistered {
+            user_id: Uuid::new_v4(),
+            username: "alice".into(),
+            email: "alice@example.com".into(),
+            role: "customer".into(),
+        };
+        let mut conn = pool.acquire().await.unwrap();
+        record(&mut conn, event).await.unwrap()
+    }
+
+    /// Make the event due again without waiting out its backoff
+    async fn make_due(pool: &PgPool, event_id: Uuid) {
+        sqlx::query("UPDATE outbox SET next_attempt_at = NOW() WHERE id = $1")
+            .bind(event_id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[derive(FromRow)]
+    struct Progress {
+        attempts: i32,
+        delay_secs: f64,
+        processed: bool,
+        failed: bool,
+    }
+
+    async fn progress(pool: &PgPool, event_id: Uuid) -> Progress {
+        sqlx::query_as(
+            r#"
+            SELECT attempts, EXTRACT(EPOCH FROM next_attempt_at - NOW())::FLOAT8 AS delay_secs,
+                   processed_at IS NOT NULL AS processed, failed_at IS NOT NULL AS failed
+            FROM outbox WHERE id = $1
+            "#,
+        )
+        .bind(event_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn failed_deliveries_back_off_then_give_up(pool: PgPool) {
+        let event_id = record_event(&pool).await;
+        let failing = Counting::new("failing", true);
+        let subscribers: Vec<Arc<dyn EventSubscriber>> = vec![failing.clone()];
+
+        for (attempt, backoff) in [(1, 2.0), (2, 4.0), (3, 8.0)] {
+            assert_eq!(dispatch_batch(&pool, &subscribers).await.unwrap(), 1);
+            let progress = progress(&pool, event_id).await;
+            assert_eq!(progress.attempts, attempt);
+            assert!(progress.delay_secs > backoff - 1.0 && progress.delay_secs <= backoff, "{}", progress.delay_secs);
+            assert!(!progress.processed && !progress.failed);
+
+            // Not due yet
+            assert_eq!(dispatch_batch(&pool, &subscribers).await.unwrap(), 0);
+            make_due(&pool, event_id).await;
+        }
+
+        sqlx::query("UPDATE outbox SET attempts = $2 WHERE id = $1")
+            .bind(event_id)
+            .bind(MAX_ATTEMPTS - 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+        dispatch_batch(&pool, &subscribers).await.unwrap();
+        let progress = progress(&pool, event_id).await;
+        assert_eq!(progress.attempts, MAX_ATTEMPTS);
+        assert!(progress.failed && !progress.processed);
+
+        make_due(&pool, event_id).await;
+        let calls = failing.calls();
+        assert_eq!(dispatch_batch(&pool, &subscribers).await.unwrap(), 0);
+        assert_eq!(failing.calls(), calls);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn retries_skip_subscribers_that_already_succeeded(pool: PgPool) {
+        let event_id = record_event(&pool).await;
+        let working = Counting::new("working", false);
+        let flaky = Counting::new("flaky", true);
+        dispatch_batch(&pool, &[working.clone(), flaky.clone()]).await.unwrap();
+        assert_eq!((working.calls(), flaky.calls()), (1, 1));
+
+        // Another instance's dispatcher, where the flaky subscriber recovered
+        make_due(&pool, event_id).await;
+        let other_working = Counting::new("working", false);
+        let recovered = Counting::new("flaky", false);
+        dispatch_batch(&pool, &[other_working.clone(), recovered.clone()]).await.unwrap();
+        assert_eq!((other_working.calls(), recovered.calls()), (0, 1));
+        assert!(progress(&pool, event_id).await.processed);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn events_locked_by_another_dispatcher_are_skipped(pool: PgPool) {
+        let locked_id = record_event(&pool).await;
+        let mut other = pool.begin().await.unwrap();
+        sqlx::query("SELECT id FROM outbox WHERE id = $1 FOR NO KEY UPDATE")
+            .bind(locked_id)
+            .execute(&mut *other)
+            .await
+            .unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let free_event = DomainEvent::OrderStatusChanged {
+            order_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            previous_status: "pending".into(),
+            status: "shipped".into(),
+        };
+        let free_id = record(&mut conn, free_event).await.unwrap();
+        drop(conn);
+
+        let log = Counting::new("log", false);
+        let subscribers: Vec<Arc<dyn EventSubscriber>> = vec![log.clone()];
+        assert_eq!(dispatch_batch(&pool, &subscribers).await.unwrap(), 1);
+        assert!(progress(&pool, free_id).await.processed);
+        assert!(!progress(&pool, locked_id).await.processed);
+
+        other.rollback().await.unwrap();
+        assert_eq!(dispatch_batch(&pool, &subscribers).await.unwrap(), 1);
+        assert_eq!(log.calls(), 2);
+    }
+}
@@ -0,0 +1,161 @@
+//! Inventory movements. Every change to `products.stock` goes through `apply`,
+//! which changes the stock, records the movement and its `ProductStockChanged`
+//! event in the caller's transaction. A product's stock therefore equals the
+//! sum of its movements; `reconcile` records a correction wherever it doesn't,
+//! e.g. after a change made directly in the database. Low-stock alerts and
+//! back-in-stock emails are triggered from the same place.
+
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::email_templates;
+use crate::events::{self, DomainEvent};
+use crate::jobs;
+use crate::notifier::Recipient;
+use crate::models::Inventory::InventoryMovement;
+
+/// Why stock changed
+pub const MOVEMENT_REASONS: [&str; 7] = ["initial", "sale", "cancellation", "return", "adjustment", "import", "correction"];
+
+pub const MOVEMENT_COLUMNS: &str =
+    "id, product_id, change, stock_after, reason, order_id, return_id, actor_id, note, created_at";
+
+/// A stock change and what caused it
+#[derive(Debug, Clone, Default)]
+pub struct Movement {
+    pub product_id: Uuid,
+    pub change: i32,             // Positive when stock is added
+    pub reason: &'static str,
+    pub order_id: Option<Uuid>,
+    pub return_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,  // Who made a manual change
+    pub note: Option<String>,
+}
+
+async fn insert(conn: &mut PgConnection, movement: &Movement, stock_after: i32) -> Result<InventoryMovement, sqlx::Error> {
+    sqlx::query_as::<_, InventoryMovement>(&format!(
+        r#"
+        INSERT INTO inventory_movements (product_id, change, stock_after, reason, order_id, return_id, actor_id, note)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING {}
+        "#,
+        MOVEMENT_COLUMNS
+    ))
+    .bind(movement.product_id)
+    .bind(movement.change)
+    .bind(stock_after)
+    .bind(movement.reason)
+    .bind(movement.order_id)
+    .bind(movement.return_id)
+    .bind(movement.actor_id)
+    .bind(&movement.note)
+    .fetch_one(conn)
+    .await
+}
+
+/// Change a product's stock by `movement.change` and record why. Returns the
+/// recorded movement, or `None` if the product doesn't exist or nothing changed.
+/// Dropping to the product's low-stock threshold emails the vendor, and coming
+/// back from zero queues the back-in-stock emails, both only if the caller's
+/// transaction commits.
+pub async fn apply(conn: &mut PgConnection, movement: Movement) -> Result<Option<InventoryMovement>, sqlx::Error> {
+    let row: Option<(Uuid, String, i32, Option<i32>)> = sqlx::query_as(
+        "UPDATE products SET stock = stock + $1 WHERE id = $2 RETURNING vendor_id, name, stock, low_stock_threshold",
+    )
+    .bind(movement.change)
+    .bind(movement.product_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some((vendor_id, name, stock, low_stock_threshold)) = row else { return Ok(None) };
+    if movement.change == 0 {
+        return Ok(None);
+    }
+
+    let recorded = insert(&mut *conn, &movement, stock).await?;
+    let event = DomainEvent::ProductStockChanged {
+        product_id: movement.product_id,
+        vendor_id,
+        change: movement.change,
+        stock,
+        reason: movement.reason.into(),
+    };
+    events::record(&mut *conn, event).await?;
+
+    let previous = stock - movement.change;
+    if let Some(threshold) = low_stock_threshold {
+        if previous > threshold && stock <= threshold {
+            notify_low_stock(&mut *conn, vendor_id, movement.product_id, &name, stock, threshold).await?;
+        }
+    }
+    if previous <= 0 && stock > 0 {
+        jobs::enqueue(
+            &mut *conn,
+            "inventory.back_in_stock",
+            json!({ "product_id": movement.product_id }),
+            None,
+        )
+        .await?;
+    }
+    Ok(Some(recorded))
+}
+
+async fn notify_low_stock(
+    conn: &mut PgConnection,
+    vendor_id: Uuid,
+    product_id: Uuid,
+    name: &str,
+    stock: i32,
+    threshold: i32,
+) -> Result<(), sqlx::Error> {
+    let (username, email): (String, String) = sqlx::query_as("SELECT username, email FROM users WHERE id = $1")
+        .bind(vendor_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let message = email_templates::low_stock(&Recipient { username, email }, product_id, name, stock, threshold);
+    let message = serde_json::to_value(message).expect("EmailMessage serializes");
+    jobs::enqueue(conn, "email.send", message, None).await
+}
+
+/// Record the stock a product was created with, by hand ("initial") or by an
+/// import ("import")
+pub async fn record_initial(
+    conn: &mut PgConnection,
+    product_id: Uuid,
+    stock: i32,
+    reason: &'static str,
+    actor_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    if stock == 0 {
+        return Ok(());
+    }
+    let movement = Movement {
+        product_id,
+        change: stock,
+        reason,
+        actor_id: Some(actor_id),
+        ..Default::default()
+    };
+    insert(conn, &movement, stock).await?;
+    Ok(())
+}
+
+/// Record a correction for every product whose stock differs from the sum of
+/// its movements, so the history explains the current stock again. Returns how
+/// many products were corrected.
+pub async fn reconcile(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO inventory_movements (product_id, change, stock_after, reason, note)
+        SELECT p.id, p.stock - COALESCE(m.total, 0), p.stock, 'correction', 'Stock differed from its movement history'
+        FROM products p
+        LEFT JOIN (
+            SELECT product_id, SUM(change)::INTEGER AS total FROM inventory_movements GROUP BY product_id
+        ) m ON m.product_id = p.id
+        WHERE p.stock <> COALESCE(m.total, 0)
+        "#,
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,482 @@
+//! Invoices and credit notes. Documents are snapshots: they are issued inside the
+//! same transaction as the sale or refund they describe and never change after,
+//! so prices, names and addresses stay as they were at the time.
+
+use bigdecimal::BigDecimal;
+use printpdf::{BuiltinFont, Mm, PdfDocument, PdfLayerReference};
+use sqlx::PgConnection;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::ledger;
+use crate::models::Invoice::{Invoice, InvoiceDocument, InvoiceLine};
+
+/// A line to put on a new invoice or credit note
+#[derive(Debug, Clone)]
+pub struct LineInput {
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: BigDecimal,
+}
+
+/// Tax rate applied to new documents, from `TAX_RATE` (e.g. "0.075"). Prices are
+/// tax inclusive, so the tax is broken out of the total rather than added to it.
+fn tax_rate() -> BigDecimal {
+    std::env::var("TAX_RATE")
+        .ok()
+        .and_then(|rate| BigDecimal::from_str(&rate).ok())
+        .unwrap_or_else(|| BigDecimal::from(0))
+}
+
+/// Reserve the next document number for a vendor
+async fn next_number(conn: &mut PgConnection, vendor_id: Uuid, kind: &str) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO invoice_counters (vendor_id, kind, last_number)
+        VALUES ($1, $2, 1)
+        ON CONFLICT (vendor_id, kind) DO UPDATE SET last_number = invoice_counters.last_number + 1
+        RETURNING last_number
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(kind)
+    .fetch_one(conn)
+    .await
+}
+
+async fn issue(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    vendor_id: Uuid,
+    kind: &str,
+    lines: &[LineInput],
+    reason: Option<&str>,
+) -> Result<Invoice, sqlx::Error> {
+    let number = next_number(&mut *conn, vendor_id, kind).await?;
+    let prefix = if kind == "credit_note" { "CN" } else { "INV" };
+    let document_number = format!(
+        "{}-{}-{:06}",
+        prefix,
+        vendor_id.simple().to_string()[..8].to_uppercase(),
+        number
+    );
+
+    let total = lines
+        .iter()
+        .fold(BigDecimal::from(0), |sum, line| sum + &line.unit_price * BigDecimal::from(line.quantity));
+    let rate = tax_rate();
+    let subtotal = (&total / (BigDecimal::from(1) + &rate)).round(2);
+    let tax_amount = &total - &subtotal;
+
+    let invoice = sqlx::query_as::<_, Invoice>(
+        r#"
+        INSERT INTO invoices (order_id, vendor_id, kind, number, document_number, invoice_id,
+                              seller_name, seller_email, buyer_name, buyer_email, buyer_address,
+                              subtotal, tax_rate, tax_amount, total, reason)
+        SELECT o.id, v.id, $3, $4, $5,
+               (SELECT id FROM invoices WHERE order_id = o.id AND vendor_id = v.id AND kind = 'invoice' LIMIT 1),
+               v.username, v.email, c.username, c.email, o.shipping_address,
+               $6, $7, $8, $9, $10
+        FROM orders o
+        JOIN users c ON c.id = o.user_id
+        JOIN users v ON v.id = $2
+        WHERE o.id = $1
+        RETURNING id, order_id, vendor_id, kind, number, document_number, invoice_id,
+                  seller_name, seller_email, buyer_name, buyer_email, buyer_address,
+                  subtotal, tax_rate, tax_amount, total, reason, issued_at
+        "#,
+    )
+    .bind(order_id)
+    .bind(vendor_id)
+    .bind(kind)
+    .bind(number)
+    .bind(&document_number)
+    .bind(&subtotal)
+    .bind(&rate)
+    .bind(&tax_amount)
+    .bind(&total)
+    .bind(reason)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    for (line_number, line) in lines.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO invoice_lines (invoice_id, line_number, description, quantity, unit_price, total)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(invoice.id)
+        .bind(line_number as i32 + 1)
+        .bind(&line.description)
+        .bind(line.quantity)
+        .bind(&line.unit_price)
+        .bind(&line.unit_price * BigDecimal::from(line.quantity))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // Every document moves money between the customer, the platform and the vendor
+    if kind == "credit_note" {
+        ledger::record_refund(&mut *conn, &invoice).await?;
+    } else {
+        ledger::record_sale(&mut *conn, &invoice).await?;
+    }
+
+    Ok(invoice)
+}
+
+/// Issue one invoice per vendor for a newly placed order, from the
+/// `order_items` price snapshots and the shipping chosen for each vendor
+pub async fn issue_order_invoices(conn: &mut PgConnection, order_id: Uuid) -> Result<Vec<Invoice>, sqlx::Error> {
+    let mut lines = active_item_lines(&mut *conn, order_id).await?;
+    lines.extend(shipping_lines(&mut *conn, order_id, None).await?);
+
+    let mut invoices = Vec::new();
+    for (vendor_id, vendor_lines) in group_by_vendor(lines) {
+        invoices.push(issue(&mut *conn, order_id, vendor_id, "invoice", &vendor_lines, None).await?);
+    }
+    Ok(invoices)
+}
+
+/// Issue credit notes for refunded lines, one per vendor
+pub async fn issue_credit_notes(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    lines: Vec<(Uuid, LineInput)>,
+    reason: Option<&str>,
+) -> Result<Vec<Invoice>, sqlx::Error> {
+    let mut credit_notes = Vec::new();
+    for (vendor_id, vendor_lines) in group_by_vendor(lines) {
+        credit_notes.push(issue(&mut *conn, order_id, vendor_id, "credit_note", &vendor_lines, reason).await?);
+    }
+    Ok(credit_notes)
+}
+
+fn group_by_vendor(lines: Vec<(Uuid, LineInput)>) -> BTreeMap<Uuid, Vec<LineInput>> {
+    let mut groups: BTreeMap<Uuid, Vec<LineInput>> = BTreeMap::new();
+    for (vendor_id, line) in lines {
+        groups.entry(vendor_id).or_default().push(line);
+    }
+    groups
+}
+
+/// Units of each line item that have not been cancelled, keyed by vendor
+pub async fn active_item_lines(conn: &mut PgConnection, order_id: Uuid) -> Result<Vec<(Uuid, LineInput)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (Uuid, String, i32, BigDecimal)>(
+        r#"
+        SELECT oi.vendor_id, p.name, oi.quantity - oi.cancelled_quantity, oi.price
+        FROM order_items oi
+        JOIN products p ON oi.product_id = p.id
+        WHERE oi.order_id = $1 AND oi.cancelled_quantity < oi.quantity
+        ORDER BY oi.id
+        "#,
+    )
+    .bind(order_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(vendor_id, description, quantity, unit_price)| {
+            (vendor_id, LineInput { description, quantity, unit_price })
+        })
+        .collect())
+}
+
+/// Shipping charged to each vendor group of an order, or to one vendor only
+pub async fn shipping_lines(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    vendor_id: Option<Uuid>,
+) -> Result<Vec<(Uuid, LineInput)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (Uuid, String, BigDecimal)>(
+        r#"
+        SELECT vendor_id, method_name, cost
+        FROM order_shipping
+        WHERE order_id = $1 AND cost > 0 AND ($2::UUID IS NULL OR vendor_id = $2)
+        "#,
+    )
+    .bind(order_id)
+    .bind(vendor_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(vendor_id, method_name, cost)| {
+            (vendor_id, LineInput {
+                description: format!("Shipping: {}", method_name),
+                quantity: 1,
+                unit_price: cost,
+            })
+        })
+        .collect())
+}
+
+/// Load invoices and credit notes for an order, optionally for one vendor only
+pub async fn fetch_documents(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    vendor_id: Option<Uuid>,
+) -> Result<Vec<InvoiceDocument>, sqlx::Error> {
+    let invoices = sqlx::query_as::<_, Invoice>(
+        r#"
+        SELECT id, order_id, vendor_id, kind, number, document_number, invoice_id,
+               seller_name, seller_email, buyer_name, buyer_email, buyer_address,
+               subtotal, tax_rate, tax_amount, total, reason, issued_at
+        FROM invoices
+        WHERE order_id = $1 AND ($2::uuid IS NULL OR vendor_id = $2)
+        ORDER BY issued_at, document_number
+        "#,
+    )
+    .bind(order_id)
+    .bind(vendor_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut documents = Vec::new();
+    for invoice in invoices {
+        let lines = sqlx::query_as::<_, InvoiceLine>(
+            "SELECT description, quantity, unit_price, total FROM invoice_lines WHERE invoice_id = $1 ORDER BY line_number",
+        )
+        .bind(invoice.id)
+        .fetch_all(&mut *conn)
+        .await?;
+        documents.push(InvoiceDocument { invoice, lines });
+    }
+    Ok(documents)
+}
+
+fn title(invoice: &Invoice) -> &'static str {
+    if invoice.kind == "credit_note" { "Credit Note" } else { "Invoice" }
+}
+
+fn money(amount: &BigDecimal) -> String {
+    amount.with_scale(2).to_string()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Render documents as a standalone HTML page
+pub fn render_html(documents: &[InvoiceDocument]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Invoices</title>\
+         <style>body{font-family:sans-serif;margin:2em}section{margin-bottom:3em}\
+         table{border-collapse:collapse;width:100%}th,td{border-bottom:1px solid #ddd;padding:4px;text-align:left}\
+         .num{text-align:right}</style></head><body>\n",
+    );
+
+    for doc in documents {
+        let invoice = &doc.invoice;
+        html.push_str(&format!(
+            "<section>\n<h1>{} {}</h1>\n<p>Order {}<br>Issued {}</p>\n",
+            title(invoice),
+            escape_html(&invoice.document_number),
+            invoice.order_id,
+            invoice.issued_at.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+        ));
+        html.push_str(&format!(
+            "<p><strong>Seller</strong><br>{}<br>{}</p>\n<p><strong>Bill to</strong><br>{}<br>{}<br>{}</p>\n",
+            escape_html(&invoice.seller_name),
+            escape_html(&invoice.seller_email),
+            escape_html(&invoice.buyer_name),
+            escape_html(&invoice.buyer_email),
+            escape_html(invoice.buyer_address.as_deref().unwrap_or("")).replace('\n', "<br>"),
+        ));
+        if let Some(reason) = &invoice.reason {
+            html.push_str(&format!("<p>Reason: {}</p>\n", escape_html(reason)));
+        }
+
+        html.push_str("<table>\n<tr><th>Description</th><th class=\"num\">Qty</th><th class=\"num\">Unit price</th><th class=\"num\">Total</th></tr>\n");
+        for line in &doc.lines {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+                escape_html(&line.description),
+                line.quantity,
+                money(&line.unit_price),
+                money(&line.total),
+            ));
+        }
+        html.push_str(&format!(
+            "<tr><td colspan=\"3\">Subtotal (excl. tax)</td><td class=\"num\">{}</td></tr>\n\
+             <tr><td colspan=\"3\">Tax ({}%)</td><td class=\"num\">{}</td></tr>\n\
+             <tr><th colspan=\"3\">Total</th><th class=\"num\">{}</th></tr>\n</table>\n</section>\n",
+            money(&invoice.subtotal),
+            (&invoice.tax_rate * BigDecimal::from(100)).normalized(),
+            money(&invoice.tax_amount),
+            money(&invoice.total),
+        ));
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// A4 page size, and where text starts and must stop on it, in millimetres
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const PAGE_TOP: f32 = 275.0;
+const PAGE_BOTTOM: f32 = 20.0;
+
+/// Render documents as a PDF. Each document starts on a new page and long
+/// ones run onto further pages.
+pub fn render_pdf(documents: &[InvoiceDocument]) -> Result<Vec<u8>, printpdf::Error> {
+    let (doc, first_page, first_layer) = PdfDocument::new("Invoices", Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let line = |layer: &PdfLayerReference, text: String, size: f32, x: f32, y: f32, bold_text: bool| {
+        layer.use_text(text, size, Mm(x), Mm(y), if bold_text { &bold } else { &font });
+    };
+    let item_header = |layer: &PdfLayerReference, y: f32| {
+        line(layer, "Description".into(), 10.0, 20.0, y, true);
+        line(layer, "Qty".into(), 10.0, 120.0, y, true);
+        line(layer, "Unit price".into(), 10.0, 140.0, y, true);
+        line(layer, "Total".into(), 10.0, 170.0, y, true);
+    };
+    // Lines that don't fit continue on a new page under the document number
+    let next_page = |invoice: &Invoice| {
+        let (page, layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let layer = doc.get_page(page).get_layer(layer);
+        let y = PAGE_TOP;
+        line(&layer, format!("{} {} (continued)", title(invoice), invoice.document_number), 11.0, 20.0, y, true);
+        (layer, y - 10.0)
+    };
+
+    for (index, document) in documents.iter().enumerate() {
+        let (page, layer) = if index == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1")
+        };
+        let mut layer = doc.get_page(page).get_layer(layer);
+        let invoice = &document.invoice;
+
+        let mut y = PAGE_TOP;
+        line(&layer, format!("{} {}", title(invoice), invoice.document_number), 18.0, 20.0, y, true);
+        y -= 10.0;
+        line(&layer, format!("Order {}", invoice.order_id), 10.0, 20.0, y, false);
+        y -= 5.0;
+        line(
+            &layer,
+            format!("Issued {}", invoice.issued_at.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default()),
+            10.0, 20.0, y, false,
+        );
+        y -= 12.0;
+        line(&layer, "Seller".into(), 11.0, 20.0, y, true);
+        line(&layer, "Bill to".into(), 11.0, 110.0, y, true);
+        y -= 5.0;
+        line(&layer, invoice.seller_name.clone(), 10.0, 20.0, y, false);
+        line(&layer, invoice.buyer_name.clone(), 10.0, 110.0, y, false);
+        y -= 5.0;
+        line(&layer, invoice.seller_email.clone(), 10.0, 20.0, y, false);
+        line(&layer, invoice.buyer_email.clone(), 10.0, 110.0, y, false);
+        for address_line in invoice.buyer_address.as_deref().unwrap_or("").lines() {
+            y -= 5.0;
+            line(&layer, address_line.to_string(), 10.0, 110.0, y, false);
+        }
+        if let Some(reason) = &invoice.reason {
+            y -= 8.0;
+            line(&layer, format!("Reason: {}", reason), 10.0, 20.0, y, false);
+        }
+
+        y -= 14.0;
+        item_header(&layer, y);
+        for item in &document.lines {
+            y -= 6.0;
+            if y < PAGE_BOTTOM {
+                (layer, y) = next_page(invoice);
+                item_header(&layer, y);
+                y -= 6.0;
+            }
+            line(&layer, item.description.clone(), 10.0, 20.0, y, false);
+            line(&layer, item.quantity.to_string(), 10.0, 120.0, y, false);
+            line(&layer, money(&item.unit_price), 10.0, 140.0, y, false);
+            line(&layer, money(&item.total), 10.0, 170.0, y, false);
+        }
+
+        // The totals block is kept together
+        y -= 12.0;
+        if y - 12.0 < PAGE_BOTTOM {
+            (layer, y) = next_page(invoice);
+        }
+        line(&layer, "Subtotal (excl. tax)".into(), 10.0, 120.0, y, false);
+        line(&layer, money(&invoice.subtotal), 10.0, 170.0, y, false);
+        y -= 6.0;
+        line(&layer, format!("Tax ({}%)", (&invoice.tax_rate * BigDecimal::from(100)).normalized()), 10.0, 120.0, y, false);
+        line(&layer, money(&invoice.tax_amount), 10.0, 170.0, y, false);
+        y -= 6.0;
+        line(&layer, "Total".into(), 11.0, 120.0, y, true);
+        line(&layer, money(&invoice.total), 11.0, 170.0, y, true);
+    }
+
+    doc.save_to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(line_count: usize) -> InvoiceDocument {
+        InvoiceDocument {
+            invoice: Invoice {
+                id: Uuid::nil(),
+                order_id: Uuid::nil(),
+                vendor_id: Uuid::nil(),
+                kind: "invoice".into(),
+                number: 1,
+                document_number: "INV-00000000-000001".into(),
+                invoice_id: None,
+                seller_name: "Seller".into(),
+                seller_email: "seller@example.com".into(),
+                buyer_name: "Buyer".into(),
+                buyer_email: "buyer@example.com".into(),
+                buyer_address: Some("1 Main St\nTown".into()),
+                subtotal: BigDecimal::from(10),
+                tax_rate: BigDecimal::from(0),
+                tax_amount: BigDecimal::from(0),
+                total: BigDecimal::from(10),
+                reason: None,
+                issued_at: None,
+            },
+            lines: (0..line_count)
+                .map(|i| InvoiceLine {
+                    description: format!("Item {}", i),
+                    quantity: 1,
+                    unit_price: BigDecimal::from(1),
+                    total: BigDecimal::from(1),
+                })
+                .collect(),
+        }
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(pdf);
+        text.matches("/Type/Page/").count()
+    }
+
+    #[test]
+    fn short_invoice_fits_on_one_page() {
+        let pdf = render_pdf(&[document(3)]).unwrap();
+        assert_eq!(page_count(&pdf), 1);
+    }
+
+    #[test]
+    fn long_invoice_continues_on_new_pages() {
+        let pdf = render_pdf(&[document(100)]).unwrap();
+        assert_eq!(page_count(&pdf), 3);
+    }
+
+    #[test]
+    fn each_document_starts_a_new_page() {
+        let pdf = render_pdf(&[document(3), document(3)]).unwrap();
+        assert_eq!(page_count(&pdf), 2);
+    }
+}
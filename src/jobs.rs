@@ -0,0 +1,406 @@
+//! Durable background jobs stored in Postgres.
+//!
+//! `enqueue` adds a job, optionally in the caller's transaction so it only runs
+//! if the surrounding change commits. The runner started from `main` claims due
+//! jobs with `FOR UPDATE SKIP LOCKED`, so any number of instances can share the
+//! queue. Failed jobs are retried with backoff, as are jobs whose worker stopped
+//! mid-run; after `max_attempts` they are marked "dead" and left in the table
+//! for inspection. Recurring jobs are
+//! described by `ScheduledJob` and tracked in `job_schedules`.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow, PgExecutor, PgPool};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A job still "running" after this long is assumed lost with its worker and retried
+const STALE_AFTER_SECS: f64 = 900.0;
+
+/// A claimed job handed to its handler
+#[derive(Debug, Clone, FromRow)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: Json<Value>,
+    pub attempts: i32,  // Including the current one
+    pub max_attempts: i32,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Runs every job of one kind
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    async fn run(&self, job: &Job) -> Result<(), JobError>;
+}
+
+/// A job enqueued on a cron schedule (`sec min hour day-of-month month day-of-week`, UTC)
+pub struct ScheduledJob {
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub cron: &'static str,
+    pub payload: Value,
+}
+
+/// Add a job to run as soon as a worker is free. With a `unique_key`, enqueueing
+/// the same key again is a no-op, which makes retried callers safe.
+pub async fn enqueue<'e>(
+    executor: impl PgExecutor<'e>,
+    kind: &str,
+    payload: Value,
+    unique_key: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO jobs (kind, payload, unique_key)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (unique_key) DO NOTHING
+        "#,
+    )
+    .bind(kind)
+    .bind(Json(payload))
+    .bind(unique_key)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+fn next_run(cron: &str, after: DateTime<Utc>) -> DateTime<Utc> {
+    let schedule = cron::Schedule::from_str(cron)
+        .unwrap_or_else(|e| panic!("Invalid cron expression '{}': {}", cron, e));
+    schedule
+        .after(&after)
+        .next()
+        .unwrap_or_else(|| panic!("Cron expression '{}' never fires", cron))
+}
+
+/// Start the job runner on a background task
+pub fn spawn_runner(db: Arc<PgPool>, handlers: Vec<Arc<dyn JobHandler>>, schedules: Vec<ScheduledJob>) {
+    let handlers: HashMap<&'static str, Arc<dyn JobHandler>> =
+        handlers.into_iter().map(|handler| (handler.kind(), handler)).collect();
+
+    tokio::spawn(async move {
+        if let Err(e) = register_schedules(&db, &schedules).await {
+            println!("Failed to register job schedules: {:?}", e);
+        }
+
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = enqueue_due_schedules(&db).await {
+                println!("Failed to enqueue scheduled jobs: {:?}", e);
+            }
+            if let Err(e) = bury_stale_jobs(&db).await {
+                println!("Failed to dead-letter stale jobs: {:?}", e);
+            }
+            // Work through everything that is due, then wait for the next tick
+            loop {
+                match run_next(&db, &handlers).await {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(e) => {
+                        println!("Job runner failed: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Create or update the schedule rows. A changed cron expression takes effect
+/// from now; an unchanged one keeps its pending run.
+async fn register_schedules(db: &PgPool, schedules: &[ScheduledJob]) -> Result<(), sqlx::Error> {
+    for schedule in schedules {
+        sqlx::query(
+            r#"
+            INSERT INTO job_schedules (name, kind, cron, payload, next_run_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (name) DO UPDATE
+            SET kind = EXCLUDED.kind,
+                payload = EXCLUDED.payload,
+                next_run_at = CASE WHEN job_schedules.cron = EXCLUDED.cron
+                                   THEN job_schedules.next_run_at ELSE EXCLUDED.next_run_at END,
+                cron = EXCLUDED.cron
+            "#,
+        )
+        .bind(schedule.name)
+        .bind(schedule.kind)
+        .bind(schedule.cron)
+        .bind(Json(&schedule.payload))
+        .bind(next_run(schedule.cron, Utc::now()))
+        .execute(db)
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(FromRow)]
+struct DueSchedule {
+    name: String,
+    kind: String,
+    cron: String,
+    payload: Json<Value>,
+    next_run_at: DateTime<Utc>,
+}
+
+/// Enqueue one job per due schedule. Runs missed while the server was down are
+/// collapsed into a single job rather than replayed one by one.
+async fn enqueue_due_schedules(db: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let due = sqlx::query_as::<_, DueSchedule>(
+        r#"
+        SELECT name, kind, cron, payload, next_run_at
+        FROM job_schedules
+        WHERE next_run_at <= NOW()
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for schedule in due {
+        let unique_key = format!("schedule:{}:{}", schedule.name, schedule.next_run_at.timestamp());
+        enqueue(&mut *tx, &schedule.kind, schedule.payload.0, Some(&unique_key)).await?;
+
+        sqlx::query("UPDATE job_schedules SET next_run_at = $2, last_run_at = NOW() WHERE name = $1")
+            .bind(&schedule.name)
+            .bind(next_run(&schedule.cron, Utc::now()))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Move stale running jobs that have used up their attempts to the dead
+/// letters; `run_next` only reclaims the ones with attempts left
+async fn bury_stale_jobs(db: &PgPool) -> Result<(), sqlx::Error> {
+    let buried: Vec<(Uuid, String, i32)> = sqlx::query_as(
+        r#"
+        UPDATE jobs
+        SET status = 'dead', finished_at = NOW(),
+            last_error = 'Worker stopped responding during the last attempt'
+        WHERE status = 'running' AND attempts >= max_attempts
+          AND locked_at < NOW() - make_interval(secs => $1)
+        RETURNING id, kind, attempts
+        "#,
+    )
+    .bind(STALE_AFTER_SECS)
+    .fetch_all(db)
+    .await?;
+
+    for (id, kind, attempts) in buried {
+        println!("Job {} ({}) moved to dead letters after {} attempts: worker stopped responding", id, kind, attempts);
+    }
+    Ok(())
+}
+
+/// Claim and run one due job. Returns false when the queue is empty.
+async fn run_next(db: &PgPool, handlers: &HashMap<&'static str, Arc<dyn JobHandler>>) -> Result<bool, sqlx::Error> {
+    // Claiming commits straight away so the job isn't holding a transaction while it runs
+    let job = sqlx::query_as::<_, Job>(
+        r#"
+        UPDATE jobs
+        SET status = 'running', attempts = attempts + 1, locked_at = NOW()
+        WHERE id = (
+            SELECT id FROM jobs
+            WHERE (status = 'pending' AND run_at <= NOW())
+               OR (status = 'running' AND attempts < max_attempts
+                   AND locked_at < NOW() - make_interval(secs => $1))
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, kind, payload, attempts, max_attempts, created_at
+        "#,
+    )
+    .bind(STALE_AFTER_SECS)
+    .fetch_optional(db)
+    .await?;
+
+    let Some(job) = job else {
+        return Ok(false);
+    };
+
+    let result = match handlers.get(job.kind.as_str()) {
+        Some(handler) => handler.run(&job).await,
+        None => Err(format!("No handler registered for job kind '{}'", job.kind).into()),
+    };
+
+    match result {
+        Ok(()) => {
+            sqlx::query("UPDATE jobs SET status = 'succeeded', last_error = NULL, finished_at = NOW() WHERE id = $1")
+                .bind(job.id)
+                .execute(db)
+                .await?;
+        }
+        Err(e) if job.attempts >= job.max_attempts => {
+            println!("Job {} ({}) moved to dead letters after {} attempts: {}", job.id, job.kind, job.attempts, e);
+            sqlx::query("UPDATE jobs SET status = 'dead', last_error = $2, finished_at = NOW() WHERE id = $1")
+                .bind(job.id)
+                .bind(e.to_string())
+                .execute(db)
+                .await?;
+        }
+        Err(e) => {
+            println!("Job {} ({}) failed (attempt {}): {}", job.id, job.kind, job.attempts, e);
+            // Exponential backoff: 10s, 20s, 40s, ... capped at an hour
+            let delay_secs = (10 * 2_i64.pow((job.attempts - 1).min(20) as u32)).min(3600);
+            sqlx::query(
+                r#"
+                UPDATE jobs
+                SET status = 'pending', last_error = $2, run_at = NOW() + make_interval(secs => $3)
+                WHERE id = $1
+                "#,
+            )
+            .bind(job.id)
+            .bind(e.to_string())
+            .bind(delay_secs as f64)
+            .execute(db)
+            .await?;
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::load_schema;
+    use serde_json::json;
+
+    /// Fails every job while `fails` is set
+    struct TestHandler {
+        fails: bool,
+    }
+
+    #[async_trait]
+    impl JobHandler for TestHandler {
+        fn kind(&self) -> &'static str {
+            "test.job"
+        }
+
+        async fn run(&self, _job: &Job) -> Result<(), JobError> {
+            if self.fails {
+                return Err("unavailable".into());
+            }
+            Ok(())
+        }
+    }
+
+    fn handlers(fails: bool) -> HashMap<&'static str, Arc<dyn JobHandler>> {
+        HashMap::from([("test.job", Arc::new(TestHandler { fails }) as Arc<dyn JobHandler>)])
+    }
+
+    async fn enqueue_job(pool: &PgPool) -> Uuid {
+        load_schema(pool).await;
+        enqueue(pool, "test.job", json!({}), None).await.unwrap();
+        sqlx::query_scalar("SELECT id FROM jobs").fetch_one(pool).await.unwrap()
+    }
+
+    #[derive(FromRow)]
+    struct JobState {
+        status: String,
+        attempts: i32,
+        delay_secs: f64,
+    }
+
+    async fn job_state(pool: &PgPool, job_id: Uuid) -> JobState {
+        sqlx::query_as(
+            "SELECT status, attempts, EXTRACT(EPOCH FROM run_at - NOW())::FLOAT8 AS delay_secs FROM jobs WHERE id = $1",
+        )
+        .bind(job_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    /// Pretend the job's worker claimed it `secs` ago on attempt `attempts` and vanished
+    async fn abandon(pool: &PgPool, job_id: Uuid, attempts: i32, secs: f64) {
+        sqlx::query(
+            "UPDATE jobs SET status = 'running', attempts = $2, locked_at = NOW() - make_interval(secs => $3) WHERE id = $1",
+        )
+        .bind(job_id)
+        .bind(attempts)
+        .bind(secs)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn failed_jobs_back_off_then_are_dead_lettered(pool: PgPool) {
+        let job_id = enqueue_job(&pool).await;
+        let failing = handlers(true);
+
+        for (attempt, backoff) in [(1, 10.0), (2, 20.0), (3, 40.0), (4, 80.0)] {
+            assert!(run_next(&pool, &failing).await.unwrap());
+            let state = job_state(&pool, job_id).await;
+            assert_eq!((state.status.as_str(), state.attempts), ("pending", attempt));
+            assert!(state.delay_secs > backoff - 1.0 && state.delay_secs <= backoff, "{}", state.delay_secs);
+
+            // Not due yet
+            assert!(!run_next(&pool, &failing).await.unwrap());
+            sqlx::query("UPDATE jobs SET run_at = NOW() WHERE id = $1")
+                .bind(job_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        assert!(run_next(&pool, &failing).await.unwrap());
+        let state = job_state(&pool, job_id).await;
+        assert_eq!((state.status.as_str(), state.attempts), ("dead", 5));
+        assert!(!run_next(&pool, &failing).await.unwrap());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn jobs_claimed_by_another_worker_are_skipped(pool: PgPool) {
+        let job_id = enqueue_job(&pool).await;
+        let mut other = pool.begin().await.unwrap();
+        sqlx::query("SELECT id FROM jobs WHERE id = $1 FOR UPDATE")
+            .bind(job_id)
+            .execute(&mut *other)
+            .await
+            .unwrap();
+
+        assert!(!run_next(&pool, &handlers(false)).await.unwrap());
+
+        other.rollback().await.unwrap();
+        assert!(run_next(&pool, &handlers(false)).await.unwrap());
+        assert_eq!(job_state(&pool, job_id).await.status, "succeeded");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn stale_running_jobs_are_retried_until_out_of_attempts(pool: PgPool) {
+        let job_id = enqueue_job(&pool).await;
+
+        // Still within its time, the worker may yet finish
+        abandon(&pool, job_id, 2, STALE_AFTER_SECS - 60.0).await;
+        assert!(!run_next(&pool, &handlers(false)).await.unwrap());
+
+        abandon(&pool, job_id, 2, STALE_AFTER_SECS + 60.0).await;
+        assert!(run_next(&pool, &handlers(false)).await.unwrap());
+        let state = job_state(&pool, job_id).await;
+        assert_eq!((state.status.as_str(), state.attempts), ("succeeded", 3));
+
+        // Lost on its last attempt: dead-lettered rather than run a sixth time
+        abandon(&pool, job_id, 5, STALE_AFTER_SECS + 60.0).await;
+        assert!(!run_next(&pool, &handlers(false)).await.unwrap());
+        bury_stale_jobs(&pool).await.unwrap();
+        let state = job_state(&pool, job_id).await;
+        assert_eq!((state.status.as_str(), state.attempts), ("dead", 5));
+    }
+}
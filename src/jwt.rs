@@ -0,0 +1,396 @@
+//! Asymmetric signing keys for session tokens.
+//!
+//! Keys are PEM files in `JWT_KEYS_DIR` (default `keys`), named after their key
+//! id: `<kid>.pem` holds a private key (RSA for RS256, Ed25519 for EdDSA) and
+//! `<kid>.pub.pem` a public key kept only to verify tokens it already signed.
+//! `JWT_ACTIVE_KID` picks the private key that signs new tokens. Every key
+//! verifies tokens carrying its `kid` and is published at
+//! `/.well-known/jwks.json`, so other services can check our tokens without a
+//! shared secret. To rotate, add a new key, make it active, and remove the old
+//! one once the tokens it signed have expired.
+//!
+//! Startup fails without a signing key, unless `JWT_ALLOW_EPHEMERAL_KEY` is set
+//! for development, in which case a temporary key is generated.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, DecodePrivateKey, DecodePublicKey, EncodePrivateKey};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{Error, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters,
+        OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rsa::{
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    traits::PublicKeyParts,
+    RsaPrivateKey, RsaPublicKey,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+/// The key new tokens are signed with
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+/// Signs session tokens with the active key and verifies them against every known key
+pub struct KeyRing {
+    signing: SigningKey,
+    verification: HashMap<String, (Algorithm, DecodingKey)>,
+    jwks: JwkSet,
+    issuer: String,
+    audience: String,
+}
+
+fn jwk(kid: &str, algorithm: KeyAlgorithm, parameters: AlgorithmParameters) -> Jwk {
+    Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    }
+}
+
+fn rsa_jwk(kid: &str, key: &RsaPublicKey) -> Jwk {
+    jwk(
+        kid,
+        KeyAlgorithm::RS256,
+        AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }),
+    )
+}
+
+fn ed25519_jwk(kid: &str, key: &ed25519_dalek::VerifyingKey) -> Jwk {
+    jwk(
+        kid,
+        KeyAlgorithm::EdDSA,
+        AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(key.to_bytes()),
+        }),
+    )
+}
+
+/// Parse a private key file into its signing key and public JWK
+fn load_private_key(kid: &str, pem: &str) -> Result<(SigningKey, Jwk), String> {
+    let rsa_key = RsaPrivateKey::from_pkcs8_pem(pem).or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem));
+    let (algorithm, key, public) = if let Ok(rsa_key) = rsa_key {
+        let key = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        (Algorithm::RS256, key, rsa_jwk(kid, &rsa_key.to_public_key()))
+    } else if let Ok(ed_key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+        let key = EncodingKey::from_ed_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        (Algorithm::EdDSA, key, ed25519_jwk(kid, &ed_key.verifying_key()))
+    } else {
+        return Err("not an RSA or Ed25519 private key".into());
+    };
+    Ok((
+        SigningKey {
+            kid: kid.to_string(),
+            algorithm,
+            key,
+        },
+        public,
+    ))
+}
+
+/// Parse a public key file into its JWK
+fn load_public_key(kid: &str, pem: &str) -> Result<Jwk, String> {
+    if let Ok(key) = RsaPublicKey::from_public_key_pem(pem).or_else(|_| RsaPublicKey::from_pkcs1_pem(pem)) {
+        return Ok(rsa_jwk(kid, &key));
+    }
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+        return Ok(ed25519_jwk(kid, &key));
+    }
+    Err("not an RSA or Ed25519 public key".into())
+}
+
+/// A throwaway Ed25519 key for development, when no keys are configured
+fn ephemeral_key() -> (SigningKey, Jwk) {
+    let key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+    let pem = key.to_pkcs8_pem(LineEnding::LF).expect("Ed25519 key encodes as PKCS#8");
+    let kid = format!("ephemeral-{}", &hex::encode(key.verifying_key().to_bytes())[..8]);
+    load_private_key(&kid, &pem).expect("Generated key loads")
+}
+
+impl KeyRing {
+    /// Load the keys described in the module docs. Panics on unreadable or
+    /// invalid key files, like other startup configuration errors, and when
+    /// there are no keys at all unless `JWT_ALLOW_EPHEMERAL_KEY` opts into a
+    /// temporary one for development.
+    pub fn from_env() -> KeyRing {
+        let dir = env::var("JWT_KEYS_DIR").unwrap_or_else(|_| "keys".into());
+        let active_kid = env::var("JWT_ACTIVE_KID").ok();
+        let allow_ephemeral = env::var("JWT_ALLOW_EPHEMERAL_KEY").is_ok_and(|v| v == "true" || v == "1");
+        KeyRing::from_dir(&dir, active_kid.as_deref(), allow_ephemeral)
+    }
+
+    /// A ring with only a throwaway signing key, for development and tests
+    pub fn ephemeral() -> KeyRing {
+        KeyRing::new(vec![ephemeral_key()], 0, Vec::new())
+    }
+
+    fn from_dir(dir: &str, active_kid: Option<&str>, allow_ephemeral: bool) -> KeyRing {
+        let mut private_keys = Vec::new();
+        let mut public_keys = Vec::new();
+
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let pem = || {
+                    fs::read_to_string(&path)
+                        .unwrap_or_else(|e| panic!("Failed to read JWT key {}: {}", path.display(), e))
+                };
+                if let Some(kid) = file_name.strip_suffix(".pub.pem") {
+                    let jwk = load_public_key(kid, &pem())
+                        .unwrap_or_else(|e| panic!("Invalid JWT key {}: {}", path.display(), e));
+                    public_keys.push(jwk);
+                } else if let Some(kid) = file_name.strip_suffix(".pem") {
+                    let key = load_private_key(kid, &pem())
+                        .unwrap_or_else(|e| panic!("Invalid JWT key {}: {}", path.display(), e));
+                    private_keys.push(key);
+                }
+            }
+        }
+
+        let signing_index = match active_kid {
+            Some(kid) => private_keys
+                .iter()
+                .position(|(key, _)| key.kid == kid)
+                .unwrap_or_else(|| panic!("JWT_ACTIVE_KID '{}' has no private key in {}", kid, dir)),
+            None if private_keys.len() == 1 => 0,
+            None if private_keys.is_empty() && allow_ephemeral => {
+                println!(
+                    "No JWT signing keys in {}; using a temporary key. Tokens won't survive a restart",
+                    dir
+                );
+                private_keys.push(ephemeral_key());
+                0
+            }
+            None if private_keys.is_empty() => panic!(
+                "No JWT signing keys in {}; add one, or set JWT_ALLOW_EPHEMERAL_KEY=true for development",
+                dir
+            ),
+            None => panic!("Several JWT private keys in {}; set JWT_ACTIVE_KID to choose one", dir),
+        };
+
+        KeyRing::new(private_keys, signing_index, public_keys)
+    }
+
+    fn new(private_keys: Vec<(SigningKey, Jwk)>, signing_index: usize, public_keys: Vec<Jwk>) -> KeyRing {
+        let mut jwks = Vec::new();
+        let mut signing = None;
+        for (index, (key, public)) in private_keys.into_iter().enumerate() {
+            if index == signing_index {
+                signing = Some(key);
+            }
+            jwks.push(public);
+        }
+        jwks.extend(public_keys);
+
+        let verification = jwks
+            .iter()
+            .map(|jwk| {
+                let kid = jwk.common.key_id.clone().expect("Every JWK has a kid");
+                let algorithm = match jwk.algorithm {
+                    AlgorithmParameters::RSA(_) => Algorithm::RS256,
+                    _ => Algorithm::EdDSA,
+                };
+                let key = DecodingKey::from_jwk(jwk).expect("Loaded keys convert to decoding keys");
+                (kid, (algorithm, key))
+            })
+            .collect();
+
+        KeyRing {
+            signing: signing.expect("Signing key was selected"),
+            verification,
+            jwks: JwkSet { keys: jwks },
+            issuer: env::var("JWT_ISSUER").unwrap_or_else(|_| "shop".into()),
+            audience: env::var("JWT_AUDIENCE").unwrap_or_else(|_| "shop-api".into()),
+        }
+    }
+
+    /// Value of the `iss` claim in our tokens
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Value of the `aud` claim in our tokens
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    /// The public keys, for `/.well-known/jwks.json`
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    /// Sign claims with the active key, naming it in the `kid` header
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let mut header = Header::new(self.signing.algorithm);
+        header.kid = Some(self.signing.kid.clone());
+        encode(&header, claims, &self.signing.key)
+    }
+
+    /// Verify a token's signature against the key named by its `kid`, using
+    /// only that key's algorithm, and check `exp`, `iss` and `aud`
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, Error> {
+        let kid = decode_header(token)?.kid.ok_or(Error::from(ErrorKind::InvalidToken))?;
+        let (algorithm, key) = self.verification.get(&kid).ok_or(Error::from(ErrorKind::InvalidToken))?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        decode::<T>(token, key, &validation).map(|decoded| decoded.claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::pkcs8::EncodePublicKey;
+    use serde::Deserialize;
+    use std::path::PathBuf;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        iss: String,
+        aud: String,
+        exp: i64,
+    }
+
+    fn claims(ring: &KeyRing, exp: i64) -> TestClaims {
+        TestClaims {
+            sub: "alice".into(),
+            iss: ring.issuer().into(),
+            aud: ring.audience().into(),
+            exp,
+        }
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    /// An empty key directory that is removed when dropped
+    struct KeyDir(PathBuf);
+
+    impl KeyDir {
+        fn new() -> KeyDir {
+            let dir = env::temp_dir().join(format!("jwt-keys-{}", uuid::Uuid::new_v4()));
+            fs::create_dir(&dir).unwrap();
+            KeyDir(dir)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+
+        /// Write a new Ed25519 key as `<kid>.pem`, returning its public PEM
+        fn add_private_key(&self, kid: &str) -> String {
+            let key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+            fs::write(self.0.join(format!("{}.pem", kid)), key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes()).unwrap();
+            key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap()
+        }
+    }
+
+    impl Drop for KeyDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn signed_tokens_verify() {
+        let ring = KeyRing::ephemeral();
+        let signed = claims(&ring, in_an_hour());
+        let token = ring.sign(&signed).unwrap();
+
+        assert_eq!(ring.verify::<TestClaims>(&token).unwrap(), signed);
+    }
+
+    #[test]
+    fn tokens_for_another_issuer_or_audience_are_rejected() {
+        let ring = KeyRing::ephemeral();
+        let wrong_issuer = TestClaims { iss: "someone-else".into(), ..claims(&ring, in_an_hour()) };
+        let wrong_audience = TestClaims { aud: "another-api".into(), ..claims(&ring, in_an_hour()) };
+
+        let error = ring.verify::<TestClaims>(&ring.sign(&wrong_issuer).unwrap()).unwrap_err();
+        assert_eq!(error.kind(), &ErrorKind::InvalidIssuer);
+        let error = ring.verify::<TestClaims>(&ring.sign(&wrong_audience).unwrap()).unwrap_err();
+        assert_eq!(error.kind(), &ErrorKind::InvalidAudience);
+    }
+
+    #[test]
+    fn tokens_signed_with_an_unknown_key_are_rejected() {
+        let ring = KeyRing::ephemeral();
+        let other = KeyRing::ephemeral();
+        let token = other.sign(&claims(&other, in_an_hour())).unwrap();
+
+        let error = ring.verify::<TestClaims>(&token).unwrap_err();
+        assert_eq!(error.kind(), &ErrorKind::InvalidToken);
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let ring = KeyRing::ephemeral();
+        let token = ring.sign(&claims(&ring, chrono::Utc::now().timestamp() - 3600)).unwrap();
+
+        let error = ring.verify::<TestClaims>(&token).unwrap_err();
+        assert_eq!(error.kind(), &ErrorKind::ExpiredSignature);
+    }
+
+    #[test]
+    fn tokens_from_a_rotated_out_key_verify_while_it_is_published() {
+        let old_dir = KeyDir::new();
+        let old_public = old_dir.add_private_key("2025-01");
+        let old_ring = KeyRing::from_dir(old_dir.path(), None, false);
+        let token = old_ring.sign(&claims(&old_ring, in_an_hour())).unwrap();
+
+        // The new key signs; the old one is only kept as a public key
+        let new_dir = KeyDir::new();
+        new_dir.add_private_key("2025-07");
+        fs::write(new_dir.0.join("2025-01.pub.pem"), old_public).unwrap();
+        let new_ring = KeyRing::from_dir(new_dir.path(), Some("2025-07"), false);
+
+        assert!(new_ring.verify::<TestClaims>(&token).is_ok());
+        let mut kids: Vec<_> = new_ring.jwks().keys.iter().filter_map(|jwk| jwk.common.key_id.clone()).collect();
+        kids.sort();
+        assert_eq!(kids, ["2025-01", "2025-07"]);
+        let new_token = new_ring.sign(&claims(&new_ring, in_an_hour())).unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("2025-07"));
+    }
+
+    #[test]
+    #[should_panic(expected = "No JWT signing keys")]
+    fn startup_fails_without_keys() {
+        let dir = KeyDir::new();
+        KeyRing::from_dir(dir.path(), None, false);
+    }
+
+    #[test]
+    fn temporary_key_needs_opting_in() {
+        let dir = KeyDir::new();
+        let ring = KeyRing::from_dir(dir.path(), None, true);
+
+        assert_eq!(ring.jwks().keys.len(), 1);
+    }
+}
@@ -0,0 +1,397 @@
+//! Double-entry ledger of the money the platform holds for vendors. Every sale,
+//! refund and payout is posted as one transaction whose entries sum to zero
+//! (debits positive, credits negative), in the same database transaction as the
+//! invoice or payout it records. A vendor's balance is the credit balance of
+//! their `vendor_payable` account.
+
+use bigdecimal::BigDecimal;
+use sqlx::{PgConnection, PgExecutor};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::models::Invoice::Invoice;
+use crate::models::Ledger::VendorBalance;
+
+/// Money the platform holds: customer payments in, refunds and payouts out
+pub const PLATFORM_CASH: &str = "platform_cash";
+/// What the platform owes a vendor
+pub const VENDOR_PAYABLE: &str = "vendor_payable";
+/// The platform's commission
+pub const COMMISSION_REVENUE: &str = "commission_revenue";
+
+/// Days a sale's proceeds are held back from payouts so refunds can still come
+/// out of them, from `PAYOUT_HOLD_DAYS` (default 14)
+pub fn payout_hold_days() -> i32 {
+    env::var("PAYOUT_HOLD_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(14)
+}
+
+/// Smallest available balance a payout run pays out, from `PAYOUT_MINIMUM` (default 10)
+pub fn payout_minimum() -> BigDecimal {
+    parse_payout_minimum(env::var("PAYOUT_MINIMUM").ok().as_deref())
+}
+
+/// A zero or negative minimum would pay out empty or overdrawn balances, so
+/// those fall back to the default like unparseable values
+fn parse_payout_minimum(value: Option<&str>) -> BigDecimal {
+    match value.and_then(|v| BigDecimal::from_str(v.trim()).ok()) {
+        Some(minimum) if minimum > BigDecimal::from(0) => minimum,
+        Some(minimum) => {
+            println!("Ignoring PAYOUT_MINIMUM={}; it must be positive", minimum);
+            BigDecimal::from(10)
+        }
+        None => BigDecimal::from(10),
+    }
+}
+
+/// What a ledger transaction is for, and what it points at
+struct Posting {
+    vendor_id: Uuid,
+    kind: &'static str,
+    order_id: Option<Uuid>,
+    invoice_id: Option<Uuid>,
+    payout_id: Option<Uuid>,
+}
+
+/// Entries of a sale. The commission is capped at the payment.
+fn sale_entries(total: &BigDecimal, commission: BigDecimal) -> [(&'static str, BigDecimal); 3] {
+    let commission = commission.min(total.clone());
+    [
+        (PLATFORM_CASH, total.clone()),
+        (VENDOR_PAYABLE, -(total - &commission)),
+        (COMMISSION_REVENUE, -commission),
+    ]
+}
+
+/// What has been posted so far for a vendor's part of an order
+#[derive(Debug, sqlx::FromRow)]
+struct OrderPostings {
+    sold: BigDecimal,         // Sale gross
+    commission: BigDecimal,   // Commission charged on the sale
+    reversed: BigDecimal,     // Commission already handed back by refunds
+    refunded: BigDecimal,
+}
+
+/// Commission to hand back for a refund of `total`, rounded to cents. The
+/// refund that completes the order's refund gets whatever is left, so rounding
+/// never strands commission on a fully refunded order.
+fn commission_reversal(total: &BigDecimal, postings: &OrderPostings, item_total: &BigDecimal) -> BigDecimal {
+    let remaining = &postings.commission - &postings.reversed;
+    if &postings.refunded + total >= postings.sold || *item_total == BigDecimal::from(0) {
+        remaining
+    } else {
+        (total * &postings.commission / item_total).round(2).min(remaining)
+    }
+}
+
+/// Entries of a refund
+fn refund_entries(total: &BigDecimal, reversal: BigDecimal) -> [(&'static str, BigDecimal); 3] {
+    [
+        (VENDOR_PAYABLE, total - &reversal),
+        (COMMISSION_REVENUE, reversal),
+        (PLATFORM_CASH, -total.clone()),
+    ]
+}
+
+/// Entries of a payout
+fn payout_entries(amount: &BigDecimal) -> [(&'static str, BigDecimal); 2] {
+    [(VENDOR_PAYABLE, amount.clone()), (PLATFORM_CASH, -amount.clone())]
+}
+
+/// Insert one balanced transaction. Zero amounts are left out; the deferred
+/// constraint trigger rejects the commit if the entries don't sum to zero.
+async fn post(
+    conn: &mut PgConnection,
+    posting: Posting,
+    entries: &[(&'static str, BigDecimal)],
+) -> Result<Uuid, sqlx::Error> {
+    let transaction_id = Uuid::new_v4();
+    for (account, amount) in entries {
+        if *amount == BigDecimal::from(0) {
+            continue;
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO ledger_entries (transaction_id, account, vendor_id, amount, kind, order_id, invoice_id, payout_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(transaction_id)
+        .bind(account)
+        .bind(posting.vendor_id)
+        .bind(amount)
+        .bind(posting.kind)
+        .bind(posting.order_id)
+        .bind(posting.invoice_id)
+        .bind(posting.payout_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(transaction_id)
+}
+
+/// Commission on a vendor's items in an order at the current rates. Each item
+/// uses the vendor's rate, else its category's, else the global one; shipping
+/// is not commissioned.
+async fn sale_commission(conn: &mut PgConnection, order_id: Uuid, vendor_id: Uuid) -> Result<BigDecimal, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(ROUND(SUM(
+                   (oi.quantity - oi.cancelled_quantity) * oi.price
+                   * COALESCE(vr.rate, cr.rate, gr.rate, 0)
+               ), 2), 0)
+        FROM order_items oi
+        JOIN products p ON p.id = oi.product_id
+        LEFT JOIN commission_rates vr ON vr.vendor_id = oi.vendor_id
+        LEFT JOIN commission_rates cr ON cr.vendor_id IS NULL AND cr.category = LOWER(p.category)
+        LEFT JOIN commission_rates gr ON gr.vendor_id IS NULL AND gr.category IS NULL
+        WHERE oi.order_id = $1 AND oi.vendor_id = $2
+        "#,
+    )
+    .bind(order_id)
+    .bind(vendor_id)
+    .fetch_one(conn)
+    .await
+}
+
+/// Post a vendor's invoice: the customer's payment comes in, the platform keeps
+/// its commission and owes the vendor the rest
+pub async fn record_sale(conn: &mut PgConnection, invoice: &Invoice) -> Result<Uuid, sqlx::Error> {
+    let commission = sale_commission(&mut *conn, invoice.order_id, invoice.vendor_id).await?;
+
+    post(
+        conn,
+        Posting {
+            vendor_id: invoice.vendor_id,
+            kind: "sale",
+            order_id: Some(invoice.order_id),
+            invoice_id: Some(invoice.id),
+            payout_id: None,
+        },
+        &sale_entries(&invoice.total, commission),
+    )
+    .await
+}
+
+/// Post a vendor's credit note: the refund goes out, and the commission taken on
+/// the refunded amount is handed back. The rate is the one the sale was actually
+/// charged at, so later rate changes don't affect refunds; a refund that
+/// completes the order's refund reverses whatever commission is left.
+pub async fn record_refund(conn: &mut PgConnection, credit_note: &Invoice) -> Result<Uuid, sqlx::Error> {
+    let postings = sqlx::query_as::<_, OrderPostings>(
+        r#"
+        SELECT COALESCE(SUM(amount) FILTER (WHERE kind = 'sale' AND account = 'platform_cash'), 0) AS sold,
+               COALESCE(-SUM(amount) FILTER (WHERE kind = 'sale' AND account = 'commission_revenue'), 0) AS commission,
+               COALESCE(SUM(amount) FILTER (WHERE kind = 'refund' AND account = 'commission_revenue'), 0) AS reversed,
+               COALESCE(-SUM(amount) FILTER (WHERE kind = 'refund' AND account = 'platform_cash'), 0) AS refunded
+        FROM ledger_entries
+        WHERE order_id = $1 AND vendor_id = $2
+        "#,
+    )
+    .bind(credit_note.order_id)
+    .bind(credit_note.vendor_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // Commission was charged on items only, so the rate is over the item total
+    let item_total: BigDecimal = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(quantity * price), 0) FROM order_items WHERE order_id = $1 AND vendor_id = $2",
+    )
+    .bind(credit_note.order_id)
+    .bind(credit_note.vendor_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let reversal = commission_reversal(&credit_note.total, &postings, &item_total);
+
+    post(
+        conn,
+        Posting {
+            vendor_id: credit_note.vendor_id,
+            kind: "refund",
+            order_id: Some(credit_note.order_id),
+            invoice_id: Some(credit_note.id),
+            payout_id: None,
+        },
+        &refund_entries(&credit_note.total, reversal),
+    )
+    .await
+}
+
+/// Post a payout: the vendor is paid what they are owed out of the platform's cash
+pub async fn record_payout(
+    conn: &mut PgConnection,
+    vendor_id: Uuid,
+    payout_id: Uuid,
+    amount: &BigDecimal,
+) -> Result<Uuid, sqlx::Error> {
+    post(
+        conn,
+        Posting {
+            vendor_id,
+            kind: "payout",
+            order_id: None,
+            invoice_id: None,
+            payout_id: Some(payout_id),
+        },
+        &payout_entries(amount),
+    )
+    .await
+}
+
+/// A vendor's balance and totals. Sales within the holding period are not yet
+/// available; refunds and payouts count against the available balance at once.
+pub async fn vendor_balance<'e>(executor: impl PgExecutor<'e>, vendor_id: Uuid) -> Result<VendorBalance, sqlx::Error> {
+    sqlx::query_as::<_, VendorBalance>(
+        r#"
+        SELECT balance,
+               GREATEST(available, 0) AS available,
+               GREATEST(balance - GREATEST(available, 0), 0) AS held,
+               gross_sales, commission, refunds, paid_out
+        FROM (
+            SELECT COALESCE(-SUM(amount) FILTER (WHERE account = 'vendor_payable'), 0) AS balance,
+                   COALESCE(-SUM(amount) FILTER (
+                       WHERE account = 'vendor_payable'
+                         AND NOT (kind = 'sale' AND created_at >= NOW() - make_interval(days => $2))
+                   ), 0) AS available,
+                   COALESCE(SUM(amount) FILTER (WHERE account = 'platform_cash' AND kind = 'sale'), 0) AS gross_sales,
+                   COALESCE(-SUM(amount) FILTER (WHERE account = 'commission_revenue'), 0) AS commission,
+                   COALESCE(-SUM(amount) FILTER (WHERE account = 'platform_cash' AND kind = 'refund'), 0) AS refunds,
+                   COALESCE(SUM(amount) FILTER (WHERE account = 'vendor_payable' AND kind = 'payout'), 0) AS paid_out
+            FROM ledger_entries
+            WHERE vendor_id = $1
+        ) totals
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(payout_hold_days())
+    .fetch_one(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{insert_order_item, insert_user, insert_vendor_product, load_schema};
+    use sqlx::PgPool;
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn assert_balanced(entries: &[(&'static str, BigDecimal)]) {
+        let debits: BigDecimal = entries.iter().map(|(_, amount)| amount).filter(|a| **a > BigDecimal::from(0)).sum();
+        let credits: BigDecimal = entries.iter().map(|(_, amount)| amount).filter(|a| **a < BigDecimal::from(0)).sum();
+        assert_eq!(debits, -credits, "unbalanced entries: {:?}", entries);
+    }
+
+    fn postings(sold: &str, commission: &str, reversed: &str, refunded: &str) -> OrderPostings {
+        OrderPostings {
+            sold: dec(sold),
+            commission: dec(commission),
+            reversed: dec(reversed),
+            refunded: dec(refunded),
+        }
+    }
+
+    #[test]
+    fn payout_minimum_must_be_positive() {
+        assert_eq!(parse_payout_minimum(Some("25.50")), dec("25.50"));
+        for value in [Some("0"), Some("-5"), Some("ten"), None] {
+            assert_eq!(parse_payout_minimum(value), dec("10"), "PAYOUT_MINIMUM={:?}", value);
+        }
+    }
+
+    #[test]
+    fn sales_balance() {
+        for (total, commission) in [("25.00", "2.50"), ("19.99", "0"), ("0.01", "0.01"), ("100.00", "12.35")] {
+            assert_balanced(&sale_entries(&dec(total), dec(commission)));
+        }
+    }
+
+    #[test]
+    fn sale_commission_is_capped_at_the_payment() {
+        let entries = sale_entries(&dec("5.00"), dec("7.50"));
+        assert_balanced(&entries);
+        assert_eq!(entries[1], (VENDOR_PAYABLE, dec("0")));
+        assert_eq!(entries[2], (COMMISSION_REVENUE, dec("-5.00")));
+    }
+
+    #[test]
+    fn refunds_and_payouts_balance() {
+        for (total, reversal) in [("25.00", "2.50"), ("3.33", "0.42"), ("10.00", "0")] {
+            assert_balanced(&refund_entries(&dec(total), dec(reversal)));
+        }
+        for amount in ["10.00", "0.01", "1234.56"] {
+            assert_balanced(&payout_entries(&dec(amount)));
+        }
+    }
+
+    #[test]
+    fn partial_refunds_reverse_commission_rounded_to_cents() {
+        // 12.5% of 10.00 in items; (refund, expected reversal)
+        let cases = [("3.33", "0.42"), ("1.00", "0.13"), ("0.03", "0"), ("8.00", "1.00")];
+        for (refund, expected) in cases {
+            let reversal = commission_reversal(&dec(refund), &postings("10.00", "1.25", "0", "0"), &dec("10.00"));
+            assert_eq!(reversal, dec(expected), "reversal for a refund of {}", refund);
+        }
+    }
+
+    #[test]
+    fn last_refund_reverses_the_commission_left() {
+        let item_total = dec("10.00");
+        let first = commission_reversal(&dec("3.33"), &postings("10.00", "1.25", "0", "0"), &item_total);
+        let second = commission_reversal(&dec("3.33"), &postings("10.00", "1.25", "0.42", "3.33"), &item_total);
+        let last = commission_reversal(&dec("3.34"), &postings("10.00", "1.25", "0.84", "6.66"), &item_total);
+        assert_eq!((first, second, last.clone()), (dec("0.42"), dec("0.42"), dec("0.41")));
+        assert_eq!(dec("0.42") + dec("0.42") + last, dec("1.25"));
+    }
+
+    #[test]
+    fn reversal_never_exceeds_the_commission_left() {
+        let reversal = commission_reversal(&dec("5.00"), &postings("10.00", "1.25", "1.20", "4.00"), &dec("10.00"));
+        assert_eq!(reversal, dec("0.05"));
+    }
+
+    #[test]
+    fn shipping_only_refunds_reverse_the_commission_left() {
+        let reversal = commission_reversal(&dec("4.99"), &postings("4.99", "0", "0", "0"), &dec("0"));
+        assert_eq!(reversal, dec("0"));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn sale_commission_rounds_the_vendor_total_not_each_item(pool: PgPool) {
+        load_schema(&pool).await;
+        let vendor_id = insert_user(&pool, "vendor", "vendor").await;
+        let customer_id = insert_user(&pool, "customer", "customer").await;
+        let order_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO commission_rates (vendor_id, rate) VALUES ($1, 0.125)")
+            .bind(vendor_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO orders (id, user_id, total) VALUES ($1, $2, 4.95)")
+            .bind(order_id)
+            .bind(customer_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // 0.37125 + 0.12375 = 0.495, which rounds to 0.50; rounding each item would give 0.49.
+        // The cancelled unit isn't commissioned.
+        for (quantity, cancelled) in [(3, 0), (2, 1)] {
+            let product_id = insert_vendor_product(&pool, vendor_id, "0.99", 0).await;
+            let item_id = insert_order_item(&pool, order_id, product_id, quantity).await;
+            sqlx::query("UPDATE order_items SET cancelled_quantity = $2 WHERE id = $1")
+                .bind(item_id)
+                .bind(cancelled)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let mut conn = pool.acquire().await.unwrap();
+        let commission = sale_commission(&mut conn, order_id, vendor_id).await.unwrap();
+
+        assert_eq!(commission, dec("0.50"));
+    }
+}
@@ -0,0 +1,267 @@
+//! Failed login tracking. Attempts are counted per email address and per client
+//! IP. After a few failures an account has to wait progressively longer between
+//! attempts, and after `LOCKOUT_THRESHOLD` it is locked out for a while, which is
+//! recorded as an `AccountLockedOut` event. Unknown email addresses are throttled
+//! exactly like real ones so the responses don't reveal which accounts exist.
+
+use axum::http::HeaderMap;
+use chrono::{Duration, Utc};
+use sqlx::{FromRow, PgExecutor, PgPool};
+use std::env;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use crate::events::{self, DomainEvent};
+
+/// Failures older than this are forgotten
+const WINDOW_MINUTES: i32 = 15;
+/// Failures allowed before delays start
+const FREE_ATTEMPTS: i64 = 3;
+/// Longest delay between attempts before the lockout kicks in
+const MAX_DELAY_SECS: i64 = 60;
+/// Failures in the window that lock the account
+const LOCKOUT_THRESHOLD: i64 = 10;
+const LOCKOUT_MINUTES: i64 = 15;
+/// Failures in the window after which one IP is refused, across all accounts
+const IP_THRESHOLD: i64 = 50;
+
+/// The client address, taken from `X-Forwarded-For` when `TRUST_PROXY_HEADERS`
+/// is set because the app runs behind a reverse proxy
+pub fn client_ip(headers: &HeaderMap, addr: SocketAddr) -> String {
+    let trust_proxy = env::var("TRUST_PROXY_HEADERS").is_ok_and(|v| v == "true" || v == "1");
+    if trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(|ip| ip.trim().to_string());
+        if let Some(ip) = forwarded.filter(|ip| !ip.is_empty()) {
+            return ip;
+        }
+    }
+    addr.ip().to_string()
+}
+
+/// Attempts are tracked by the email as entered, ignoring case and whitespace
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[derive(FromRow)]
+struct FailureStats {
+    failures: i64,
+    last_failure_secs: Option<f64>,
+}
+
+/// Recent failures for an email, since its last successful login
+async fn account_failures<'e>(executor: impl PgExecutor<'e>, email: &str) -> Result<FailureStats, sqlx::Error> {
+    sqlx::query_as::<_, FailureStats>(
+        r#"
+        SELECT COUNT(*) AS failures,
+               EXTRACT(EPOCH FROM NOW() - MAX(created_at))::FLOAT8 AS last_failure_secs
+        FROM login_attempts
+        WHERE email = $1 AND NOT succeeded
+          AND created_at > NOW() - make_interval(mins => $2)
+          AND created_at > COALESCE(
+              (SELECT MAX(created_at) FROM login_attempts WHERE email = $1 AND succeeded),
+              '-infinity'
+          )
+        "#,
+    )
+    .bind(email)
+    .bind(WINDOW_MINUTES)
+    .fetch_one(executor)
+    .await
+}
+
+/// How long the account must wait after its latest failure
+fn account_delay_secs(failures: i64) -> i64 {
+    if failures >= LOCKOUT_THRESHOLD {
+        LOCKOUT_MINUTES * 60
+    } else if failures >= FREE_ATTEMPTS {
+        // 1s, 2s, 4s, ... capped at a minute
+        2_i64.pow((failures - FREE_ATTEMPTS).min(6) as u32).min(MAX_DELAY_SECS)
+    } else {
+        0
+    }
+}
+
+#[derive(FromRow)]
+struct IpStats {
+    failures: i64,
+    oldest_failure_secs: Option<f64>,
+}
+
+/// Seconds to wait before another attempt for this email from this IP, if any
+pub async fn retry_after(db: &PgPool, email: &str, ip: &str) -> Result<Option<i64>, sqlx::Error> {
+    let account = account_failures(db, email).await?;
+    let account_wait = account
+        .last_failure_secs
+        .map_or(0.0, |secs| account_delay_secs(account.failures) as f64 - secs);
+
+    let ip_stats = sqlx::query_as::<_, IpStats>(
+        r#"
+        SELECT COUNT(*) AS failures,
+               EXTRACT(EPOCH FROM NOW() - MIN(created_at))::FLOAT8 AS oldest_failure_secs
+        FROM login_attempts
+        WHERE ip_address = $1 AND NOT succeeded
+          AND created_at > NOW() - make_interval(mins => $2)
+        "#,
+    )
+    .bind(ip)
+    .bind(WINDOW_MINUTES)
+    .fetch_one(db)
+    .await?;
+    // Refused until enough failures age out of the window
+    let ip_wait = match ip_stats.oldest_failure_secs {
+        Some(secs) if ip_stats.failures >= IP_THRESHOLD => (WINDOW_MINUTES * 60) as f64 - secs,
+        _ => 0.0,
+    };
+
+    let wait = account_wait.max(ip_wait).ceil() as i64;
+    Ok((wait > 0).then_some(wait))
+}
+
+/// Record a failed attempt, and the lockout event when this failure triggers one
+pub async fn record_failure(db: &PgPool, email: &str, user_id: Option<Uuid>, ip: &str) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("INSERT INTO login_attempts (email, user_id, ip_address, succeeded) VALUES ($1, $2, $3, FALSE)")
+        .bind(email)
+        .bind(user_id)
+        .bind(ip)
+        .execute(&mut *tx)
+        .await?;
+
+    let failures = account_failures(&mut *tx, email).await?.failures;
+
+    if failures == LOCKOUT_THRESHOLD {
+        println!("Locking out login for {} after {} failed attempts (last from {})", email, failures, ip);
+        if let Some(user_id) = user_id {
+            let event = DomainEvent::AccountLockedOut {
+                user_id,
+                email: email.to_string(),
+                ip_address: ip.to_string(),
+                failed_attempts: failures,
+                locked_until: Utc::now() + Duration::minutes(LOCKOUT_MINUTES),
+            };
+            events::record(&mut tx, event).await?;
+        }
+    }
+
+    tx.commit().await
+}
+
+/// Record a successful login, which clears the account's failure count
+pub async fn record_success(db: &PgPool, email: &str, user_id: Uuid, ip: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO login_attempts (email, user_id, ip_address, succeeded) VALUES ($1, $2, $3, TRUE)")
+        .bind(email)
+        .bind(user_id)
+        .bind(ip)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{insert_user, load_schema};
+
+    const IP: &str = "203.0.113.7";
+
+    async fn fail(db: &PgPool, email: &str, user_id: Option<Uuid>, times: i64) {
+        for _ in 0..times {
+            record_failure(db, email, user_id, IP).await.unwrap();
+        }
+    }
+
+    async fn lockout_events(db: &PgPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM outbox WHERE event_type = 'AccountLockedOut'")
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn delays_double_after_the_free_attempts(pool: PgPool) {
+        load_schema(&pool).await;
+        let user_id = insert_user(&pool, "alice", "customer").await;
+        let email = "alice@example.com";
+
+        fail(&pool, email, Some(user_id), FREE_ATTEMPTS - 1).await;
+        assert_eq!(retry_after(&pool, email, IP).await.unwrap(), None);
+
+        for expected in [1, 2, 4, 8] {
+            fail(&pool, email, Some(user_id), 1).await;
+            assert_eq!(retry_after(&pool, email, IP).await.unwrap(), Some(expected));
+        }
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn the_account_locks_once_at_the_threshold(pool: PgPool) {
+        load_schema(&pool).await;
+        let user_id = insert_user(&pool, "alice", "customer").await;
+        let email = "alice@example.com";
+
+        fail(&pool, email, Some(user_id), LOCKOUT_THRESHOLD - 1).await;
+        assert_eq!(retry_after(&pool, email, IP).await.unwrap(), Some(MAX_DELAY_SECS));
+        assert_eq!(lockout_events(&pool).await, 0);
+
+        fail(&pool, email, Some(user_id), 1).await;
+        assert_eq!(retry_after(&pool, email, IP).await.unwrap(), Some(LOCKOUT_MINUTES * 60));
+        assert_eq!(lockout_events(&pool).await, 1);
+
+        // Further failures while locked out don't raise it again
+        fail(&pool, email, Some(user_id), 2).await;
+        assert_eq!(lockout_events(&pool).await, 1);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn a_successful_login_resets_the_count(pool: PgPool) {
+        load_schema(&pool).await;
+        let user_id = insert_user(&pool, "alice", "customer").await;
+        let email = "alice@example.com";
+
+        fail(&pool, email, Some(user_id), FREE_ATTEMPTS + 2).await;
+        assert!(retry_after(&pool, email, IP).await.unwrap().is_some());
+
+        record_success(&pool, email, user_id, IP).await.unwrap();
+        assert_eq!(retry_after(&pool, email, IP).await.unwrap(), None);
+        fail(&pool, email, Some(user_id), 1).await;
+        assert_eq!(retry_after(&pool, email, IP).await.unwrap(), None);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn one_ip_is_refused_after_too_many_failures_across_accounts(pool: PgPool) {
+        load_schema(&pool).await;
+
+        // A couple of tries per address stays under the per-account delays
+        for i in 0..IP_THRESHOLD / 2 {
+            fail(&pool, &format!("user{}@example.com", i), None, 2).await;
+        }
+
+        let wait = retry_after(&pool, "fresh@example.com", IP).await.unwrap();
+        assert!(wait.is_some_and(|secs| secs > (WINDOW_MINUTES * 60 - 60) as i64), "{:?}", wait);
+        assert_eq!(retry_after(&pool, "fresh@example.com", "198.51.100.1").await.unwrap(), None);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn unknown_emails_are_throttled_like_real_ones(pool: PgPool) {
+        load_schema(&pool).await;
+        let user_id = insert_user(&pool, "alice", "customer").await;
+
+        for failures in 1..=LOCKOUT_THRESHOLD {
+            fail(&pool, "alice@example.com", Some(user_id), 1).await;
+            fail(&pool, "nobody@example.com", None, 1).await;
+            assert_eq!(
+                retry_after(&pool, "nobody@example.com", IP).await.unwrap(),
+                retry_after(&pool, "alice@example.com", IP).await.unwrap(),
+                "after {} failures",
+                failures
+            );
+        }
+        // Only the real account's lockout is an event
+        assert_eq!(lockout_events(&pool).await, 1);
+    }
+}
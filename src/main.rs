@@ -2,15 +2,34 @@ use axum::{ routing::get, Router };
 use dotenvy::dotenv;
 use std::env;
 use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 pub mod models;
 pub mod routers;
 pub mod controllers;
 pub mod app_state;
+pub mod payment;
+pub mod checkout;
+pub mod invoice;
+pub mod inventory;
+pub mod product_import;
+pub mod ledger;
+pub mod notifier;
+pub mod email_templates;
+pub mod events;
+pub mod webhooks;
+pub mod jobs;
+pub mod tasks;
+pub mod tokens;
+pub mod mfa;
+pub mod login_attempts;
+pub mod jwt;
+#[cfg(test)]
+mod test_support;
 
 use app_state::AppState;
-use routers::{ auth::auth_routes, cart::cart_routes, product::product_routes, order::order_routes};
+use routers::{ auth::auth_routes, cart::cart_routes, checkout::checkout_routes, product::product_routes, order::order_routes, shipping::shipping_routes, returns::return_routes, webhooks::webhook_routes, admin::admin_routes, api_keys::api_key_routes, users::user_routes, vendors::vendor_routes, analytics::analytics_routes, ledger::ledger_routes, product_import::product_import_routes};
 
 #[tokio::main]
 async fn main() {
@@ -26,20 +45,50 @@ async fn main() {
     // Create AppState and wrap the entire thing in Arc
     let state = Arc::new(AppState {
         db: Arc::new(pool),
+        notifier: notifier::from_env(),
+        keys: Arc::new(jwt::KeyRing::from_env()),
     });
 
+    // Deliver domain events from the outbox to in-process subscribers
+    events::spawn_dispatcher(state.db.clone(), vec![
+        Arc::new(events::LogSubscriber),
+        Arc::new(notifier::EmailSubscriber::new(state.db.clone())),
+        Arc::new(webhooks::WebhookSubscriber::new(state.db.clone())),
+    ]);
+    webhooks::spawn_worker(state.db.clone());
+
+    // Background jobs (emails, cart expiry, reports) run alongside the HTTP server
+    jobs::spawn_runner(
+        state.db.clone(),
+        tasks::handlers(state.db.clone(), state.notifier.clone()),
+        tasks::schedules(),
+    );
+
     let app = Router::new()
         .route("/", get(root))
+        .route("/.well-known/jwks.json", get(controllers::auth::jwks))
         .nest("/auth", auth_routes())
         .nest("/cart", cart_routes())
         .nest("/products", product_routes())
+        .nest("/checkout", checkout_routes())
         .nest("/orders", order_routes())
+        .nest("/shipping", shipping_routes())
+        .nest("/returns", return_routes())
+        .nest("/webhooks", webhook_routes())
+        .nest("/admin", admin_routes())
+        .nest("/api-keys", api_key_routes())
+        .nest("/users", user_routes())
+        .nest("/vendors", vendor_routes())
+        .nest("/vendor/analytics", analytics_routes())
+        .nest("/vendor/ledger", ledger_routes())
+        .nest("/vendor/products", product_import_routes())
         .route("/*any", get(|| async { "Fallback hit: route not matched" }))
         .with_state(state); // Now passing Arc<AppState>
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     println!("Server is running on port: 3000");
-    axum::serve(listener, app).await.unwrap();
+    // Peer addresses are needed for per-IP login throttling
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
 
 async fn root() -> &'static str {
@@ -0,0 +1,66 @@
+//! TOTP two-factor authentication (RFC 6238: SHA-1, 6 digits, 30 second steps,
+//! compatible with the usual authenticator apps) and single-use recovery codes.
+//! Recovery codes are stored hashed with `tokens::hash`, like emailed tokens.
+
+use rand::RngCore;
+use std::env;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::tokens;
+
+/// Recovery codes handed out per enrollment or regeneration
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const STEP_SECS: u64 = 30;
+
+/// A new Base32 secret to provision into an authenticator app
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+fn totp(secret: &str, account: &str) -> Option<TOTP> {
+    let bytes = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    // The otpauth URI uses ':' to separate issuer and account
+    let issuer = env::var("MFA_ISSUER").unwrap_or_else(|_| "Shop".into()).replace(':', "");
+    TOTP::new(Algorithm::SHA1, 6, 1, STEP_SECS, bytes, Some(issuer), account.replace(':', "")).ok()
+}
+
+/// The `otpauth://` URI for a QR code, labelled with the user's email
+pub fn provisioning_uri(secret: &str, account: &str) -> Option<String> {
+    totp(secret, account).map(|totp| totp.get_url())
+}
+
+/// The time step a code belongs to, if it is valid now (allowing one step of
+/// clock drift either way) and newer than the last accepted step
+pub fn matching_step(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let totp = totp(secret, "")?;
+    let current = (chrono::Utc::now().timestamp() as u64 / STEP_SECS) as i64;
+    (current - 1..=current + 1)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.generate(*step as u64 * STEP_SECS) == code)
+}
+
+/// A fresh set of recovery codes, formatted as `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// The value stored for a recovery code. Case and separators are ignored.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    tokens::hash(&normalized)
+}
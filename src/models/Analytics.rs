@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::NaiveDate;
+use bigdecimal::BigDecimal;
+
+/// Bucket sizes for the sales time series
+pub const ANALYTICS_INTERVALS: [&str; 3] = ["day", "week", "month"];
+
+/// Date range and grouping for the analytics endpoints. Dates are inclusive
+/// and interpreted in `tz` (an IANA name such as "Europe/Berlin", default UTC);
+/// the range defaults to the last 30 days.
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub tz: Option<String>,
+    pub interval: Option<String>,   // Time series only: day, week or month
+    pub limit: Option<i64>,         // Top products only
+}
+
+/// Totals for the range. Cancelled units are excluded; returns count units
+/// from the range's sales whose return wasn't rejected.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SalesSummary {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub timezone: String,
+    pub orders: i64,
+    pub units_sold: i64,
+    pub revenue: BigDecimal,
+    pub average_order_value: BigDecimal,
+    pub returned_units: i64,
+    pub returns_rate: f64,   // returned_units / units_sold
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TopProduct {
+    pub product_id: Uuid,
+    pub name: String,
+    pub orders: i64,
+    pub units_sold: i64,
+    pub revenue: BigDecimal,
+}
+
+/// One day, week (starting Monday) or month of sales
+#[derive(Debug, Serialize, FromRow)]
+pub struct SalesBucket {
+    pub period_start: NaiveDate,
+    pub orders: i64,
+    pub units_sold: i64,
+    pub revenue: BigDecimal,
+}
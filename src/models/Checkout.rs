@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// A checkout holding stock for a customer
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CheckoutSession {
+    pub id: Uuid,
+    pub status: String,            // active, completed, released, expired or failed
+    pub expires_at: DateTime<Utc>,
+    pub order_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A quantity held by a checkout
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CheckoutItem {
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub quantity: i32,
+    pub price: BigDecimal,
+}
+
+/// A checkout with what it holds
+#[derive(Debug, Serialize)]
+pub struct CheckoutDetails {
+    #[serde(flatten)]
+    pub session: CheckoutSession,
+    pub items: Vec<CheckoutItem>,
+    pub subtotal: BigDecimal,      // Items only; shipping is priced when the order is placed
+}
+
+/// A cart line that can't be held, returned with a 409
+#[derive(Debug, Serialize, FromRow)]
+pub struct UnavailableItem {
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub requested: i32,
+    pub available: i32,
+}
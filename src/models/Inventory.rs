@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// One change to a product's stock
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InventoryMovement {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub change: i32,               // Positive when stock was added
+    pub stock_after: i32,
+    pub reason: String,            // initial, sale, cancellation, return, adjustment, import or correction
+    pub order_id: Option<Uuid>,
+    pub return_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,    // Who made a manual change
+    pub note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Filters for a product's inventory history, newest first
+#[derive(Debug, Deserialize)]
+pub struct InventoryQuery {
+    pub reason: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// A product's current stock and the movements that led to it
+#[derive(Debug, Serialize)]
+pub struct InventoryHistory {
+    pub product_id: Uuid,
+    pub stock: i32,
+    pub low_stock_threshold: Option<i32>,
+    pub waiting_customers: i64,   // Back-in-stock subscriptions not yet notified
+    pub movements: Vec<InventoryMovement>,
+}
+
+/// Payload for adding or removing stock by hand (e.g. a delivery or damaged goods)
+#[derive(Debug, Deserialize)]
+pub struct CreateInventoryAdjustment {
+    pub change: i32,
+    pub note: Option<String>,
+}
+
+/// Payload for setting (or, with null, removing) a product's low-stock alert.
+/// The vendor is emailed when stock drops from above the threshold to or below it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateLowStockAlert {
+    pub low_stock_threshold: Option<i32>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LowStockAlert {
+    pub product_id: Uuid,
+    pub stock: i32,
+    pub low_stock_threshold: Option<i32>,
+}
+
+/// A customer's "notify me when available" request
+#[derive(Debug, Serialize, FromRow)]
+pub struct StockSubscription {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub stock: i32,
+    pub created_at: Option<DateTime<Utc>>,
+    pub notified_at: Option<DateTime<Utc>>,   // Set once the back-in-stock email went out
+}
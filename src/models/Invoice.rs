@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// An invoice or credit note issued by one vendor for one order
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Invoice {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub vendor_id: Uuid,
+    pub kind: String,              // "invoice" or "credit_note"
+    pub number: i32,               // Sequential per vendor and kind
+    pub document_number: String,   // e.g. INV-1A2B3C4D-000042
+    pub invoice_id: Option<Uuid>,  // Credit notes: the invoice being credited
+    pub seller_name: String,
+    pub seller_email: String,
+    pub buyer_name: String,
+    pub buyer_email: String,
+    pub buyer_address: Option<String>,
+    pub subtotal: BigDecimal,      // Net of tax
+    pub tax_rate: BigDecimal,
+    pub tax_amount: BigDecimal,
+    pub total: BigDecimal,         // Tax inclusive
+    pub reason: Option<String>,
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+/// A line on an invoice or credit note
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InvoiceLine {
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: BigDecimal,
+    pub total: BigDecimal,
+}
+
+/// Invoice with its lines - used for rendering
+#[derive(Debug, Serialize)]
+pub struct InvoiceDocument {
+    #[serde(flatten)]
+    pub invoice: Invoice,
+    pub lines: Vec<InvoiceLine>,
+}
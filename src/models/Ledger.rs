@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, NaiveDate, Utc};
+use bigdecimal::BigDecimal;
+
+/// A commission rate: global when neither `vendor_id` nor `category` is set
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CommissionRate {
+    pub id: Uuid,
+    pub vendor_id: Option<Uuid>,
+    pub category: Option<String>,
+    pub rate: BigDecimal,          // Fraction of the item total, e.g. 0.1 for 10%
+    pub updated_by: Option<Uuid>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for setting a commission rate (admin only). Set at most one of
+/// `vendor_id` and `category`; with neither, the global rate is set.
+#[derive(Debug, Deserialize)]
+pub struct UpsertCommissionRate {
+    pub vendor_id: Option<Uuid>,
+    pub category: Option<String>,
+    pub rate: BigDecimal,
+}
+
+/// What the platform owes a vendor. `available` is the part a payout run can
+/// pay: sales newer than the holding period are kept back for refunds.
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorBalance {
+    pub balance: BigDecimal,
+    pub available: BigDecimal,
+    pub held: BigDecimal,
+    pub gross_sales: BigDecimal,
+    pub commission: BigDecimal,    // Net of commission returned on refunds
+    pub refunds: BigDecimal,
+    pub paid_out: BigDecimal,
+}
+
+/// Date range for the statement; dates are inclusive and in UTC
+#[derive(Debug, Deserialize)]
+pub struct StatementQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// One ledger transaction from the vendor's side. Amounts are signed: money
+/// in is positive, refunds and payouts negative. `net` is what the balance
+/// changed by, `balance` the balance after it.
+#[derive(Debug, Serialize, FromRow)]
+pub struct StatementLine {
+    pub transaction_id: Uuid,
+    pub kind: String,              // "sale", "refund" or "payout"
+    pub order_id: Option<Uuid>,
+    pub document_number: Option<String>,
+    pub payout_reference: Option<String>,
+    pub gross: BigDecimal,
+    pub commission: BigDecimal,
+    pub net: BigDecimal,
+    pub balance: BigDecimal,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A vendor statement: the balance carried in, the range's lines and the
+/// balance at the end
+#[derive(Debug, Serialize)]
+pub struct Statement {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub opening_balance: BigDecimal,
+    pub closing_balance: BigDecimal,
+    pub lines: Vec<StatementLine>,
+}
+
+/// A settlement of a vendor's balance
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Payout {
+    pub id: Uuid,
+    pub vendor_id: Uuid,
+    pub reference: String,
+    pub amount: BigDecimal,
+    pub payout_method: String,
+    pub payout_account_last4: Option<String>,
+    pub settlement_date: NaiveDate,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for running payouts now (admin only); the date defaults to today (UTC)
+#[derive(Debug, Default, Deserialize)]
+pub struct RunPayouts {
+    pub settlement_date: Option<NaiveDate>,
+}
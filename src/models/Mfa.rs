@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// Roles an MFA policy can be set for
+pub const MFA_POLICY_ROLES: [&str; 3] = ["customer", "vendor", "admin"];
+
+/// Returned when enrollment starts; the secret is confirmed with a code before it takes effect
+#[derive(Debug, Serialize)]
+pub struct MfaEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,   // Render as a QR code for authenticator apps
+}
+
+/// Single-use recovery codes, shown only when they are generated
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// A session token issued after the second step of login
+#[derive(Debug, Serialize)]
+pub struct MfaLoginResponse {
+    pub token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,   // Set when enrollment was completed during login
+}
+
+/// Payload carrying a TOTP code or, where accepted, a recovery code
+#[derive(Debug, Deserialize)]
+pub struct MfaCode {
+    pub code: String,
+}
+
+/// Payload for the second step of login
+#[derive(Debug, Deserialize)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+/// Payload for starting the enrollment required during login
+#[derive(Debug, Deserialize)]
+pub struct MfaEnrollmentRequest {
+    pub mfa_token: String,
+}
+
+/// Whether a role must use two-factor authentication
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MfaPolicy {
+    pub role: String,
+    pub required: bool,
+    pub updated_by: Option<Uuid>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for setting a role's MFA policy (admin only)
+#[derive(Debug, Deserialize)]
+pub struct UpdateMfaPolicy {
+    pub required: bool,
+}
+
+/// Returned by login instead of a session token when a second step is needed
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub enrollment_required: bool,   // The role requires MFA and the user hasn't set it up yet
+    pub mfa_token: String,
+    pub expires_in: i64,             // Seconds
+}
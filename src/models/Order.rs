@@ -4,6 +4,7 @@ use sqlx::FromRow;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use bigdecimal::BigDecimal;
+use crate::models::Shipping::{OrderShipping, ShippingSelection};
 
 /// Main Order structure representing a customer's order
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -11,7 +12,10 @@ pub struct Order {
     pub id: Uuid,
     pub user_id: Uuid,
     pub total: BigDecimal,
+    pub shipping_total: BigDecimal,
     pub status: String,
+    pub cancellation_reason: Option<String>,
+    pub cancelled_at: Option<DateTime<Utc>>,
     pub created_at: Option<DateTime<Utc>>,
 }
 
@@ -24,6 +28,7 @@ pub struct OrderItem {
     pub vendor_id: Uuid,
     pub quantity: i32,
     pub price: BigDecimal,  // Price at time of purchase
+    pub cancelled_quantity: i32,
 }
 
 /// Complete order details with items - used for API responses
@@ -32,9 +37,14 @@ pub struct OrderDetails {
     pub id: Uuid,
     pub user_id: Uuid,
     pub total: BigDecimal,
+    pub shipping_total: BigDecimal,
+    pub refunded_total: BigDecimal,
     pub status: String,
+    pub cancellation_reason: Option<String>,
+    pub cancelled_at: Option<DateTime<Utc>>,
     pub created_at: Option<DateTime<Utc>>,
     pub items: Vec<OrderItemDetails>,
+    pub shipping: Vec<OrderShipping>,
 }
 
 /// Order item with product information - used in OrderDetails
@@ -45,15 +55,19 @@ pub struct OrderItemDetails {
     pub product_name: String,  // From products table
     pub vendor_id: Uuid,
     pub quantity: i32,
+    pub cancelled_quantity: i32,
     pub price: BigDecimal,
-    pub subtotal: BigDecimal,  // price * quantity
+    pub subtotal: BigDecimal,  // price * (quantity - cancelled_quantity)
 }
 
 /// Payload for creating an order (minimal - cart conversion handles the data)
 #[derive(Debug, Deserialize)]
 pub struct CreateOrderRequest {
-    // For now, orders are created from cart, so no fields needed
+    // Items come from the cart; the customer picks a shipping method per vendor
     // Could add payment_method, shipping_address, etc. later
+    #[serde(default)]
+    pub shipping: Vec<ShippingSelection>,
+    pub shipping_address: Option<String>,
 }
 
 /// Payload for updating order status (vendor only)
@@ -62,6 +76,28 @@ pub struct UpdateOrderStatus {
     pub status: String,  // Should be "pending", "shipped", or "delivered"
 }
 
+/// Payload for cancelling a whole order
+#[derive(Debug, Default, Deserialize)]
+pub struct CancelOrderRequest {
+    pub reason: Option<String>,
+}
+
+/// Payload for cancelling part of a single line item
+#[derive(Debug, Deserialize)]
+pub struct CancelOrderItemRequest {
+    pub quantity: Option<i32>,  // Defaults to everything not yet cancelled
+    pub reason: Option<String>,
+}
+
+/// Response structure for order and line item cancellation
+#[derive(Debug, Serialize)]
+pub struct OrderCancellationResponse {
+    pub order_id: Uuid,
+    pub status: String,
+    pub refunded_amount: BigDecimal,
+    pub message: String,
+}
+
 /// Cart item structure for cart-to-order conversion
 /// This matches your CartItem but includes product info for calculations
 #[derive(Debug, FromRow)]
@@ -73,6 +109,10 @@ pub struct CartItemWithProduct {
     pub price: BigDecimal,
     pub vendor_id: Uuid,
     pub stock: i32,
+    pub weight_kg: Option<BigDecimal>,
+    pub length_cm: Option<BigDecimal>,
+    pub width_cm: Option<BigDecimal>,
+    pub height_cm: Option<BigDecimal>,
 }
 
 /// Response structure for order creation
@@ -80,6 +120,7 @@ pub struct CartItemWithProduct {
 pub struct OrderCreationResponse {
     pub order_id: Uuid,
     pub total: BigDecimal,
+    pub shipping_total: BigDecimal,
     pub status: String,
     pub message: String,
 }
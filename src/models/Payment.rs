@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// A charge or refund recorded by the payment layer
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Payment {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub kind: String,     // "charge" or "refund"
+    pub amount: BigDecimal,
+    pub status: String,   // Always "succeeded" while payments are simulated
+    pub reason: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
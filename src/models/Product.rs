@@ -8,31 +8,93 @@ use bigdecimal::BigDecimal;
 pub struct Product {
     pub id: Uuid,
     pub vendor_id: Uuid,
+    pub sku: Option<String>,      // Vendor's stock keeping unit, unique per vendor
     pub name: String,
     pub description: Option<String>,
     pub price: BigDecimal,
     pub stock: i32,
     pub category: Option<String>,
+    pub weight_kg: Option<BigDecimal>,
+    pub length_cm: Option<BigDecimal>,
+    pub width_cm: Option<BigDecimal>,
+    pub height_cm: Option<BigDecimal>,
     pub created_at: Option<DateTime<Utc>>,  // Was: DateTime<Utc>
-    pub updated_at: Option<DateTime<Utc>>
+    pub updated_at: Option<DateTime<Utc>>,
+    pub archived_at: Option<DateTime<Utc>>  // Set while hidden from the catalog
+}
+
+/// The store selling a product, so clients can show "sold by"
+#[derive(Debug, Clone, Serialize)]
+pub struct SoldBy {
+    pub store_name: String,
+    pub slug: String,
+    pub logo_url: Option<String>,
+}
+
+/// A product row joined with its vendor's store profile
+#[derive(Debug, sqlx::FromRow)]
+pub struct ProductRow {
+    #[sqlx(flatten)]
+    pub product: Product,
+    pub store_name: Option<String>,
+    pub store_slug: Option<String>,
+    pub store_logo_url: Option<String>,
+    pub available_stock: i32,
+}
+
+/// A product as returned by the catalog endpoints
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductWithVendor {
+    #[serde(flatten)]
+    pub product: Product,
+    pub available_stock: i32,      // Stock not held by other customers' checkouts
+    pub sold_by: Option<SoldBy>,   // None for vendors without a store profile
+}
+
+impl From<ProductRow> for ProductWithVendor {
+    fn from(row: ProductRow) -> Self {
+        let sold_by = match (row.store_name, row.store_slug) {
+            (Some(store_name), Some(slug)) => Some(SoldBy {
+                store_name,
+                slug,
+                logo_url: row.store_logo_url,
+            }),
+            _ => None,
+        };
+        ProductWithVendor {
+            product: row.product,
+            available_stock: row.available_stock,
+            sold_by,
+        }
+    }
 }
 
 /// Payload used when creating a new product via an API request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateProduct {
+    pub sku: Option<String>,
     pub name: String,
     pub description: Option<String>,
     pub price: BigDecimal,
     pub stock: i32,
     pub category: Option<String>,
+    pub weight_kg: Option<BigDecimal>,
+    pub length_cm: Option<BigDecimal>,
+    pub width_cm: Option<BigDecimal>,
+    pub height_cm: Option<BigDecimal>,
 }
 
 /// Payload used when updating a product.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateProduct {
+    pub sku: Option<String>,
     pub name: Option<String>,
     pub description: Option<String>,
     pub price: Option<BigDecimal>,
     pub stock: Option<i32>,
     pub category: Option<String>,
+    pub weight_kg: Option<BigDecimal>,
+    pub length_cm: Option<BigDecimal>,
+    pub width_cm: Option<BigDecimal>,
+    pub height_cm: Option<BigDecimal>,
 }
\ No newline at end of file
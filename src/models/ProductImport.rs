@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// Options for `POST /vendor/products/import`. The format defaults to the
+/// request's Content-Type (text/csv or application/json).
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    pub format: Option<String>,
+    pub dry_run: Option<bool>,
+}
+
+/// Options for `GET /vendor/products/export`
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,   // csv (default) or json
+}
+
+/// One product in an import or export file. Every column except sku may be
+/// left out when updating; a new product needs at least a name and a price.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+pub struct ProductRecord {
+    pub sku: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub price: Option<BigDecimal>,
+    pub stock: Option<i32>,
+    pub category: Option<String>,
+    pub weight_kg: Option<BigDecimal>,
+    pub length_cm: Option<BigDecimal>,
+    pub width_cm: Option<BigDecimal>,
+    pub height_cm: Option<BigDecimal>,
+}
+
+/// What happened to one row of an import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowResult {
+    pub row: usize,                 // 1-based, not counting a CSV header
+    pub sku: Option<String>,
+    pub status: String,             // created, updated or invalid
+    pub product_id: Option<Uuid>,   // None for invalid rows and new products in a dry run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// An import and, once processed, its per-row report
+#[derive(Debug, Serialize, FromRow)]
+pub struct ProductImport {
+    pub id: Uuid,
+    pub format: String,
+    pub dry_run: bool,              // Validated and reported without saving
+    pub status: String,             // pending or completed
+    pub total_rows: i32,
+    pub created_count: i32,
+    pub updated_count: i32,
+    pub failed_count: i32,
+    pub report: Json<Vec<ImportRowResult>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// A customer's request to return items from a delivered order
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ReturnRequest {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub vendor_id: Uuid,
+    pub status: String,  // "requested", "approved", "rejected", "shipped" or "received"
+    pub reason: String,
+    pub vendor_note: Option<String>,
+    pub tracking_number: Option<String>,
+    pub approved_amount: Option<BigDecimal>,
+    pub refunded_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A line item being returned, with the price snapshot from the order
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ReturnItemDetails {
+    pub order_item_id: Uuid,
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub quantity: i32,
+    pub price: BigDecimal,
+}
+
+/// Return request with its items - used for API responses
+#[derive(Debug, Serialize)]
+pub struct ReturnDetails {
+    #[serde(flatten)]
+    pub request: ReturnRequest,
+    pub items: Vec<ReturnItemDetails>,
+}
+
+/// One order item and how many units to send back
+#[derive(Debug, Deserialize)]
+pub struct ReturnItemInput {
+    pub order_item_id: Uuid,
+    pub quantity: i32,
+}
+
+/// Payload for opening a return (customer only)
+#[derive(Debug, Deserialize)]
+pub struct CreateReturnRequest {
+    pub order_id: Uuid,
+    pub reason: String,
+    pub items: Vec<ReturnItemInput>,
+}
+
+/// Payload for approving a return (vendor only)
+#[derive(Debug, Default, Deserialize)]
+pub struct ApproveReturnRequest {
+    pub note: Option<String>,
+    pub amount: Option<BigDecimal>,  // Defaults to the full price of the returned items
+}
+
+/// Payload for rejecting a return (vendor only)
+#[derive(Debug, Deserialize)]
+pub struct RejectReturnRequest {
+    pub note: String,
+}
+
+/// Payload for marking a return as shipped back (customer only)
+#[derive(Debug, Deserialize)]
+pub struct ShipReturnRequest {
+    pub tracking_number: Option<String>,
+}
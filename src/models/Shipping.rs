@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
+
+/// Shipping methods a vendor can offer
+pub const SHIPPING_METHODS: [&str; 3] = ["flat_rate", "weight_based", "free_over_threshold"];
+
+/// A vendor-defined shipping option
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ShippingProfile {
+    pub id: Uuid,
+    pub vendor_id: Uuid,
+    pub name: String,
+    pub method: String,                       // "flat_rate", "weight_based" or "free_over_threshold"
+    pub base_rate: BigDecimal,
+    pub per_kg_rate: Option<BigDecimal>,      // Only used by weight_based
+    pub free_threshold: Option<BigDecimal>,   // Only used by free_over_threshold
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for creating a shipping profile (vendor only)
+#[derive(Debug, Deserialize)]
+pub struct CreateShippingProfile {
+    pub name: String,
+    pub method: String,
+    pub base_rate: BigDecimal,
+    pub per_kg_rate: Option<BigDecimal>,
+    pub free_threshold: Option<BigDecimal>,
+}
+
+/// Payload for updating a shipping profile
+#[derive(Debug, Deserialize)]
+pub struct UpdateShippingProfile {
+    pub name: Option<String>,
+    pub method: Option<String>,
+    pub base_rate: Option<BigDecimal>,
+    pub per_kg_rate: Option<BigDecimal>,
+    pub free_threshold: Option<BigDecimal>,
+}
+
+/// A priced shipping option for one vendor group in the cart
+#[derive(Debug, Serialize)]
+pub struct ShippingOption {
+    pub shipping_profile_id: Uuid,
+    pub name: String,
+    pub method: String,
+    pub cost: BigDecimal,
+}
+
+/// Shipping quote for the cart items sold by a single vendor
+#[derive(Debug, Serialize)]
+pub struct VendorShippingQuote {
+    pub vendor_id: Uuid,
+    pub subtotal: BigDecimal,
+    pub total_weight_kg: BigDecimal,   // Chargeable weight: volumetric for bulky items
+    pub options: Vec<ShippingOption>,
+}
+
+/// Shipping method chosen by the customer for one vendor group at checkout
+#[derive(Debug, Deserialize)]
+pub struct ShippingSelection {
+    pub vendor_id: Uuid,
+    pub shipping_profile_id: Uuid,
+}
+
+/// Shipping method and cost stored on an order for one vendor group
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrderShipping {
+    pub vendor_id: Uuid,
+    pub shipping_profile_id: Option<Uuid>,
+    pub method_name: String,
+    pub cost: BigDecimal,
+}
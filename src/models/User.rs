@@ -1,12 +1,127 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use bigdecimal::BigDecimal;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+/// A user row including its secrets. Deliberately not `Serialize`, so it can't
+/// be returned from a handler; respond with `UserPublic`, `VendorPublic` or `UserProfile`.
+#[derive(Debug, FromRow)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub email: String,
     pub password_hash: String,
     pub role: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub session_version: i32,
+}
+
+/// Resolves to the inherent constant when `T: Serialize`, otherwise to the trait default
+struct SerializeCheck<T>(std::marker::PhantomData<T>);
+
+trait NotSerialize {
+    const IS_SERIALIZE: bool = false;
+}
+
+impl<T> NotSerialize for SerializeCheck<T> {}
+
+impl<T: Serialize> SerializeCheck<T> {
+    const IS_SERIALIZE: bool = true;
+}
+
+// Fails the build if `Serialize` is ever derived for `User`; the second line
+// shows the check does tell serializable types apart
+const _: () = assert!(!SerializeCheck::<User>::IS_SERIALIZE);
+const _: () = assert!(SerializeCheck::<UserPublic>::IS_SERIALIZE);
+
+/// A user as shown to themselves, e.g. when they register
+#[derive(Debug, Serialize)]
+pub struct UserPublic {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub role: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
+}
+
+impl From<User> for UserPublic {
+    fn from(user: User) -> Self {
+        UserPublic {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            role: user.role,
+            email_verified_at: user.email_verified_at,
+        }
+    }
+}
+
+/// A vendor as shown to other users; no contact details
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorPublic {
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+}
+
+/// The authenticated user's own account, as returned by `/users/me`
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub role: String,
+    pub email_verified: bool,
+    pub pending_email: Option<String>,   // Becomes the email once the link sent to it is opened
+    pub mfa_enabled: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for updating the profile; omitted fields are left unchanged
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfile {
+    pub username: Option<String>,
+    pub display_name: Option<String>,   // An empty string clears it
+    pub email: Option<String>,
+    pub current_password: Option<String>,   // Required to change the email
+}
+
+/// Payload for closing the account
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccount {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct CustomerStats {
+    pub orders: i64,
+    pub open_orders: i64,
+    pub total_spent: BigDecimal,
+    pub cart_items: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorStats {
+    pub products: i64,
+    pub out_of_stock_products: i64,
+    pub open_orders: i64,
+    pub units_sold: i64,
+    pub revenue: BigDecimal,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AccountStats {
+    Customer(CustomerStats),
+    Vendor(VendorStats),
+}
+
+/// The profile plus activity stats for the user's role
+#[derive(Debug, Serialize)]
+pub struct Dashboard {
+    #[serde(flatten)]
+    pub profile: UserProfile,
+    pub stats: AccountStats,
 }
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use crate::models::User::VendorPublic;
+
+/// Review states of a vendor's store
+pub const VENDOR_STATUSES: [&str; 3] = ["pending", "approved", "suspended"];
+
+/// Ways a vendor can be paid out
+pub const PAYOUT_METHODS: [&str; 2] = ["bank_transfer", "paypal"];
+
+/// A vendor's store details and approval state
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorProfile {
+    pub vendor_id: Uuid,
+    pub store_name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub logo_url: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    pub payout_method: Option<String>,
+    pub payout_account_last4: Option<String>,   // The full account is never returned
+    pub status: String,
+    pub status_reason: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A vendor profile with the account it belongs to, for admin review
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorApplication {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub profile: VendorProfile,
+    #[sqlx(flatten)]
+    pub vendor: VendorPublic,
+    pub email: String,   // For the admin to reach the vendor; not shown publicly
+    pub reviewed_by: Option<Uuid>,
+}
+
+/// Payload for submitting or updating the store profile; replaces every field
+#[derive(Debug, Deserialize)]
+pub struct UpsertVendorProfile {
+    pub store_name: String,
+    pub slug: Option<String>,   // Derived from the store name when omitted
+    pub description: Option<String>,
+    pub logo_url: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    pub payout_method: Option<String>,
+    pub payout_account: Option<String>,   // Keeps the current account when omitted and the method is unchanged
+}
+
+/// Payload for approving or suspending a vendor (admin only)
+#[derive(Debug, Deserialize)]
+pub struct UpdateVendorStatus {
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+/// Filters for the admin's vendor list
+#[derive(Debug, Deserialize)]
+pub struct VendorQuery {
+    pub status: Option<String>,
+}
+
+/// A vendor's public store page
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorStorefront {
+    pub vendor_id: Uuid,
+    pub store_name: String,
+    pub slug: String,
+    pub description: Option<String>,
+    pub logo_url: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    #[sqlx(flatten)]
+    pub vendor: VendorPublic,
+    pub product_count: i64,
+    pub rating: Option<f64>,   // Average of customer ratings, 1 to 5; None until the first one
+    pub rating_count: i64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A customer's rating of a vendor for one delivered order
+#[derive(Debug, Serialize, FromRow)]
+pub struct VendorRating {
+    pub id: Uuid,
+    pub vendor_id: Uuid,
+    pub order_id: Uuid,
+    pub rating: i16,
+    pub comment: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Payload for rating a vendor after an order was delivered
+#[derive(Debug, Deserialize)]
+pub struct CreateVendorRating {
+    pub order_id: Uuid,
+    pub rating: i16,
+    pub comment: Option<String>,
+}
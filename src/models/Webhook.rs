@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// Events vendors can subscribe a webhook endpoint to
+pub const WEBHOOK_EVENT_TYPES: [&str; 3] = ["OrderPlaced", "OrderStatusChanged", "ProductStockChanged"];
+
+/// A vendor's HTTP endpoint that receives signed event payloads
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub vendor_id: Uuid,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,                       // Only returned once, when the endpoint is created
+    pub event_types: Vec<String>,
+    pub is_active: bool,
+    pub consecutive_failures: i32,
+    pub disabled_at: Option<DateTime<Utc>>,   // Set when turned off after repeated failures
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Returned when an endpoint is created, the only time the signing secret is shown
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhookEndpoint {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+/// Payload for registering a webhook endpoint (vendor only)
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookEndpoint {
+    pub url: String,
+    pub event_types: Vec<String>,
+}
+
+/// Payload for updating a webhook endpoint; setting is_active re-enables a disabled one
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookEndpoint {
+    pub url: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    pub is_active: Option<bool>,
+}
+
+/// One attempt-tracked delivery of an event to an endpoint
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub endpoint_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: Json<serde_json::Value>,
+    pub status: String,                       // "pending", "succeeded" or "failed"
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Query parameters for the delivery log
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
@@ -1,9 +1,36 @@
+// Model modules are named after the type they hold
+#![allow(non_snake_case)]
+
 pub mod User;
 pub mod Cart;
 pub mod Order;
 pub mod Product;
+pub mod Shipping;
+pub mod Payment;
+pub mod Return;
+pub mod Invoice;
+pub mod Webhook;
+pub mod Mfa;
+pub mod ApiKey;
+pub mod Vendor;
+pub mod Analytics;
+pub mod Ledger;
+pub mod Inventory;
+pub mod Checkout;
+pub mod ProductImport;
 
-pub use User::*;
 pub use Cart::*;
 pub use Order::*;
 pub use Product::*;
+pub use Shipping::*;
+pub use Return::*;
+pub use Invoice::*;
+pub use Webhook::*;
+pub use Mfa::*;
+pub use ApiKey::*;
+pub use Vendor::*;
+pub use Analytics::*;
+pub use Ledger::*;
+pub use Inventory::*;
+pub use Checkout::*;
+pub use ProductImport::*;
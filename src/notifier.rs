@@ -0,0 +1,293 @@
+//! Transactional email. `EmailSubscriber` turns domain events from the outbox
+//! into "email.send" jobs, so emails go out only once the change that triggered
+//! them has committed and are retried by the job runner if the mail server is down.
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::email_templates;
+use crate::jobs;
+use crate::events::{DomainEvent, EventError, EventSubscriber, OutboxEvent};
+
+pub type NotifyError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A plain-text email ready to send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Who an email is addressed to
+#[derive(Debug, Clone, Deserialize, FromRow)]
+pub struct Recipient {
+    pub username: String,
+    pub email: String,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), NotifyError>;
+}
+
+/// Sends email through any SMTP server, including a local sink such as MailHog
+pub struct SmtpNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpNotifier {
+    /// Configure from `SMTP_HOST`, `SMTP_PORT`, `SMTP_TLS` ("none", "starttls" or "tls"),
+    /// `SMTP_USERNAME`, `SMTP_PASSWORD` and `SMTP_FROM`
+    pub fn from_env(host: &str) -> Result<Self, NotifyError> {
+        let tls = env::var("SMTP_TLS").unwrap_or_else(|_| "none".into());
+        let mut builder = match tls.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            _ => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+
+        if let Ok(port) = env::var("SMTP_PORT") {
+            builder = builder.port(port.parse()?);
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from = env::var("SMTP_FROM").unwrap_or_else(|_| "Shop <no-reply@localhost>".into());
+        Ok(SmtpNotifier {
+            transport: builder.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(&self, message: EmailMessage) -> Result<(), NotifyError> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(message.to.parse()?)
+            .subject(message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body)?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
+
+/// Prints emails instead of sending them, used when no SMTP server is configured
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, message: EmailMessage) -> Result<(), NotifyError> {
+        println!("Email to {}: {}\n{}", message.to, message.subject, message.body);
+        Ok(())
+    }
+}
+
+/// SMTP notifier when `SMTP_HOST` is set, otherwise one that just logs
+pub fn from_env() -> Arc<dyn Notifier> {
+    match env::var("SMTP_HOST") {
+        Ok(host) => match SmtpNotifier::from_env(&host) {
+            Ok(notifier) => Arc::new(notifier),
+            Err(e) => panic!("Invalid SMTP configuration: {}", e),
+        },
+        Err(_) => {
+            println!("SMTP_HOST not set, emails will be logged instead of sent");
+            Arc::new(LogNotifier)
+        }
+    }
+}
+
+/// Queues the customer-facing emails for domain events
+pub struct EmailSubscriber {
+    db: Arc<PgPool>,
+}
+
+impl EmailSubscriber {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        EmailSubscriber { db }
+    }
+
+    async fn recipient(&self, user_id: Uuid) -> Result<Recipient, NotifyError> {
+        let recipient = sqlx::query_as::<_, Recipient>("SELECT username, email FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&*self.db)
+            .await?;
+        Ok(recipient)
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for EmailSubscriber {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), EventError> {
+        let message = match &event.event {
+            DomainEvent::UserRegistered { username, email, .. } => email_templates::registration(&Recipient {
+                username: username.clone(),
+                email: email.clone(),
+            }),
+            DomainEvent::OrderPlaced { order_id, user_id, total, .. } => {
+                email_templates::order_placed(&self.recipient(*user_id).await?, *order_id, total)
+            }
+            DomainEvent::OrderStatusChanged { order_id, user_id, status, .. }
+                if status == "shipped" || status == "delivered" =>
+            {
+                email_templates::order_status_changed(&self.recipient(*user_id).await?, *order_id, status)
+            }
+            _ => return Ok(()),
+        };
+        // Keyed on the event so handling it twice still sends one email
+        let unique_key = format!("event-email:{}", event.id);
+        jobs::enqueue(&*self.db, "email.send", serde_json::to_value(message)?, Some(&unique_key)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use chrono::Utc;
+
+    use crate::test_support::load_schema;
+
+    async fn subscriber(pool: PgPool) -> (EmailSubscriber, Uuid) {
+        load_schema(&pool).await;
+        let user_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO users (id, username, email, password_hash) VALUES ($1, 'alice', 'alice@example.com', 'x')")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        (EmailSubscriber::new(Arc::new(pool)), user_id)
+    }
+
+    fn outbox(event: DomainEvent) -> OutboxEvent {
+        OutboxEvent {
+            id: Uuid::new_v4(),
+            event,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// The emails queued so far
+    async fn queued(subscriber: &EmailSubscriber) -> Vec<EmailMessage> {
+        let payloads: Vec<serde_json::Value> =
+            sqlx::query_scalar("SELECT payload FROM jobs WHERE kind = 'email.send' ORDER BY created_at")
+                .fetch_all(&*subscriber.db)
+                .await
+                .unwrap();
+        payloads.into_iter().map(|payload| serde_json::from_value(payload).unwrap()).collect()
+    }
+
+    fn status_changed(user_id: Uuid, status: &str) -> OutboxEvent {
+        outbox(DomainEvent::OrderStatusChanged {
+            order_id: Uuid::new_v4(),
+            user_id,
+            previous_status: "paid".into(),
+            status: status.into(),
+        })
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn registration_queues_a_welcome_email(pool: PgPool) {
+        let (subscriber, user_id) = subscriber(pool).await;
+        let event = outbox(DomainEvent::UserRegistered {
+            user_id,
+            username: "alice".into(),
+            email: "alice@example.com".into(),
+            role: "customer".into(),
+        });
+
+        subscriber.handle(&event).await.unwrap();
+
+        let emails = queued(&subscriber).await;
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].to, "alice@example.com");
+        assert_eq!(emails[0].subject, "Welcome to the shop");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn order_placed_emails_the_buyer(pool: PgPool) {
+        let (subscriber, user_id) = subscriber(pool).await;
+        let order_id = Uuid::new_v4();
+        let event = outbox(DomainEvent::OrderPlaced {
+            order_id,
+            user_id,
+            total: BigDecimal::from(12),
+            vendor_ids: vec![],
+        });
+
+        subscriber.handle(&event).await.unwrap();
+
+        let emails = queued(&subscriber).await;
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].to, "alice@example.com");
+        assert_eq!(emails[0].subject, format!("Order {} received", order_id));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn only_shipped_and_delivered_send_status_emails(pool: PgPool) {
+        let (subscriber, user_id) = subscriber(pool).await;
+
+        for status in ["pending", "paid", "cancelled", "shipped", "delivered"] {
+            subscriber.handle(&status_changed(user_id, status)).await.unwrap();
+        }
+
+        let subjects: Vec<String> = queued(&subscriber).await.into_iter().map(|email| email.subject).collect();
+        assert_eq!(subjects.len(), 2, "unexpected emails: {:?}", subjects);
+        assert!(subjects[0].ends_with(" shipped"));
+        assert!(subjects[1].ends_with(" delivered"));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn stock_and_lockout_events_send_no_email(pool: PgPool) {
+        let (subscriber, user_id) = subscriber(pool).await;
+        let stock_changed = outbox(DomainEvent::ProductStockChanged {
+            product_id: Uuid::new_v4(),
+            vendor_id: user_id,
+            change: -1,
+            stock: 0,
+            reason: "sale".into(),
+        });
+        let locked_out = outbox(DomainEvent::AccountLockedOut {
+            user_id,
+            email: "alice@example.com".into(),
+            ip_address: "127.0.0.1".into(),
+            failed_attempts: 5,
+            locked_until: Utc::now(),
+        });
+
+        subscriber.handle(&stock_changed).await.unwrap();
+        subscriber.handle(&locked_out).await.unwrap();
+
+        assert!(queued(&subscriber).await.is_empty());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn handling_an_event_twice_sends_one_email(pool: PgPool) {
+        let (subscriber, user_id) = subscriber(pool).await;
+        let event = status_changed(user_id, "shipped");
+
+        subscriber.handle(&event).await.unwrap();
+        subscriber.handle(&event).await.unwrap();
+
+        assert_eq!(queued(&subscriber).await.len(), 1);
+    }
+}
@@ -0,0 +1,64 @@
+//! Simulated payment layer. Charges and refunds always succeed, and each one is
+//! recorded in the `payments` table on the caller's connection so it commits or
+//! rolls back together with the order change that caused it.
+
+use bigdecimal::BigDecimal;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::models::Payment::Payment;
+
+/// Charge the customer for an order
+pub async fn charge(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    amount: &BigDecimal,
+) -> Result<Payment, sqlx::Error> {
+    let payment = record(conn, order_id, "charge", amount, None).await?;
+    println!("Payment simulated successfully for order {}", order_id);
+    Ok(payment)
+}
+
+/// Refund part or all of an order back to the customer
+pub async fn refund(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    amount: &BigDecimal,
+    reason: Option<&str>,
+) -> Result<Payment, sqlx::Error> {
+    let payment = record(conn, order_id, "refund", amount, reason).await?;
+    println!("Refund of {} simulated successfully for order {}", amount, order_id);
+    Ok(payment)
+}
+
+async fn record(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    kind: &str,
+    amount: &BigDecimal,
+    reason: Option<&str>,
+) -> Result<Payment, sqlx::Error> {
+    sqlx::query_as::<_, Payment>(
+        r#"
+        INSERT INTO payments (order_id, kind, amount, reason)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, order_id, kind, amount, status, reason, created_at
+        "#,
+    )
+    .bind(order_id)
+    .bind(kind)
+    .bind(amount)
+    .bind(reason)
+    .fetch_one(conn)
+    .await
+}
+
+/// Total refunded so far for an order
+pub async fn refunded_total(conn: &mut PgConnection, order_id: Uuid) -> Result<BigDecimal, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount), 0) FROM payments WHERE order_id = $1 AND kind = 'refund'",
+    )
+    .bind(order_id)
+    .fetch_one(conn)
+    .await
+}
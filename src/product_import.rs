@@ -0,0 +1,547 @@
+//! Bulk product imports and exports. An import file (CSV with a header row, or
+//! a JSON array of objects) is parsed into `ProductRecord`s that are matched to
+//! the vendor's products by SKU: unknown SKUs are created, known ones updated.
+//! Rows that don't validate are reported and skipped while the rest are saved.
+//! A dry run goes through the same steps in a savepoint that is rolled back, so
+//! its report shows exactly what the import would do. Stock set by an import is
+//! recorded as an "import" inventory movement.
+
+use bigdecimal::BigDecimal;
+use serde_json::Value;
+use sqlx::{types::Json, Connection, PgConnection, PgPool};
+use std::collections::HashSet;
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::inventory::{self, Movement};
+use crate::jobs::JobError;
+use crate::models::ProductImport::{ImportRowResult, ProductImport, ProductRecord};
+
+pub const IMPORT_FORMATS: [&str; 2] = ["csv", "json"];
+
+/// Columns of an import or export file, in export order
+pub const COLUMNS: [&str; 10] = [
+    "sku", "name", "description", "price", "stock", "category", "weight_kg", "length_cm", "width_cm", "height_cm",
+];
+
+/// Most rows a single file may have
+pub const MAX_ROWS: usize = 10_000;
+
+pub const IMPORT_COLUMNS: &str = "id, format, dry_run, status, total_rows, created_count, updated_count, \
+     failed_count, report, created_at, completed_at";
+
+/// Files with more rows than `IMPORT_ASYNC_ROWS` (default 100) are processed
+/// by a background job instead of during the request
+pub fn async_rows() -> usize {
+    env::var("IMPORT_ASYNC_ROWS").ok().and_then(|v| v.parse().ok()).unwrap_or(100)
+}
+
+/// A parsed row and what's wrong with it, if anything
+#[derive(Debug)]
+pub struct ParsedRow {
+    pub record: ProductRecord,
+    pub errors: Vec<String>,
+}
+
+/// Parse an import file. Errors are about the file as a whole (malformed JSON,
+/// unknown CSV columns); problems with single rows are kept with the row.
+pub fn parse(format: &str, data: &str) -> Result<Vec<ParsedRow>, String> {
+    match format {
+        "csv" => parse_csv(data),
+        "json" => parse_json(data),
+        _ => Err(format!("Invalid format. Valid formats are: {}", IMPORT_FORMATS.join(", "))),
+    }
+}
+
+fn parse_csv(data: &str) -> Result<Vec<ParsedRow>, String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(data.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Couldn't read the CSV header: {}", e))?
+        .iter()
+        .map(str::to_lowercase)
+        .collect();
+
+    let unknown: Vec<&str> = headers
+        .iter()
+        .map(String::as_str)
+        .filter(|header| !COLUMNS.contains(header))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "Unknown column(s): {}. Valid columns are: {}",
+            unknown.join(", "),
+            COLUMNS.join(", ")
+        ));
+    }
+    if !headers.iter().any(|header| header == "sku") {
+        return Err("The file needs a sku column".into());
+    }
+
+    let rows = reader
+        .records()
+        .map(|record| match record {
+            Ok(record) => {
+                let fields = headers
+                    .iter()
+                    .zip(record.iter())
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(header, value)| (header.clone(), value.to_string()))
+                    .collect();
+                to_record(fields)
+            }
+            Err(e) => ParsedRow {
+                record: ProductRecord::default(),
+                errors: vec![e.to_string()],
+            },
+        })
+        .collect();
+    Ok(rows)
+}
+
+fn parse_json(data: &str) -> Result<Vec<ParsedRow>, String> {
+    let items: Vec<Value> = serde_json::from_str(data)
+        .map_err(|e| format!("The file must be a JSON array of products: {}", e))?;
+
+    let rows = items
+        .into_iter()
+        .map(|item| {
+            let Value::Object(object) = item else {
+                return ParsedRow {
+                    record: ProductRecord::default(),
+                    errors: vec!["Each product must be a JSON object".into()],
+                };
+            };
+            let mut errors = Vec::new();
+            let mut fields = Vec::new();
+            for (key, value) in object {
+                if !COLUMNS.contains(&key.as_str()) {
+                    errors.push(format!("Unknown field {}", key));
+                    continue;
+                }
+                match value {
+                    Value::Null => {}
+                    Value::String(s) if s.trim().is_empty() => {}
+                    Value::String(s) => fields.push((key, s.trim().to_string())),
+                    Value::Number(n) => fields.push((key, n.to_string())),
+                    _ => errors.push(format!("{} must be a string or a number", key)),
+                }
+            }
+            let mut row = to_record(fields);
+            errors.append(&mut row.errors);
+            row.errors = errors;
+            row
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// Validate a row's non-empty fields, given as (column, value)
+fn to_record(fields: Vec<(String, String)>) -> ParsedRow {
+    let mut record = ProductRecord::default();
+    let mut errors = Vec::new();
+
+    let text = |value: String, max: usize, column: &str, errors: &mut Vec<String>| {
+        if value.chars().count() > max {
+            errors.push(format!("{} must be at most {} characters", column, max));
+        }
+        Some(value)
+    };
+    let decimal = |value: &str, column: &str, errors: &mut Vec<String>| match BigDecimal::from_str(value) {
+        Ok(n) if n < BigDecimal::from(0) => {
+            errors.push(format!("{} must not be negative", column));
+            None
+        }
+        Ok(n) => Some(n),
+        Err(_) => {
+            errors.push(format!("{} must be a number", column));
+            None
+        }
+    };
+
+    for (column, value) in fields {
+        match column.as_str() {
+            "sku" => record.sku = text(value, 64, "sku", &mut errors),
+            "name" => record.name = text(value, 255, "name", &mut errors),
+            "description" => record.description = Some(value),
+            "category" => record.category = text(value, 100, "category", &mut errors),
+            "price" => record.price = decimal(&value, "price", &mut errors),
+            "weight_kg" => record.weight_kg = decimal(&value, "weight_kg", &mut errors),
+            "length_cm" => record.length_cm = decimal(&value, "length_cm", &mut errors),
+            "width_cm" => record.width_cm = decimal(&value, "width_cm", &mut errors),
+            "height_cm" => record.height_cm = decimal(&value, "height_cm", &mut errors),
+            "stock" => match value.parse::<i32>() {
+                Ok(stock) if stock < 0 => errors.push("stock must not be negative".into()),
+                Ok(stock) => record.stock = Some(stock),
+                Err(_) => errors.push("stock must be a whole number".into()),
+            },
+            _ => {}
+        }
+    }
+    if record.sku.is_none() {
+        errors.insert(0, "sku is required".into());
+    }
+    ParsedRow { record, errors }
+}
+
+/// Create or update the vendor's products from the parsed rows and report on
+/// every row. With `dry_run` nothing is kept.
+pub async fn process(
+    conn: &mut PgConnection,
+    vendor_id: Uuid,
+    import_id: Uuid,
+    rows: Vec<ParsedRow>,
+    dry_run: bool,
+) -> Result<Vec<ImportRowResult>, sqlx::Error> {
+    let mut work = conn.begin().await?;
+    let mut seen = HashSet::new();
+    let mut report = Vec::with_capacity(rows.len());
+
+    for (index, ParsedRow { record, mut errors }) in rows.into_iter().enumerate() {
+        let sku = record.sku.clone();
+        if let Some(sku) = &sku {
+            if !seen.insert(sku.clone()) && errors.is_empty() {
+                errors.push("sku appears more than once in the file".into());
+            }
+        }
+
+        let mut outcome = None;
+        if errors.is_empty() {
+            // Each row gets its own savepoint, so a row the database rejects
+            // doesn't undo the others
+            let mut row = work.begin().await?;
+            match save(&mut row, vendor_id, import_id, &record).await {
+                Ok(Ok(saved)) => {
+                    row.commit().await?;
+                    outcome = Some(saved);
+                }
+                Ok(Err(error)) => errors.push(error),
+                Err(e) => {
+                    println!("Failed to import row {}: {:?}", index + 1, e);
+                    errors.push("The product couldn't be saved".into());
+                }
+            }
+        }
+
+        report.push(match outcome {
+            Some((status, product_id)) => ImportRowResult {
+                row: index + 1,
+                sku,
+                status: status.into(),
+                product_id: if dry_run && status == "created" { None } else { Some(product_id) },
+                errors,
+            },
+            None => ImportRowResult {
+                row: index + 1,
+                sku,
+                status: "invalid".into(),
+                product_id: None,
+                errors,
+            },
+        });
+    }
+
+    if dry_run {
+        work.rollback().await?;
+    } else {
+        work.commit().await?;
+    }
+    Ok(report)
+}
+
+/// Create or update one product. Returns whether it was "created" or
+/// "updated", or why the row can't be imported.
+async fn save(
+    conn: &mut PgConnection,
+    vendor_id: Uuid,
+    import_id: Uuid,
+    record: &ProductRecord,
+) -> Result<Result<(&'static str, Uuid), String>, sqlx::Error> {
+    let existing: Option<(Uuid, i32, bool)> = sqlx::query_as(
+        "SELECT id, stock, archived_at IS NOT NULL FROM products WHERE vendor_id = $1 AND sku = $2 FOR UPDATE",
+    )
+    .bind(vendor_id)
+    .bind(&record.sku)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((product_id, stock, archived)) = existing else {
+        let (Some(name), Some(price)) = (&record.name, &record.price) else {
+            return Ok(Err("name and price are required for a new product".into()));
+        };
+        let stock = record.stock.unwrap_or(0);
+        let product_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO products (vendor_id, sku, name, description, price, stock, category, weight_kg, length_cm, width_cm, height_cm)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id
+            "#,
+        )
+        .bind(vendor_id)
+        .bind(&record.sku)
+        .bind(name)
+        .bind(&record.description)
+        .bind(price)
+        .bind(stock)
+        .bind(&record.category)
+        .bind(&record.weight_kg)
+        .bind(&record.length_cm)
+        .bind(&record.width_cm)
+        .bind(&record.height_cm)
+        .fetch_one(&mut *conn)
+        .await?;
+        inventory::record_initial(&mut *conn, product_id, stock, "import", vendor_id).await?;
+        return Ok(Ok(("created", product_id)));
+    };
+
+    // Like a product update, an import doesn't change archived products
+    if archived {
+        return Ok(Err("product is archived, restore it first".into()));
+    }
+
+    if let Some(new_stock) = record.stock {
+        let movement = Movement {
+            product_id,
+            change: new_stock - stock,
+            reason: "import",
+            actor_id: Some(vendor_id),
+            note: Some(format!("Import {}", import_id)),
+            ..Default::default()
+        };
+        inventory::apply(&mut *conn, movement).await?;
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE products
+        SET name = COALESCE($2, name),
+            description = COALESCE($3, description),
+            price = COALESCE($4, price),
+            category = COALESCE($5, category),
+            weight_kg = COALESCE($6, weight_kg),
+            length_cm = COALESCE($7, length_cm),
+            width_cm = COALESCE($8, width_cm),
+            height_cm = COALESCE($9, height_cm),
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(product_id)
+    .bind(&record.name)
+    .bind(&record.description)
+    .bind(&record.price)
+    .bind(&record.category)
+    .bind(&record.weight_kg)
+    .bind(&record.length_cm)
+    .bind(&record.width_cm)
+    .bind(&record.height_cm)
+    .execute(&mut *conn)
+    .await?;
+    Ok(Ok(("updated", product_id)))
+}
+
+/// Store the report and mark the import completed
+pub async fn finish(
+    conn: &mut PgConnection,
+    import_id: Uuid,
+    report: Vec<ImportRowResult>,
+) -> Result<ProductImport, sqlx::Error> {
+    let count = |status: &str| report.iter().filter(|row| row.status == status).count() as i32;
+    let (created, updated, failed) = (count("created"), count("updated"), count("invalid"));
+
+    sqlx::query_as::<_, ProductImport>(&format!(
+        r#"
+        UPDATE product_imports
+        SET status = 'completed', payload = NULL, created_count = $2, updated_count = $3,
+            failed_count = $4, report = $5, completed_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        IMPORT_COLUMNS
+    ))
+    .bind(import_id)
+    .bind(created)
+    .bind(updated)
+    .bind(failed)
+    .bind(Json(report))
+    .fetch_one(conn)
+    .await
+}
+
+/// Process a stored import left for the background job. Does nothing if it
+/// was already processed.
+pub async fn run(db: &PgPool, import_id: Uuid) -> Result<(), JobError> {
+    let mut tx = db.begin().await?;
+    let pending: Option<(Uuid, String, bool, Option<String>)> = sqlx::query_as(
+        "SELECT vendor_id, format, dry_run, payload FROM product_imports WHERE id = $1 AND status = 'pending' FOR UPDATE",
+    )
+    .bind(import_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((vendor_id, format, dry_run, payload)) = pending else { return Ok(()) };
+
+    // The file was checked when it was uploaded
+    let rows = parse(&format, payload.as_deref().unwrap_or_default())?;
+    let report = process(&mut tx, vendor_id, import_id, rows, dry_run).await?;
+    finish(&mut tx, import_id, report).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{insert_user, insert_vendor_product, load_schema};
+
+    const HEADER: &str = "sku,name,price,stock\n";
+
+    fn errors(rows: &[ParsedRow]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.errors.clone()).collect()
+    }
+
+    #[test]
+    fn unknown_csv_columns_reject_the_file() {
+        let error = parse("csv", "sku,name,colour\nA1,Bell,red\n").unwrap_err();
+        assert!(error.starts_with("Unknown column(s): colour."), "{}", error);
+    }
+
+    #[test]
+    fn csv_needs_a_sku_column() {
+        assert_eq!(parse("csv", "name,price\nBell,5\n").unwrap_err(), "The file needs a sku column");
+    }
+
+    #[test]
+    fn csv_headers_are_case_insensitive() {
+        let rows = parse("csv", "SKU, Name ,PRICE\nA1,Bell,5.50\n").unwrap();
+        assert_eq!(errors(&rows), [Vec::<String>::new()]);
+        assert_eq!(rows[0].record.name.as_deref(), Some("Bell"));
+        assert_eq!(rows[0].record.price, Some(BigDecimal::from_str("5.50").unwrap()));
+    }
+
+    #[test]
+    fn bad_prices_are_reported_on_their_row() {
+        let data = format!("{}A1,Bell,five,1\nA2,Horn,-1,1\nA3,Lamp,2.50,1\n", HEADER);
+        let rows = parse("csv", &data).unwrap();
+        assert_eq!(
+            errors(&rows),
+            [vec!["price must be a number".to_string()], vec!["price must not be negative".to_string()], vec![]]
+        );
+        assert_eq!(rows[0].record.price, None);
+    }
+
+    #[test]
+    fn bad_stock_and_missing_sku_are_reported() {
+        let data = format!("{}A1,Bell,5,1.5\nA2,Horn,5,-3\n,Lamp,5,1\n", HEADER);
+        let rows = parse("csv", &data).unwrap();
+        assert_eq!(
+            errors(&rows),
+            [
+                vec!["stock must be a whole number".to_string()],
+                vec!["stock must not be negative".to_string()],
+                vec!["sku is required".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn json_must_be_an_array_of_objects() {
+        let error = parse("json", r#"{"sku": "A1"}"#).unwrap_err();
+        assert!(error.starts_with("The file must be a JSON array of products"), "{}", error);
+
+        let rows = parse("json", r#"[{"sku": "A1", "price": 5, "colour": "red"}, "A2", {"sku": "A3", "stock": true}]"#).unwrap();
+        assert_eq!(
+            errors(&rows),
+            [
+                vec!["Unknown field colour".to_string()],
+                vec!["Each product must be a JSON object".to_string()],
+                vec!["stock must be a string or a number".to_string()],
+            ]
+        );
+        assert_eq!(rows[0].record.price, Some(BigDecimal::from(5)));
+    }
+
+    /// A vendor with one existing product, SKU "OLD" with 4 in stock
+    async fn vendor_with_product(pool: &PgPool) -> Uuid {
+        load_schema(pool).await;
+        let vendor_id = insert_user(pool, "vendor", "vendor").await;
+        let product_id = insert_vendor_product(pool, vendor_id, "1", 4).await;
+        sqlx::query("UPDATE products SET sku = 'OLD', name = 'Old' WHERE id = $1")
+            .bind(product_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        vendor_id
+    }
+
+    async fn import(pool: &PgPool, vendor_id: Uuid, data: &str, dry_run: bool) -> Vec<ImportRowResult> {
+        let rows = parse("csv", data).unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        process(&mut conn, vendor_id, Uuid::new_v4(), rows, dry_run).await.unwrap()
+    }
+
+    fn statuses(report: &[ImportRowResult]) -> Vec<&str> {
+        report.iter().map(|row| row.status.as_str()).collect()
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn duplicate_skus_in_a_file_keep_the_first_row(pool: PgPool) {
+        let vendor_id = vendor_with_product(&pool).await;
+        let data = format!("{}A1,Bell,5,1\nA1,Horn,6,2\nOLD,,,3\nOLD,,,9\n", HEADER);
+
+        let report = import(&pool, vendor_id, &data, false).await;
+
+        assert_eq!(statuses(&report), ["created", "invalid", "updated", "invalid"]);
+        assert_eq!(report[1].errors, ["sku appears more than once in the file"]);
+        assert_eq!(report[3].row, 4);
+        let stock: Vec<(String, i32)> = sqlx::query_as("SELECT name, stock FROM products ORDER BY sku")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stock, [("Bell".to_string(), 1), ("Old".to_string(), 3)]);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn dry_run_reports_without_saving(pool: PgPool) {
+        let vendor_id = vendor_with_product(&pool).await;
+        let data = format!("{}A1,Bell,5,1\nOLD,Renamed,,7\nA2,,,1\nA3,Lamp,x,1\n", HEADER);
+
+        let report = import(&pool, vendor_id, &data, true).await;
+
+        assert_eq!(statuses(&report), ["created", "updated", "invalid", "invalid"]);
+        assert_eq!(report[0].product_id, None, "new products have no id in a dry run");
+        assert!(report[1].product_id.is_some());
+        assert_eq!(report[2].errors, ["name and price are required for a new product"]);
+        assert_eq!(report[3].errors, ["price must be a number"]);
+
+        let products: Vec<(String, String, i32)> = sqlx::query_as("SELECT sku, name, stock FROM products")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(products, [("OLD".to_string(), "Old".to_string(), 4)]);
+        let movements: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM inventory_movements")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(movements, 0);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn archived_products_are_not_updated(pool: PgPool) {
+        let vendor_id = vendor_with_product(&pool).await;
+        sqlx::query("UPDATE products SET archived_at = NOW() WHERE sku = 'OLD'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let report = import(&pool, vendor_id, &format!("{}OLD,Renamed,2,9\n", HEADER), false).await;
+
+        assert_eq!(statuses(&report), ["invalid"]);
+        assert_eq!(report[0].errors, ["product is archived, restore it first"]);
+        let product: (String, i32) = sqlx::query_as("SELECT name, stock FROM products WHERE sku = 'OLD'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(product, ("Old".to_string(), 4));
+    }
+}
@@ -0,0 +1,19 @@
+use axum::{Router, routing::{delete, get, post, put}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::admin::{get_mfa_policies, update_mfa_policy, get_vendors, update_vendor_status,
+        get_commission_rates, upsert_commission_rate, delete_commission_rate, run_payouts},
+    app_state::AppState,
+};
+
+pub fn admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/mfa-policies", get(get_mfa_policies))
+        .route("/mfa-policies/:role", put(update_mfa_policy))
+        .route("/vendors", get(get_vendors))
+        .route("/vendors/:id/status", put(update_vendor_status))
+        .route("/commission-rates", get(get_commission_rates).put(upsert_commission_rate))
+        .route("/commission-rates/:id", delete(delete_commission_rate))
+        .route("/payouts/run", post(run_payouts))
+}
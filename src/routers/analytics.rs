@@ -0,0 +1,14 @@
+use axum::{Router, routing::get};
+use std::sync::Arc;
+
+use crate::{
+    controllers::analytics::{get_sales_summary, get_top_products, get_sales_over_time},
+    app_state::AppState,
+};
+
+pub fn analytics_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/summary", get(get_sales_summary))
+        .route("/top-products", get(get_top_products))
+        .route("/sales", get(get_sales_over_time))
+}
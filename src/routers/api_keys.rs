@@ -0,0 +1,13 @@
+use axum::{Router, routing::{get, delete}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::api_keys::{create_api_key, get_api_keys, revoke_api_key},
+    app_state::AppState,
+};
+
+pub fn api_key_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_api_keys).post(create_api_key))
+        .route("/:id", delete(revoke_api_key))
+}
@@ -1,11 +1,30 @@
-use axum::{Router, routing::{get, post}};
+use axum::{Router, routing::{delete, get, post}};
 use std::sync::Arc;
-use crate::controllers::auth::{register, login, dashboard};
+use crate::controllers::auth::{register, login};
+use crate::controllers::users::dashboard;
+use crate::controllers::verification::{verify_email, resend_verification};
+use crate::controllers::password::{forgot_password, reset_password, change_password};
+use crate::controllers::mfa::{
+    login_mfa, start_login_enrollment, confirm_login_enrollment, start_enrollment, confirm_enrollment,
+    disable_mfa, regenerate_recovery_codes,
+};
 use crate::app_state::AppState;
 
 pub fn auth_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/register", post(register))
         .route("/login", post(login))
+        .route("/login/mfa", post(login_mfa))
+        .route("/login/mfa/enroll", post(start_login_enrollment))
+        .route("/login/mfa/enroll/confirm", post(confirm_login_enrollment))
         .route("/dashboard", get(dashboard))
+        .route("/verify-email", post(verify_email))
+        .route("/resend-verification", post(resend_verification))
+        .route("/forgot-password", post(forgot_password))
+        .route("/reset-password", post(reset_password))
+        .route("/change-password", post(change_password))
+        .route("/mfa", delete(disable_mfa))
+        .route("/mfa/enroll", post(start_enrollment))
+        .route("/mfa/confirm", post(confirm_enrollment))
+        .route("/mfa/recovery-codes", post(regenerate_recovery_codes))
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use crate::{
     controllers::cart::{ get_cart_items, add_cart_item, remove_cart_item },
+    controllers::shipping::get_cart_shipping_quote,
     app_state::AppState,
 };
 
@@ -11,4 +12,5 @@ pub fn cart_routes() -> Router<Arc<AppState>> {
         .route("/", get(get_cart_items))
         .route("/add/:product_id", put(add_cart_item))
         .route("/remove/:product_id", delete(remove_cart_item))
+        .route("/shipping", get(get_cart_shipping_quote))
 }
@@ -0,0 +1,12 @@
+use axum::{Router, routing::post};
+use std::sync::Arc;
+
+use crate::{
+    controllers::checkout::{start_checkout, get_checkout, cancel_checkout},
+    app_state::AppState,
+};
+
+pub fn checkout_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", post(start_checkout).get(get_checkout).delete(cancel_checkout))
+}
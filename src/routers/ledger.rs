@@ -0,0 +1,14 @@
+use axum::{Router, routing::get};
+use std::sync::Arc;
+
+use crate::{
+    controllers::ledger::{get_balance, get_statement, get_payouts},
+    app_state::AppState,
+};
+
+pub fn ledger_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/balance", get(get_balance))
+        .route("/statement", get(get_statement))
+        .route("/payouts", get(get_payouts))
+}
@@ -1,9 +1,31 @@
+pub mod admin;
+pub mod analytics;
+pub mod api_keys;
 pub mod auth;
 pub mod cart;
+pub mod checkout;
+pub mod ledger;
 pub mod order;
 pub mod product;
+pub mod product_import;
+pub mod returns;
+pub mod shipping;
+pub mod users;
+pub mod vendors;
+pub mod webhooks;
 
+pub use admin::*;
+pub use analytics::*;
+pub use api_keys::*;
 pub use auth::*;
 pub use cart::*;
+pub use checkout::*;
+pub use ledger::*;
 pub use order::*;
 pub use product::*;
+pub use product_import::*;
+pub use returns::*;
+pub use shipping::*;
+pub use users::*;
+pub use vendors::*;
+pub use webhooks::*;
@@ -1,8 +1,9 @@
 use axum::{
-    routing::{get, post, put, delete, patch},
+    routing::{get, post},
     Router,
 };
 use crate::controllers::order::*;
+use crate::controllers::invoice::get_order_invoice;
 use crate::app_state::AppState;
 use std::sync::Arc;
 
@@ -11,5 +12,8 @@ pub fn order_routes() -> Router<Arc<AppState>> {
         // Customer order routes
         .route("/", get(get_all_orders).post(create_order))
         .route("/:id", get(get_order_by_id).put(update_order_by_id).delete(delete_order_by_id))
+        .route("/:id/cancel", post(cancel_order))
+        .route("/:id/invoice", get(get_order_invoice))
+        .route("/:id/items/:item_id/cancel", post(cancel_order_item))
 
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
-use axum::{Router, routing::{get, post, put, delete}};
+use axum::{Router, routing::{get, post, put}};
 use crate::controllers::product::*;
+use crate::controllers::inventory::{
+    get_inventory, adjust_inventory, update_low_stock_alert, subscribe_to_restock, unsubscribe_from_restock,
+};
 use crate::app_state::AppState;
 use std::sync::Arc;
 
@@ -7,6 +10,10 @@ pub fn product_routes() -> Router<Arc<AppState>> {
         println!("Product routes loaded");
     Router::new()
         .route("/", get(get_all_products)
-        .post(create_product)).route("/:id", get(get_product_by_id).put(update_product_by_id).delete(delete_product_by_id))
+        .post(create_product)).route("/archived", get(get_archived_products)).route("/:id", get(get_product_by_id).put(update_product_by_id).delete(delete_product_by_id))
+        .route("/:id/restore", post(restore_product))
+        .route("/:id/inventory", get(get_inventory).post(adjust_inventory))
+        .route("/:id/inventory/alert", put(update_low_stock_alert))
+        .route("/:id/notify-me", post(subscribe_to_restock).delete(unsubscribe_from_restock))
 
 }
\ No newline at end of file
@@ -0,0 +1,15 @@
+use axum::{Router, routing::{get, post}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::product_import::{import_products, get_imports, get_import, export_products},
+    app_state::AppState,
+};
+
+pub fn product_import_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/import", post(import_products))
+        .route("/imports", get(get_imports))
+        .route("/imports/:id", get(get_import))
+        .route("/export", get(export_products))
+}
@@ -0,0 +1,17 @@
+use axum::{Router, routing::{get, post}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::returns::*,
+    app_state::AppState,
+};
+
+pub fn return_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_all_returns).post(create_return))
+        .route("/:id", get(get_return_by_id))
+        .route("/:id/approve", post(approve_return))
+        .route("/:id/reject", post(reject_return))
+        .route("/:id/ship", post(ship_return))
+        .route("/:id/receive", post(receive_return))
+}
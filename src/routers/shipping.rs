@@ -0,0 +1,17 @@
+use axum::{Router, routing::{get, put}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::shipping::{
+        create_shipping_profile, get_my_shipping_profiles, get_vendor_shipping_profiles,
+        update_shipping_profile, delete_shipping_profile,
+    },
+    app_state::AppState,
+};
+
+pub fn shipping_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/profiles", get(get_my_shipping_profiles).post(create_shipping_profile))
+        .route("/profiles/:id", put(update_shipping_profile).delete(delete_shipping_profile))
+        .route("/vendors/:vendor_id", get(get_vendor_shipping_profiles))
+}
@@ -0,0 +1,14 @@
+use axum::{Router, routing::get};
+use std::sync::Arc;
+
+use crate::{
+    controllers::users::{get_me, update_me, delete_me},
+    controllers::inventory::get_my_stock_subscriptions,
+    app_state::AppState,
+};
+
+pub fn user_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/me", get(get_me).patch(update_me).delete(delete_me))
+        .route("/me/stock-subscriptions", get(get_my_stock_subscriptions))
+}
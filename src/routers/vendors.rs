@@ -0,0 +1,18 @@
+use axum::{Router, routing::get};
+use std::sync::Arc;
+
+use crate::{
+    controllers::vendors::{
+        get_my_vendor_profile, upsert_my_vendor_profile, get_storefront, get_storefront_products,
+        get_vendor_ratings, rate_vendor,
+    },
+    app_state::AppState,
+};
+
+pub fn vendor_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/me", get(get_my_vendor_profile).put(upsert_my_vendor_profile))
+        .route("/:slug", get(get_storefront))
+        .route("/:slug/products", get(get_storefront_products))
+        .route("/:slug/ratings", get(get_vendor_ratings).post(rate_vendor))
+}
@@ -0,0 +1,19 @@
+use axum::{Router, routing::{get, post}};
+use std::sync::Arc;
+
+use crate::{
+    controllers::webhooks::{
+        create_webhook_endpoint, get_webhook_endpoints, get_webhook_endpoint,
+        update_webhook_endpoint, delete_webhook_endpoint, get_webhook_deliveries,
+        replay_webhook_delivery,
+    },
+    app_state::AppState,
+};
+
+pub fn webhook_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_webhook_endpoints).post(create_webhook_endpoint))
+        .route("/:id", get(get_webhook_endpoint).put(update_webhook_endpoint).delete(delete_webhook_endpoint))
+        .route("/:id/deliveries", get(get_webhook_deliveries))
+        .route("/:id/deliveries/:delivery_id/replay", post(replay_webhook_delivery))
+}
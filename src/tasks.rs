@@ -0,0 +1,484 @@
+//! Background job handlers and the recurring schedule
+
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{Duration, NaiveDate, Utc};
+use serde_json::json;
+use sqlx::{FromRow, PgPool};
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::checkout;
+use crate::email_templates;
+use crate::inventory;
+use crate::ledger;
+use crate::product_import;
+use crate::jobs::{self, Job, JobError, JobHandler, ScheduledJob};
+use crate::notifier::{EmailMessage, Notifier, Recipient};
+
+/// Handlers for every job kind the app enqueues
+pub fn handlers(db: Arc<PgPool>, notifier: Arc<dyn Notifier>) -> Vec<Arc<dyn JobHandler>> {
+    vec![
+        Arc::new(SendEmail { notifier }),
+        Arc::new(ExpireCarts { db: db.clone() }),
+        Arc::new(DailySalesReport { db: db.clone() }),
+        Arc::new(PruneLoginRecords { db: db.clone() }),
+        Arc::new(VendorPayouts { db: db.clone() }),
+        Arc::new(ReconcileInventory { db: db.clone() }),
+        Arc::new(ExpireCheckouts { db: db.clone() }),
+        Arc::new(ImportProducts { db: db.clone() }),
+        Arc::new(BackInStock { db }),
+    ]
+}
+
+pub fn schedules() -> Vec<ScheduledJob> {
+    vec![
+        ScheduledJob {
+            name: "cart-expiry",
+            kind: "cart.expire",
+            cron: "0 0 * * * *",  // Hourly
+            payload: json!({}),
+        },
+        ScheduledJob {
+            name: "daily-sales-report",
+            kind: "report.daily_sales",
+            cron: "0 0 6 * * *",  // 06:00 UTC, covering the previous day
+            payload: json!({}),
+        },
+        ScheduledJob {
+            name: "login-record-pruning",
+            kind: "auth.prune_login_records",
+            cron: "0 30 3 * * *",  // Daily at 03:30 UTC
+            payload: json!({}),
+        },
+        ScheduledJob {
+            name: "vendor-payouts",
+            kind: "ledger.payouts",
+            cron: "0 0 4 * * Mon",  // Mondays at 04:00 UTC
+            payload: json!({}),
+        },
+        ScheduledJob {
+            name: "inventory-reconciliation",
+            kind: "inventory.reconcile",
+            cron: "0 15 2 * * *",  // Daily at 02:15 UTC
+            payload: json!({}),
+        },
+        ScheduledJob {
+            name: "checkout-expiry",
+            kind: "checkout.expire",
+            cron: "0 * * * * *",  // Every minute
+            payload: json!({}),
+        },
+    ]
+}
+
+/// Sends an `EmailMessage` payload
+pub struct SendEmail {
+    notifier: Arc<dyn Notifier>,
+}
+
+#[async_trait]
+impl JobHandler for SendEmail {
+    fn kind(&self) -> &'static str {
+        "email.send"
+    }
+
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let message: EmailMessage = serde_json::from_value(job.payload.0.clone())?;
+        self.notifier.send(message).await
+    }
+}
+
+/// Removes cart items left untouched for `CART_TTL_DAYS` days (default 30)
+pub struct ExpireCarts {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for ExpireCarts {
+    fn kind(&self) -> &'static str {
+        "cart.expire"
+    }
+
+    async fn run(&self, _job: &Job) -> Result<(), JobError> {
+        let ttl_days: i32 = env::var("CART_TTL_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+        let result = sqlx::query("DELETE FROM cart_items WHERE created_at < NOW() - make_interval(days => $1)")
+            .bind(ttl_days)
+            .execute(&*self.db)
+            .await?;
+        if result.rows_affected() > 0 {
+            println!("Expired {} cart item(s) older than {} days", result.rows_affected(), ttl_days);
+        }
+        Ok(())
+    }
+}
+
+#[derive(FromRow)]
+struct VendorSales {
+    vendor_id: Uuid,
+    username: String,
+    email: String,
+    orders: i64,
+    units: i64,
+    revenue: BigDecimal,
+}
+
+/// Emails each vendor a summary of one day's sales.
+/// Payload: `{"date": "YYYY-MM-DD"}`, defaulting to yesterday (UTC).
+pub struct DailySalesReport {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for DailySalesReport {
+    fn kind(&self) -> &'static str {
+        "report.daily_sales"
+    }
+
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let date = match job.payload.0.get("date").and_then(|d| d.as_str()) {
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+            None => (Utc::now() - Duration::days(1)).date_naive(),
+        };
+
+        let sales = sqlx::query_as::<_, VendorSales>(
+            r#"
+            SELECT oi.vendor_id, u.username, u.email,
+                   COUNT(DISTINCT o.id) AS orders,
+                   SUM(oi.quantity - oi.cancelled_quantity)::BIGINT AS units,
+                   SUM((oi.quantity - oi.cancelled_quantity) * oi.price) AS revenue
+            FROM order_items oi
+            JOIN orders o ON o.id = oi.order_id
+            JOIN users u ON u.id = oi.vendor_id
+            WHERE o.status <> 'cancelled'
+              AND o.created_at >= $1::date AND o.created_at < $1::date + 1
+            GROUP BY oi.vendor_id, u.username, u.email
+            "#,
+        )
+        .bind(date)
+        .fetch_all(&*self.db)
+        .await?;
+
+        // One email job per vendor, keyed so a retry of this job doesn't send twice
+        for vendor in sales {
+            let recipient = Recipient {
+                username: vendor.username,
+                email: vendor.email,
+            };
+            let message = email_templates::daily_sales_report(&recipient, date, vendor.orders, vendor.units, &vendor.revenue);
+            let unique_key = format!("daily-sales:{}:{}", vendor.vendor_id, date);
+            jobs::enqueue(&*self.db, "email.send", serde_json::to_value(message)?, Some(&unique_key)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes login attempts older than 30 days and finished or expired MFA challenges
+pub struct PruneLoginRecords {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for PruneLoginRecords {
+    fn kind(&self) -> &'static str {
+        "auth.prune_login_records"
+    }
+
+    async fn run(&self, _job: &Job) -> Result<(), JobError> {
+        sqlx::query("DELETE FROM login_attempts WHERE created_at < NOW() - INTERVAL '30 days'")
+            .execute(&*self.db)
+            .await?;
+        sqlx::query("DELETE FROM mfa_challenges WHERE used_at IS NOT NULL OR expires_at < NOW()")
+            .execute(&*self.db)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Records a correction for products whose stock no longer matches their
+/// inventory history, e.g. after a direct database edit
+pub struct ReconcileInventory {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for ReconcileInventory {
+    fn kind(&self) -> &'static str {
+        "inventory.reconcile"
+    }
+
+    async fn run(&self, _job: &Job) -> Result<(), JobError> {
+        let corrected = inventory::reconcile(&self.db).await?;
+        if corrected > 0 {
+            println!("Corrected the inventory history of {} product(s)", corrected);
+        }
+        Ok(())
+    }
+}
+
+/// Marks checkouts past their hold time as expired. Their stock is already
+/// available again; this only keeps the session status accurate.
+pub struct ExpireCheckouts {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for ExpireCheckouts {
+    fn kind(&self) -> &'static str {
+        "checkout.expire"
+    }
+
+    async fn run(&self, _job: &Job) -> Result<(), JobError> {
+        let expired = checkout::expire(&self.db).await?;
+        if expired > 0 {
+            println!("Expired {} checkout(s)", expired);
+        }
+        Ok(())
+    }
+}
+
+/// Processes a product import too large to handle during the upload
+pub struct ImportProducts {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for ImportProducts {
+    fn kind(&self) -> &'static str {
+        "products.import"
+    }
+
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let import_id: Uuid = job
+            .payload
+            .0
+            .get("import_id")
+            .and_then(|id| id.as_str())
+            .ok_or("Missing import_id")?
+            .parse()?;
+        product_import::run(&self.db, import_id).await
+    }
+}
+
+#[derive(FromRow)]
+struct StockSubscriber {
+    username: String,
+    email: String,
+}
+
+/// Emails everyone waiting for a product that came back in stock, once each.
+/// Payload: `{"product_id": "..."}`. If the product sold out again before the
+/// job ran, the subscriptions stay armed for the next restock.
+pub struct BackInStock {
+    db: Arc<PgPool>,
+}
+
+#[async_trait]
+impl JobHandler for BackInStock {
+    fn kind(&self) -> &'static str {
+        "inventory.back_in_stock"
+    }
+
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let product_id: Uuid = job
+            .payload
+            .0
+            .get("product_id")
+            .and_then(|id| id.as_str())
+            .ok_or("Missing product_id")?
+            .parse()?;
+
+        let mut tx = self.db.begin().await?;
+        // Archived products notify their subscribers when restored instead
+        let product: Option<(String, i32)> =
+            sqlx::query_as("SELECT name, stock FROM products WHERE id = $1 AND archived_at IS NULL")
+            .bind(product_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some((name, stock)) = product else { return Ok(()) };
+        if stock <= 0 {
+            return Ok(());
+        }
+
+        let subscribers = sqlx::query_as::<_, StockSubscriber>(
+            r#"
+            UPDATE stock_subscriptions s
+            SET notified_at = NOW()
+            FROM users u
+            WHERE s.product_id = $1 AND s.notified_at IS NULL
+              AND u.id = s.user_id AND u.deleted_at IS NULL
+            RETURNING u.username, u.email
+            "#,
+        )
+        .bind(product_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for subscriber in &subscribers {
+            let recipient = Recipient {
+                username: subscriber.username.clone(),
+                email: subscriber.email.clone(),
+            };
+            let message = email_templates::back_in_stock(&recipient, product_id, &name);
+            jobs::enqueue(&mut *tx, "email.send", serde_json::to_value(message)?, None).await?;
+        }
+        tx.commit().await?;
+
+        if !subscribers.is_empty() {
+            println!("Sent {} back-in-stock email(s) for product {}", subscribers.len(), product_id);
+        }
+        Ok(())
+    }
+}
+
+#[derive(FromRow)]
+struct PayoutAccount {
+    vendor_id: Uuid,
+    username: String,
+    email: String,
+    payout_method: String,
+    account_last4: Option<String>,
+}
+
+/// Pays each approved vendor with payout details their available balance, if
+/// it reaches `PAYOUT_MINIMUM`. Payload: `{"date": "YYYY-MM-DD"}`, the
+/// settlement date, defaulting to today (UTC). A vendor is paid at most once
+/// per settlement date, so retries and repeated runs are safe.
+pub struct VendorPayouts {
+    db: Arc<PgPool>,
+}
+
+impl VendorPayouts {
+    /// Pay one vendor their available balance for the settlement date.
+    /// Returns whether a payout was made.
+    async fn pay_vendor(&self, vendor_id: Uuid, date: NaiveDate, minimum: &BigDecimal) -> Result<bool, JobError> {
+        let mut tx = self.db.begin().await?;
+
+        // Locking the profile serializes concurrent runs for the vendor
+        let account = sqlx::query_as::<_, PayoutAccount>(
+            r#"
+            SELECT vp.vendor_id, u.username, u.email, vp.payout_method, RIGHT(vp.payout_account, 4) AS account_last4
+            FROM vendor_profiles vp
+            JOIN users u ON u.id = vp.vendor_id
+            WHERE vp.vendor_id = $1 AND vp.status = 'approved'
+              AND vp.payout_method IS NOT NULL AND vp.payout_account IS NOT NULL
+            FOR UPDATE OF vp
+            "#,
+        )
+        .bind(vendor_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(account) = account else { return Ok(false) };
+
+        let balance = ledger::vendor_balance(&mut *tx, vendor_id).await?;
+        // Never pay out an empty or overdrawn balance, whatever the minimum
+        if balance.available <= BigDecimal::from(0) || balance.available < *minimum {
+            return Ok(false);
+        }
+
+        let reference = payout_reference(vendor_id, date);
+        let payout_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO payouts (vendor_id, reference, amount, payout_method, payout_account_last4, settlement_date)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (vendor_id, settlement_date) DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(vendor_id)
+        .bind(&reference)
+        .bind(&balance.available)
+        .bind(&account.payout_method)
+        .bind(&account.account_last4)
+        .bind(date)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(payout_id) = payout_id else { return Ok(false) };
+
+        ledger::record_payout(&mut tx, vendor_id, payout_id, &balance.available).await?;
+
+        let recipient = Recipient {
+            username: account.username,
+            email: account.email,
+        };
+        let message = email_templates::payout_sent(&recipient, &reference, &balance.available, account.account_last4.as_deref());
+        jobs::enqueue(&mut *tx, "email.send", serde_json::to_value(message)?, None).await?;
+
+        tx.commit().await?;
+        println!("Paid out {} to vendor {} ({})", balance.available, account.vendor_id, reference);
+        Ok(true)
+    }
+}
+
+/// The payout's reference for the vendor's bank statement. Vendors are paid at
+/// most once per settlement date, so the full vendor id keeps it unique.
+fn payout_reference(vendor_id: Uuid, date: NaiveDate) -> String {
+    format!("PO-{}-{}", date.format("%Y%m%d"), vendor_id.simple().to_string().to_uppercase())
+}
+
+#[async_trait]
+impl JobHandler for VendorPayouts {
+    fn kind(&self) -> &'static str {
+        "ledger.payouts"
+    }
+
+    async fn run(&self, job: &Job) -> Result<(), JobError> {
+        let date = match job.payload.0.get("date").and_then(|d| d.as_str()) {
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")?,
+            None => Utc::now().date_naive(),
+        };
+        let minimum = ledger::payout_minimum();
+
+        let vendor_ids: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT vp.vendor_id
+            FROM vendor_profiles vp
+            WHERE vp.status = 'approved' AND vp.payout_method IS NOT NULL AND vp.payout_account IS NOT NULL
+              AND EXISTS (SELECT 1 FROM ledger_entries le WHERE le.vendor_id = vp.vendor_id)
+            "#,
+        )
+        .fetch_all(&*self.db)
+        .await?;
+
+        // A failure for one vendor doesn't hold up the others; the job is then
+        // retried, which skips everyone already paid for this settlement date
+        let mut paid = 0;
+        let mut failed = 0;
+        for vendor_id in vendor_ids {
+            match self.pay_vendor(vendor_id, date, &minimum).await {
+                Ok(true) => paid += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    failed += 1;
+                    println!("Payout to vendor {} for {} failed: {:?}", vendor_id, date, e);
+                }
+            }
+        }
+
+        if paid > 0 {
+            println!("Settled {} vendor payout(s) for {}", paid, date);
+        }
+        if failed > 0 {
+            return Err(format!("{} vendor payout(s) for {} failed", failed, date).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payout_references_differ_for_vendors_sharing_a_prefix() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let first = Uuid::parse_str("1234abcd-0000-4000-8000-000000000001").unwrap();
+        let second = Uuid::parse_str("1234abcd-0000-4000-8000-000000000002").unwrap();
+
+        assert_eq!(payout_reference(first, date), "PO-20261019-1234ABCD000040008000000000000001");
+        assert_ne!(payout_reference(first, date), payout_reference(second, date));
+        // Fits payouts.reference
+        assert!(payout_reference(first, date).len() <= 50);
+    }
+}
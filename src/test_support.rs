@@ -0,0 +1,138 @@
+//! Helpers for tests that run handlers against a database. Use with
+//! `#[sqlx::test(migrations = false)]`, which gives each test its own
+//! empty database on the server in `DATABASE_URL`.
+
+use axum::{body, extract::State, response::IntoResponse, Json};
+use sqlx::{Executor, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::auth::{register, RegisterRequest},
+    jwt::KeyRing,
+    notifier::LogNotifier,
+};
+
+/// Password used by test users that are registered through the handlers
+pub const PASSWORD: &str = "correct horse battery staple";
+
+/// Create the tables from schema.sql in the test's database
+pub async fn load_schema(pool: &PgPool) {
+    pool.execute(include_str!("../schema.sql")).await.expect("schema.sql loads");
+}
+
+/// App state over the test's database, logging emails instead of sending them
+pub async fn app_state(pool: PgPool) -> Arc<AppState> {
+    load_schema(&pool).await;
+    Arc::new(AppState {
+        db: Arc::new(pool),
+        notifier: Arc::new(LogNotifier),
+        keys: Arc::new(KeyRing::ephemeral()),
+    })
+}
+
+/// A handler's response body as JSON text
+pub async fn response_body(response: impl IntoResponse) -> String {
+    let body = body::to_bytes(response.into_response().into_body(), usize::MAX)
+        .await
+        .expect("Response body is readable");
+    String::from_utf8(body.to_vec()).expect("Response body is UTF-8")
+}
+
+/// The stored hash of a test user's password
+pub async fn password_hash(pool: &PgPool, email: &str) -> String {
+    sqlx::query_scalar("SELECT password_hash FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(pool)
+        .await
+        .expect("User exists")
+}
+
+/// Fail if a response body leaks the password, its hash or the session version
+pub fn assert_no_hash_material(body: &str, password_hash: &str) {
+    assert!(!body.contains(password_hash), "body contains the password hash: {}", body);
+    assert!(!body.contains("$argon2"), "body contains an Argon2 hash: {}", body);
+    // The salt and digest segments of the PHC string, in case it were split up
+    for part in password_hash.split('$').filter(|part| part.len() >= 16) {
+        assert!(!body.contains(part), "body contains part of the password hash: {}", body);
+    }
+    assert!(!body.contains("password"), "body mentions a password field: {}", body);
+    assert!(!body.contains(PASSWORD), "body contains the password: {}", body);
+    assert!(!body.contains("session_version"), "body contains the session version: {}", body);
+}
+
+/// Registration for "alice" with the test password
+pub fn registration(role: &str) -> Json<RegisterRequest> {
+    Json(RegisterRequest {
+        username: "alice".into(),
+        password: PASSWORD.into(),
+        email: "alice@example.com".into(),
+        role: Some(role.into()),
+    })
+}
+
+/// Register "alice" through the handler and return the new user's id
+pub async fn register_alice(state: Arc<AppState>, role: &str) -> Uuid {
+    let registered = response_body(register(State(state), registration(role)).await).await;
+    let user: serde_json::Value = serde_json::from_str(&registered).expect("Register returns JSON");
+    user["id"].as_str().and_then(|id| id.parse().ok()).expect("Register returns the user id")
+}
+
+/// An approved store profile for a vendor
+pub async fn insert_vendor_profile(pool: &PgPool, vendor_id: Uuid, slug: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO vendor_profiles (vendor_id, store_name, slug, payout_method, payout_account, status)
+        VALUES ($1, $2, $2, 'paypal', 'payouts@example.com', 'approved')
+        "#,
+    )
+    .bind(vendor_id)
+    .bind(slug)
+    .execute(pool)
+    .await
+    .expect("Vendor profile inserts");
+}
+
+/// A user with an unusable password and `name`@example.com as email
+pub async fn insert_user(pool: &PgPool, name: &str, role: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, email, password_hash, role) VALUES ($1, $2, $2 || '@example.com', 'x', $3)")
+        .bind(id)
+        .bind(name)
+        .bind(role)
+        .execute(pool)
+        .await
+        .expect("User inserts");
+    id
+}
+
+/// A product named "Item" listed by the vendor
+pub async fn insert_vendor_product(pool: &PgPool, vendor_id: Uuid, price: &str, stock: i32) -> Uuid {
+    sqlx::query_scalar(
+        "INSERT INTO products (vendor_id, name, price, stock) VALUES ($1, 'Item', $2::DECIMAL, $3) RETURNING id",
+    )
+    .bind(vendor_id)
+    .bind(price)
+    .bind(stock)
+    .fetch_one(pool)
+    .await
+    .expect("Product inserts")
+}
+
+/// A line of an existing order, priced like the product at the time
+pub async fn insert_order_item(pool: &PgPool, order_id: Uuid, product_id: Uuid, quantity: i32) -> Uuid {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO order_items (order_id, product_id, vendor_id, quantity, price)
+        SELECT $1, id, vendor_id, $3, price FROM products WHERE id = $2
+        RETURNING id
+        "#,
+    )
+    .bind(order_id)
+    .bind(product_id)
+    .bind(quantity)
+    .fetch_one(pool)
+    .await
+    .expect("Order item inserts")
+}
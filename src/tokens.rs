@@ -0,0 +1,18 @@
+//! Single-use secrets emailed to users, such as email verification and password
+//! reset tokens. Only a SHA-256 hash is stored, so a leaked database row can't
+//! be used to act on the account.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// A new random token to send to the user
+pub fn generate() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// The value stored in the database for a token
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.trim().as_bytes()))
+}
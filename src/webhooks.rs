@@ -0,0 +1,595 @@
+//! Outbound webhooks. `WebhookSubscriber` turns domain events into one pending
+//! delivery per matching vendor endpoint; the delivery worker POSTs them with an
+//! HMAC signature, retrying with backoff and disabling endpoints that keep failing.
+//!
+//! Each request carries:
+//! - `X-Webhook-Id`: the delivery id, stable across retries and replays
+//! - `X-Webhook-Event`: the event type
+//! - `X-Webhook-Timestamp`: unix seconds when the request was sent
+//! - `X-Webhook-Signature`: `sha256=` + hex HMAC-SHA256 of `"{timestamp}.{body}"` keyed with the endpoint secret
+
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use reqwest::{redirect, Url};
+use serde::Serialize;
+use sqlx::{types::Json, PgPool};
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::events::{DomainEvent, EventError, EventSubscriber, OutboxEvent};
+use crate::models::Webhook::WebhookDelivery;
+
+/// Mark a delivery failed after this many attempts
+const MAX_ATTEMPTS: i32 = 6;
+/// Disable an endpoint after this many failed attempts in a row, across all deliveries
+const DISABLE_AFTER_FAILURES: i32 = 15;
+const BATCH_SIZE: i64 = 20;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery is hidden from other workers; longer than a full
+/// batch of requests that all time out
+const CLAIM_LEASE: Duration = Duration::from_secs(300);
+
+/// A new random signing secret
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+/// Signature sent in `X-Webhook-Signature`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether an address is one the server can reach but vendors shouldn't:
+/// loopback, private, link-local (cloud metadata) and unspecified addresses
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_internal(IpAddr::V4(mapped)),
+            None => {
+                let first = ip.segments()[0];
+                // fc00::/7 unique local and fe80::/10 link-local
+                ip.is_loopback() || ip.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// Hosts that may resolve to internal addresses, from the comma separated
+/// `WEBHOOK_ALLOWED_PRIVATE_HOSTS`. Meant for testing webhooks locally.
+fn allowed_private_hosts() -> Vec<String> {
+    env::var("WEBHOOK_ALLOWED_PRIVATE_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Check a webhook URL is http(s) and its host doesn't resolve to an internal
+/// address. Checked when an endpoint is saved and again before each request,
+/// since DNS can change in between.
+pub async fn check_destination(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|parsed| parsed.scheme() == "http" || parsed.scheme() == "https")
+        .ok_or("url must be an absolute http or https URL")?;
+    let host = parsed.host_str().ok_or("url must have a host")?;
+    if allowed_private_hosts().iter().any(|allowed| allowed.as_str() == host.to_lowercase()) {
+        return Ok(());
+    }
+
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    // IP literals are bracketed in IPv6 URLs
+    let addrs: Vec<SocketAddr> = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| format!("Could not resolve {}", host))?
+            .collect(),
+    };
+    if addrs.is_empty() {
+        return Err(format!("Could not resolve {}", host));
+    }
+    if addrs.iter().any(|addr| is_internal(addr.ip())) {
+        return Err(format!("{} resolves to a private or loopback address", host));
+    }
+    Ok(())
+}
+
+/// Seconds to wait before retrying a delivery that has failed `attempts` times,
+/// or None once it has used all of them. Exponential backoff: 30s, 1m, 2m, 4m, 8m
+fn retry_delay_secs(attempts: i32) -> Option<i64> {
+    (attempts < MAX_ATTEMPTS).then(|| 30 * 2_i64.pow((attempts - 1) as u32))
+}
+
+/// Queues a delivery for every active endpoint subscribed to the event
+pub struct WebhookSubscriber {
+    db: Arc<PgPool>,
+}
+
+impl WebhookSubscriber {
+    pub fn new(db: Arc<PgPool>) -> Self {
+        WebhookSubscriber { db }
+    }
+
+    /// Vendors an event concerns
+    async fn vendor_ids(&self, event: &DomainEvent) -> Result<Vec<Uuid>, sqlx::Error> {
+        match event {
+            DomainEvent::OrderPlaced { vendor_ids, .. } => Ok(vendor_ids.clone()),
+            DomainEvent::OrderStatusChanged { order_id, .. } => {
+                sqlx::query_scalar("SELECT DISTINCT vendor_id FROM order_items WHERE order_id = $1")
+                    .bind(order_id)
+                    .fetch_all(&*self.db)
+                    .await
+            }
+            DomainEvent::ProductStockChanged { vendor_id, .. } => Ok(vec![*vendor_id]),
+            DomainEvent::UserRegistered { .. } | DomainEvent::AccountLockedOut { .. } => Ok(Vec::new()),
+        }
+    }
+
+    /// The part of an order one vendor sells: their lines and subtotal
+    async fn vendor_order(&self, order_id: Uuid, vendor_id: Uuid) -> Result<VendorOrder, sqlx::Error> {
+        let items = sqlx::query_as::<_, VendorOrderItem>(
+            r#"
+            SELECT oi.product_id, p.name AS product_name, oi.quantity, oi.price
+            FROM order_items oi
+            JOIN products p ON p.id = oi.product_id
+            WHERE oi.order_id = $1 AND oi.vendor_id = $2
+            ORDER BY oi.created_at, oi.id
+            "#,
+        )
+        .bind(order_id)
+        .bind(vendor_id)
+        .fetch_all(&*self.db)
+        .await?;
+        let subtotal: BigDecimal = items.iter().map(|item| &item.price * BigDecimal::from(item.quantity)).sum();
+        Ok(VendorOrder { order_id, vendor_id, items, subtotal: subtotal.with_scale(2) })
+    }
+}
+
+/// `OrderPlaced` data as sent to one vendor
+#[derive(Serialize)]
+struct VendorOrder {
+    order_id: Uuid,
+    vendor_id: Uuid,
+    items: Vec<VendorOrderItem>,
+    subtotal: BigDecimal,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct VendorOrderItem {
+    product_id: Uuid,
+    product_name: String,
+    quantity: i32,
+    price: BigDecimal,
+}
+
+#[async_trait]
+impl EventSubscriber for WebhookSubscriber {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn handle(&self, event: &OutboxEvent) -> Result<(), EventError> {
+        let vendor_ids = self.vendor_ids(&event.event).await?;
+        if vendor_ids.is_empty() {
+            return Ok(());
+        }
+
+        // A new order can span several vendors; each only hears about its own lines
+        let payloads = match &event.event {
+            DomainEvent::OrderPlaced { order_id, .. } => {
+                let mut payloads = Vec::new();
+                for vendor_id in vendor_ids {
+                    let data = self.vendor_order(*order_id, vendor_id).await?;
+                    let payload = serde_json::json!({ "type": event.event.event_type(), "data": data });
+                    payloads.push((vec![vendor_id], payload));
+                }
+                payloads
+            }
+            _ => vec![(vendor_ids, serde_json::to_value(&event.event)?)],
+        };
+
+        for (vendor_ids, mut payload) in payloads {
+            payload["id"] = serde_json::json!(event.id);
+            payload["created_at"] = serde_json::json!(event.created_at);
+
+            // The unique (endpoint_id, event_id) pair makes re-handling the same event harmless
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_deliveries (endpoint_id, event_id, event_type, payload)
+                SELECT id, $1, $2, $3
+                FROM webhook_endpoints
+                WHERE vendor_id = ANY($4) AND is_active AND $2 = ANY(event_types)
+                ON CONFLICT (endpoint_id, event_id) DO NOTHING
+                "#,
+            )
+            .bind(event.id)
+            .bind(event.event.event_type())
+            .bind(Json(&payload))
+            .bind(&vendor_ids)
+            .execute(&*self.db)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Start sending pending deliveries on a background task
+pub fn spawn_worker(db: Arc<PgPool>) {
+    // A redirect could point anywhere, including addresses check_destination refuses
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(redirect::Policy::none())
+        .build()
+        .expect("Failed to build webhook HTTP client");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = deliver_batch(&db, &client).await {
+                println!("Webhook delivery failed: {:?}", e);
+            }
+        }
+    });
+}
+
+#[derive(sqlx::FromRow)]
+struct PendingDelivery {
+    #[sqlx(flatten)]
+    delivery: WebhookDelivery,
+    url: String,
+    secret: String,
+}
+
+/// Claim due deliveries by pushing `next_attempt_at` past the time a batch can
+/// take to send, so other workers skip them while no transaction is held open.
+/// A claim left behind by a crashed worker lapses and the delivery is retried.
+async fn claim_due(db: &PgPool) -> Result<Vec<PendingDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, PendingDelivery>(
+        r#"
+        WITH due AS (
+            SELECT d.id
+            FROM webhook_deliveries d
+            JOIN webhook_endpoints e ON e.id = d.endpoint_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND e.is_active
+            ORDER BY d.next_attempt_at
+            LIMIT $1
+            FOR UPDATE OF d SKIP LOCKED
+        ),
+        claimed AS (
+            UPDATE webhook_deliveries d
+            SET next_attempt_at = NOW() + make_interval(secs => $2)
+            FROM due
+            WHERE d.id = due.id
+            RETURNING d.*
+        )
+        SELECT c.id, c.endpoint_id, c.event_id, c.event_type, c.payload, c.status, c.attempts,
+               c.response_status, c.last_error, c.next_attempt_at, c.delivered_at, c.created_at,
+               e.url, e.secret
+        FROM claimed c
+        JOIN webhook_endpoints e ON e.id = c.endpoint_id
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .bind(CLAIM_LEASE.as_secs_f64())
+    .fetch_all(db)
+    .await
+}
+
+/// POST a delivery, returning the response status and the error if it failed
+async fn send(client: &reqwest::Client, delivery: &WebhookDelivery, url: &str, secret: &str) -> (Option<i32>, Option<String>) {
+    if let Err(e) = check_destination(url).await {
+        return (None, Some(e));
+    }
+    let body = delivery.payload.0.to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let result = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Id", delivery.id.to_string())
+        .header("X-Webhook-Event", &delivery.event_type)
+        .header("X-Webhook-Timestamp", timestamp.to_string())
+        .header("X-Webhook-Signature", sign(secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) if response.status().is_success() => (Some(response.status().as_u16() as i32), None),
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            Some(format!("Endpoint responded with {}", response.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+async fn record_success(db: &PgPool, delivery: &WebhookDelivery, response_status: Option<i32>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        WITH delivered AS (
+            UPDATE webhook_deliveries
+            SET status = 'succeeded', attempts = attempts + 1, response_status = $2, last_error = NULL, delivered_at = NOW()
+            WHERE id = $1
+            RETURNING endpoint_id
+        )
+        UPDATE webhook_endpoints SET consecutive_failures = 0
+        WHERE id IN (SELECT endpoint_id FROM delivered)
+        "#,
+    )
+    .bind(delivery.id)
+    .bind(response_status)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Schedule the retry or give up, and count the failure against the endpoint.
+/// Returns whether the endpoint has now been disabled.
+async fn record_failure(
+    db: &PgPool,
+    delivery: &WebhookDelivery,
+    response_status: Option<i32>,
+    error: &str,
+) -> Result<bool, sqlx::Error> {
+    let attempts = delivery.attempts + 1;
+    let delay_secs = retry_delay_secs(attempts);
+    let status = if delay_secs.is_some() { "pending" } else { "failed" };
+
+    let disabled: Option<bool> = sqlx::query_scalar(
+        r#"
+        WITH failed AS (
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = attempts + 1, response_status = $3, last_error = $4,
+                next_attempt_at = NOW() + make_interval(secs => $5)
+            WHERE id = $1
+            RETURNING endpoint_id
+        )
+        UPDATE webhook_endpoints
+        SET consecutive_failures = consecutive_failures + 1,
+            is_active = consecutive_failures + 1 < $6,
+            disabled_at = CASE WHEN consecutive_failures + 1 >= $6 THEN NOW() ELSE disabled_at END,
+            updated_at = NOW()
+        WHERE id IN (SELECT endpoint_id FROM failed)
+        RETURNING NOT is_active
+        "#,
+    )
+    .bind(delivery.id)
+    .bind(status)
+    .bind(response_status)
+    .bind(error)
+    .bind(delay_secs.unwrap_or(0) as f64)
+    .bind(DISABLE_AFTER_FAILURES)
+    .fetch_optional(db)
+    .await?;
+    Ok(disabled.unwrap_or(false))
+}
+
+/// Claim due deliveries and send them, recording each result as it comes in.
+/// Deliveries for disabled endpoints stay pending and resume if the endpoint is re-enabled.
+async fn deliver_batch(db: &PgPool, client: &reqwest::Client) -> Result<(), sqlx::Error> {
+    for PendingDelivery { delivery, url, secret } in claim_due(db).await? {
+        let (response_status, error) = send(client, &delivery, &url, &secret).await;
+        let attempt = delivery.attempts + 1;
+
+        let Some(error) = error else {
+            // If this isn't recorded the claim lapses and the delivery is sent again;
+            // receivers dedupe on X-Webhook-Id
+            if let Err(e) = record_success(db, &delivery, response_status).await {
+                println!("Failed to record webhook delivery {}: {:?}", delivery.id, e);
+            }
+            continue;
+        };
+
+        println!("Webhook delivery {} to {} failed (attempt {}): {}", delivery.id, url, attempt, error);
+        match record_failure(db, &delivery, response_status, &error).await {
+            Ok(true) => println!(
+                "Disabled webhook endpoint {} after {} consecutive failures",
+                delivery.endpoint_id, DISABLE_AFTER_FAILURES
+            ),
+            Ok(false) => {}
+            Err(e) => println!("Failed to record webhook delivery {}: {:?}", delivery.id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{insert_order_item, insert_user, insert_vendor_product, load_schema};
+
+    /// A due delivery to an endpoint at `url`
+    async fn insert_delivery(pool: &PgPool, url: &str) -> Uuid {
+        load_schema(pool).await;
+        let vendor_id = insert_user(pool, "vendor", "vendor").await;
+        sqlx::query_scalar(
+            r#"
+            WITH endpoint AS (
+                INSERT INTO webhook_endpoints (vendor_id, url, secret, event_types)
+                VALUES ($2, $1, 'whsec_test', ARRAY['OrderPlaced'])
+                RETURNING id
+            ),
+            event AS (
+                INSERT INTO outbox (event_type, aggregate_id, payload)
+                VALUES ('OrderPlaced', gen_random_uuid(), '{}')
+                RETURNING id
+            )
+            INSERT INTO webhook_deliveries (endpoint_id, event_id, event_type, payload)
+            SELECT endpoint.id, event.id, 'OrderPlaced', '{}' FROM endpoint, event
+            RETURNING id
+            "#,
+        )
+        .bind(url)
+        .bind(vendor_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn signature_is_hmac_sha256_of_timestamp_and_body() {
+        let body = r#"{"event":"OrderPlaced"}"#;
+        assert_eq!(
+            sign("whsec_test", 1_700_000_000, body),
+            "sha256=9f3ba11eb8a20802246a07f5b53534a268bd049e21f9b9659a6b838ac5e2e05b"
+        );
+    }
+
+    #[test]
+    fn signature_depends_on_secret_timestamp_and_body() {
+        let signature = sign("whsec_test", 1_700_000_000, "{}");
+        assert_ne!(signature, sign("whsec_other", 1_700_000_000, "{}"));
+        assert_ne!(signature, sign("whsec_test", 1_700_000_001, "{}"));
+        assert_ne!(signature, sign("whsec_test", 1_700_000_000, "{ }"));
+    }
+
+    #[test]
+    fn generated_secrets_are_prefixed_and_random() {
+        let secret = generate_secret();
+        assert!(secret.starts_with("whsec_"));
+        assert_eq!(secret.len(), "whsec_".len() + 48);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let delays: Vec<Option<i64>> = (1..MAX_ATTEMPTS).map(retry_delay_secs).collect();
+        assert_eq!(delays, [Some(30), Some(60), Some(120), Some(240), Some(480)]);
+    }
+
+    #[test]
+    fn delivery_fails_after_the_last_attempt() {
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS), None);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS + 1), None);
+    }
+
+    #[test]
+    fn internal_addresses_are_recognized() {
+        let internal = [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "172.31.255.255", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ];
+        for ip in internal {
+            assert!(is_internal(ip.parse().unwrap()), "{} is internal", ip);
+        }
+        for ip in ["93.184.216.34", "172.32.0.1", "2606:2800:220:1::1"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn destinations_on_internal_addresses_are_refused() {
+        for url in ["http://127.0.0.1:8080/hook", "https://[::1]/hook", "http://169.254.169.254/latest/meta-data"] {
+            assert!(check_destination(url).await.is_err(), "{} was allowed", url);
+        }
+        assert!(check_destination("ftp://93.184.216.34/hook").await.is_err());
+        assert_eq!(check_destination("https://93.184.216.34/hook").await, Ok(()));
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn claimed_deliveries_are_skipped_by_other_workers(pool: PgPool) {
+        let delivery_id = insert_delivery(&pool, "http://127.0.0.1:9/").await;
+
+        let claimed = claim_due(&pool).await.unwrap();
+        let claimed_again = claim_due(&pool).await.unwrap();
+
+        assert_eq!(claimed.iter().map(|p| p.delivery.id).collect::<Vec<_>>(), [delivery_id]);
+        assert_eq!(claimed[0].delivery.attempts, 0);
+        assert!(claimed[0].delivery.next_attempt_at > chrono::Utc::now() + chrono::Duration::seconds(60));
+        assert!(claimed_again.is_empty());
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn failed_delivery_is_scheduled_for_retry(pool: PgPool) {
+        // Loopback isn't an allowed destination, so the request isn't even made
+        let delivery_id = insert_delivery(&pool, "http://127.0.0.1:9/").await;
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap();
+
+        deliver_batch(&pool, &client).await.unwrap();
+
+        let (status, attempts, last_error, retry_in, failures): (String, i32, Option<String>, f64, i32) = sqlx::query_as(
+            r#"
+            SELECT d.status, d.attempts, d.last_error,
+                   EXTRACT(EPOCH FROM d.next_attempt_at - NOW())::FLOAT8, e.consecutive_failures
+            FROM webhook_deliveries d
+            JOIN webhook_endpoints e ON e.id = d.endpoint_id
+            WHERE d.id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!((status.as_str(), attempts, failures), ("pending", 1, 1));
+        assert_eq!(last_error.as_deref(), Some("127.0.0.1 resolves to a private or loopback address"));
+        assert!((20.0..=30.0).contains(&retry_in), "retry in {}s", retry_in);
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn order_placed_payloads_only_hold_the_vendors_own_lines(pool: PgPool) {
+        load_schema(&pool).await;
+        let customer_id = insert_user(&pool, "customer", "customer").await;
+        let vendors = [insert_user(&pool, "first", "vendor").await, insert_user(&pool, "second", "vendor").await];
+        let order_id: Uuid = sqlx::query_scalar("INSERT INTO orders (user_id, total) VALUES ($1, 32.00) RETURNING id")
+            .bind(customer_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        for (vendor_id, price, quantity) in [(vendors[0], "5.00", 2), (vendors[1], "22.00", 1)] {
+            let product_id = insert_vendor_product(&pool, vendor_id, price, 5).await;
+            insert_order_item(&pool, order_id, product_id, quantity).await;
+            sqlx::query("INSERT INTO webhook_endpoints (vendor_id, url, secret, event_types) VALUES ($1, 'https://example.com/hook', 'whsec_test', ARRAY['OrderPlaced'])")
+                .bind(vendor_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let placed = DomainEvent::OrderPlaced {
+            order_id,
+            user_id: customer_id,
+            total: "32.00".parse().unwrap(),
+            vendor_ids: vendors.to_vec(),
+        };
+        let mut conn = pool.acquire().await.unwrap();
+        let id = crate::events::record(&mut conn, placed.clone()).await.unwrap();
+        let event = OutboxEvent { id, event: placed, created_at: chrono::Utc::now() };
+
+        WebhookSubscriber::new(Arc::new(pool.clone())).handle(&event).await.unwrap();
+
+        for (vendor_id, subtotal) in [(vendors[0], "10.00"), (vendors[1], "22.00")] {
+            let Json(payload): Json<serde_json::Value> = sqlx::query_scalar(
+                "SELECT d.payload FROM webhook_deliveries d JOIN webhook_endpoints e ON e.id = d.endpoint_id WHERE e.vendor_id = $1",
+            )
+            .bind(vendor_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            let data = &payload["data"];
+            assert_eq!(data["vendor_id"], vendor_id.to_string());
+            assert_eq!(data["items"].as_array().unwrap().len(), 1);
+            assert_eq!(data["subtotal"], subtotal);
+            assert!(data.get("total").is_none() && data.get("vendor_ids").is_none(), "payload leaks the order: {}", payload);
+            assert_eq!(payload["id"], event.id.to_string());
+        }
+    }
+}